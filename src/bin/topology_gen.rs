@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+use std::{env, fs, process};
+use krusty_crap::initialization_file_checker::config_to_toml;
+use krusty_crap::topology_generator::{generate, GeneratorParams, GraphShape, PdrDistribution};
+
+fn usage() -> String {
+    "Usage: topology-gen [OPTIONS]\n\
+     \n\
+     Generates network initialization files that pass the checks of the simulation.\n\
+     \n\
+     Options:\n\
+     \x20 --drones <N>         Number of drones [default: 10]\n\
+     \x20 --clients <N>        Number of clients [default: 2]\n\
+     \x20 --servers <N>        Number of servers [default: 2]\n\
+     \x20 --shape <SHAPE>      ring, grid, er:<p> or ba:<m> [default: ring]\n\
+     \x20 --pdr <PDR>          A PDR or a range <min>..<max> [default: 0.05]\n\
+     \x20 --seed <SEED>        Seed of the first file [default: 0]\n\
+     \x20 --count <N>          Number of files, each one with the next seed [default: 1]\n\
+     \x20 --out <PATH>         File to write, or a directory when --count is more than 1 [default: stdout]\n\
+     \x20 -h, --help           Print this help"
+        .to_string()
+}
+
+struct GenArgs {
+    params: GeneratorParams,
+    count: u64,
+    out: Option<PathBuf>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<GenArgs>, String> {
+    let mut gen_args = GenArgs {
+        params: GeneratorParams {
+            n_drones: 10,
+            n_clients: 2,
+            n_servers: 2,
+            shape: GraphShape::Ring,
+            pdr: PdrDistribution::Constant(0.05),
+            seed: 0,
+        },
+        count: 1,
+        out: None,
+    };
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(None);
+        }
+        let value = args.next().ok_or_else(|| format!("Missing value for '{}'", arg))?;
+        let number = |value: &str| value.parse::<usize>().map_err(|_| format!("Invalid value '{}' for '{}'", value, arg));
+        match arg.as_str() {
+            "--drones" => gen_args.params.n_drones = number(&value)?,
+            "--clients" => gen_args.params.n_clients = number(&value)?,
+            "--servers" => gen_args.params.n_servers = number(&value)?,
+            "--shape" => gen_args.params.shape = value.parse()?,
+            "--pdr" => gen_args.params.pdr = value.parse()?,
+            "--seed" => gen_args.params.seed = value.parse().map_err(|_| format!("Invalid seed '{}'", value))?,
+            "--count" => gen_args.count = number(&value)? as u64,
+            "--out" => gen_args.out = Some(PathBuf::from(value)),
+            _ => return Err(format!("Unknown argument '{}'", arg)),
+        }
+    }
+
+    gen_args.params.validate()?;
+    if gen_args.count == 0 {
+        return Err("At least one file must be generated".to_string());
+    }
+    if gen_args.count > 1 && gen_args.out.is_none() {
+        return Err("--out <DIRECTORY> is needed to generate more than one file".to_string());
+    }
+    Ok(Some(gen_args))
+}
+
+fn main() {
+    let gen_args = match parse_args(env::args().skip(1)) {
+        Ok(Some(gen_args)) => gen_args,
+        Ok(None) => {
+            println!("{}", usage());
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, usage());
+            process::exit(2);
+        }
+    };
+
+    if let (true, Some(dir)) = (gen_args.count > 1, &gen_args.out) {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Error: unable to create {}: {}", dir.display(), e);
+            process::exit(1);
+        }
+    }
+
+    for i in 0..gen_args.count {
+        let params = GeneratorParams { seed: gen_args.params.seed.wrapping_add(i), ..gen_args.params.clone() };
+        let toml = match generate(&params).and_then(|config| config_to_toml(&config, params.seed)) {
+            Ok(toml) => toml,
+            Err(e) => {
+                eprintln!("Error: seed {}: {}", params.seed, e);
+                process::exit(1);
+            }
+        };
+
+        let path = match &gen_args.out {
+            None => {
+                print!("{}", toml);
+                continue;
+            }
+            Some(dir) if gen_args.count > 1 => dir.join(format!("{}_{}.toml", params.shape.to_string().replace(':', "_"), params.seed)),
+            Some(path) => path.clone(),
+        };
+        if let Err(e) = fs::write(&path, toml) {
+            eprintln!("Error: unable to write {}: {}", path.display(), e);
+            process::exit(1);
+        }
+        println!("{}", path.display());
+    }
+}
@@ -0,0 +1,60 @@
+use std::{env, process};
+use krusty_crap::initialization_file_checker::{read_config, InitializationFileChecker};
+use krusty_crap::network_extensions::NetworkExtensions;
+use krusty_crap::topology_analysis::TopologyStatistics;
+
+fn usage() -> String {
+    "Usage: topology-lint <TOPOLOGY.toml>...\n\
+     \n\
+     Checks each initialization file with the rules of the simulation and prints its graph statistics.\n\
+     Exits with 1 if a file cannot be read or breaks a rule, with 2 on a wrong command line."
+        .to_string()
+}
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", usage());
+        return;
+    }
+    if paths.is_empty() {
+        eprintln!("Error: no topology file given\n\n{}", usage());
+        process::exit(2);
+    }
+
+    let mut n_invalid = 0;
+    for path in &paths {
+        println!("== {}", path);
+        let config = match read_config(path) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("error: {}\n", e);
+                n_invalid += 1;
+                continue;
+            }
+        };
+
+        // The statistics are printed also for invalid files, they help to find what is wrong
+        let errors = InitializationFileChecker::new(&config).errors();
+        let extensions_error = NetworkExtensions::from_file(path)
+            .and_then(|extensions| extensions.validate(&config))
+            .err();
+        if errors.is_empty() && extensions_error.is_none() {
+            println!("valid");
+        } else {
+            n_invalid += 1;
+            for error in &errors {
+                println!("error: {}", error);
+            }
+            if let Some(error) = extensions_error {
+                println!("error: {}", error);
+            }
+        }
+        println!("{}\n", TopologyStatistics::new(&config));
+    }
+
+    println!("{} of {} files valid", paths.len() - n_invalid, paths.len());
+    if n_invalid > 0 {
+        process::exit(1);
+    }
+}
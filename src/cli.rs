@@ -0,0 +1,228 @@
+use std::path::PathBuf;
+use log::LevelFilter;
+
+pub const DEFAULT_TOPOLOGY: &str = "topologies/tree.toml";
+pub const DEFAULT_HTTP_PORT: u16 = 8000;
+pub const DEFAULT_WS_PORT: u16 = 8080;
+
+/// Options of the `KrustyCrap` binary.
+#[derive(Debug, Clone)]
+pub struct CliArgs {
+    pub topology_path: PathBuf,
+    pub http_port: u16,
+    pub ws_port: u16,
+    pub log_level: Option<LevelFilter>,    // None keeps the RUST_LOG behaviour
+    pub headless: bool,                    // Skip the HTTP and the WebSocket servers
+    pub scenario_path: Option<PathBuf>,    // Scenario to run instead of the web interface
+    pub seed: Option<u64>,                 // None uses the seed of the topology file, or a random one
+}
+
+/// What the binary has to do after parsing the command line.
+#[derive(Debug, Clone)]
+pub enum CliCommand {
+    Run(CliArgs),
+    Help,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        Self {
+            topology_path: PathBuf::from(DEFAULT_TOPOLOGY),
+            http_port: DEFAULT_HTTP_PORT,
+            ws_port: DEFAULT_WS_PORT,
+            log_level: None,
+            headless: false,
+            scenario_path: None,
+            seed: None,
+        }
+    }
+}
+
+impl CliArgs {
+    /// Parses the arguments (without the program name) and validates them.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<CliCommand, String> {
+        let mut cli_args = CliArgs::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(CliCommand::Help),
+                "-t" | "--topology" => {
+                    cli_args.topology_path = PathBuf::from(Self::value_of(&arg, args.next())?);
+                }
+                "--http-port" => {
+                    cli_args.http_port = Self::parse_port(&arg, args.next())?;
+                }
+                "--ws-port" => {
+                    cli_args.ws_port = Self::parse_port(&arg, args.next())?;
+                }
+                "--log-level" => {
+                    let value = Self::value_of(&arg, args.next())?;
+                    let level = value.parse::<LevelFilter>()
+                        .map_err(|_| format!("Invalid log level '{}', expected one of: off, error, warn, info, debug, trace", value))?;
+                    cli_args.log_level = Some(level);
+                }
+                "--headless" => {
+                    cli_args.headless = true;
+                }
+                "--scenario" => {
+                    cli_args.scenario_path = Some(PathBuf::from(Self::value_of(&arg, args.next())?));
+                    cli_args.headless = true;
+                }
+                "--seed" => {
+                    let value = Self::value_of(&arg, args.next())?;
+                    let seed = value.parse::<u64>()
+                        .map_err(|_| format!("Invalid seed '{}', expected a non negative integer", value))?;
+                    cli_args.seed = Some(seed);
+                }
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+
+        cli_args.validate()?;
+        Ok(CliCommand::Run(cli_args))
+    }
+
+    /// Checks the options before anything is started.
+    fn validate(&self) -> Result<(), String> {
+        if !self.topology_path.exists() {
+            return Err(format!("Topology file {} does not exist", self.topology_path.display()));
+        }
+        if !self.topology_path.is_file() {
+            return Err(format!("Topology path {} is not a file", self.topology_path.display()));
+        }
+        if self.topology_path.extension().map_or(true, |ext| ext != "toml") {
+            return Err(format!("Topology file {} is not a .toml file", self.topology_path.display()));
+        }
+        if let Some(scenario_path) = &self.scenario_path {
+            if !scenario_path.is_file() {
+                return Err(format!("Scenario file {} does not exist", scenario_path.display()));
+            }
+        }
+        if !self.headless && self.http_port == self.ws_port {
+            return Err(format!("HTTP and WebSocket servers cannot share the port {}", self.http_port));
+        }
+        Ok(())
+    }
+
+    fn value_of(flag: &str, value: Option<String>) -> Result<String, String> {
+        value.ok_or_else(|| format!("Missing value for '{}'", flag))
+    }
+
+    fn parse_port(flag: &str, value: Option<String>) -> Result<u16, String> {
+        let value = Self::value_of(flag, value)?;
+        match value.parse::<u16>() {
+            Ok(0) | Err(_) => Err(format!("Invalid port '{}' for '{}', expected a number between 1 and 65535", value, flag)),
+            Ok(port) => Ok(port),
+        }
+    }
+}
+
+pub fn usage() -> String {
+    format!(
+        "Usage: KrustyCrap [OPTIONS]\n\
+        \n\
+        Options:\n\
+        \x20 -t, --topology <PATH>    Network initialization file (default: {})\n\
+        \x20     --http-port <PORT>   Port of the web interface (default: {})\n\
+        \x20     --ws-port <PORT>     Port of the WebSocket server (default: {})\n\
+        \x20     --log-level <LEVEL>  off, error, warn, info, debug or trace (default: RUST_LOG)\n\
+        \x20     --headless           Run without the web interface and the WebSocket server\n\
+        \x20     --scenario <PATH>    Run the steps of a scenario file headless and report the results\n\
+        \x20     --seed <SEED>        Seed of every random choice, to replay a run (default: topology file or random)\n\
+        \x20 -h, --help               Print this message",
+        DEFAULT_TOPOLOGY, DEFAULT_HTTP_PORT, DEFAULT_WS_PORT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliCommand, String> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_args(args: &[&str]) -> CliArgs {
+        match parse(args) {
+            Ok(CliCommand::Run(cli_args)) => cli_args,
+            other => panic!("expected the options, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let cli_args = parse_args(&[]);
+        assert_eq!(cli_args.topology_path, PathBuf::from(DEFAULT_TOPOLOGY));
+        assert_eq!(cli_args.http_port, DEFAULT_HTTP_PORT);
+        assert_eq!(cli_args.ws_port, DEFAULT_WS_PORT);
+        assert!(!cli_args.headless);
+    }
+
+    #[test]
+    fn help_wins_over_the_other_options() {
+        assert!(matches!(parse(&["--headless", "--help"]), Ok(CliCommand::Help)));
+    }
+
+    #[test]
+    fn missing_topology_file_is_refused() {
+        let error = parse(&["--topology", "topologies/missing.toml"]).unwrap_err();
+        assert!(error.contains("does not exist"), "{}", error);
+    }
+
+    #[test]
+    fn topology_that_is_not_toml_is_refused() {
+        let error = parse(&["-t", "README.md"]).unwrap_err();
+        assert!(error.contains("not a .toml file"), "{}", error);
+    }
+
+    #[test]
+    fn topology_directory_is_refused() {
+        let error = parse(&["-t", "topologies"]).unwrap_err();
+        assert!(error.contains("not a file"), "{}", error);
+    }
+
+    #[test]
+    fn port_zero_is_refused() {
+        assert!(parse(&["--http-port", "0"]).is_err());
+        assert!(parse(&["--ws-port", "0"]).is_err());
+        assert!(parse(&["--ws-port", "65536"]).is_err());
+        assert!(parse(&["--ws-port"]).is_err());
+    }
+
+    #[test]
+    fn same_http_and_ws_port_needs_headless() {
+        let error = parse(&["--http-port", "9000", "--ws-port", "9000"]).unwrap_err();
+        assert!(error.contains("cannot share the port 9000"), "{}", error);
+
+        let cli_args = parse_args(&["--http-port", "9000", "--ws-port", "9000", "--headless"]);
+        assert!(cli_args.headless);
+    }
+
+    #[test]
+    fn scenario_forces_headless() {
+        let cli_args = parse_args(&["--scenario", "scenarios/tree_smoke.toml", "--http-port", "9000", "--ws-port", "9000"]);
+        assert!(cli_args.headless);
+        assert_eq!(cli_args.scenario_path, Some(PathBuf::from("scenarios/tree_smoke.toml")));
+    }
+
+    #[test]
+    fn missing_scenario_file_is_refused() {
+        assert!(parse(&["--scenario", "scenarios/missing.toml"]).is_err());
+    }
+
+    #[test]
+    fn log_level_and_seed_are_parsed() {
+        let cli_args = parse_args(&["--log-level", "debug", "--seed", "42"]);
+        assert_eq!(cli_args.log_level, Some(LevelFilter::Debug));
+        assert_eq!(cli_args.seed, Some(42));
+        assert!(parse(&["--log-level", "loud"]).is_err());
+        assert!(parse(&["--seed", "-1"]).is_err());
+    }
+
+    #[test]
+    fn unknown_argument_is_refused() {
+        let error = parse(&["--verbose"]).unwrap_err();
+        assert!(error.contains("Unknown argument '--verbose'"), "{}", error);
+    }
+}
@@ -12,5 +12,8 @@ pub trait Client {
         controller_recv: Receiver<ClientCommand>,
     ) -> Self;
 
+    /// Fragments of a message that can wait for their ack at the same time.
+    fn set_send_window(&mut self, send_window: usize);
+
     fn run(&mut self);
 }
@@ -11,8 +11,11 @@ use crate::clients::Client as TraitClient;
 /// Note: when you send the packet with routing the hop_index is increased in the receiving by a drone
 
 use crate::clients::client_chen::prelude::*;
-use crate::clients::client_chen::{CommandHandler, CommunicationTrait, FragmentsHandler, PacketsReceiver, Sending};
-use crate::general_use::{DroneId, MediaRef};
+use crate::clients::client_chen::{CommandHandler, CommunicationTrait, FragmentsHandler, PacketsReceiver, Router, Sending};
+use crate::general_use::{DroneId, MediaRef, PendingQueries, ServerError};
+use crate::fragmentation::{OutgoingMessage, Reassembler, Throughput, DEFAULT_SEND_WINDOW, MAX_RESPONSE_FRAGMENTS, RETRANSMISSION_CHECK_INTERVAL};
+use crossbeam_channel::tick;
+use crate::link_quality::LinkQuality;
 
 #[derive(Clone)]
 pub(crate) struct ClientChen {
@@ -60,7 +63,13 @@ impl TraitClient for ClientChen {
                 servers: HashSet::new(),
                 connected_nodes_ids: connected_nodes,
                 routing_table: HashMap::new(),
+                backup_routing_table: HashMap::new(),
+                backup_routes_to_repair: HashSet::new(),
+                link_quality: LinkQuality::new(),
+                send_window: DEFAULT_SEND_WINDOW,
+                throughput: Throughput::new(),
                 drops_counter: HashMap::new(),
+                pending_queries: PendingQueries::default(),
             },
 
             // Communication tools
@@ -74,7 +83,8 @@ impl TraitClient for ClientChen {
             // Storage
             storage: NodeStorage {
                 //irresolute_path_traces: HashMap::new(),
-                fragment_assembling_buffer: HashMap::new(),
+                reassembler: Reassembler::new(MAX_RESPONSE_FRAGMENTS),
+                outgoing_messages: HashMap::new(),
                 output_buffer: HashMap::new(),
                 packets_status: HashMap::new(),
                 current_list_file: Vec::new(),
@@ -82,6 +92,7 @@ impl TraitClient for ClientChen {
                 current_text_media_list: Vec::new(),
                 current_chosen_media_ref: "".to_string(),
                 current_received_serialized_media: Default::default(),
+                last_server_error: None,
                 //current_chosen_media: String::new(),
             },
 
@@ -93,7 +104,12 @@ impl TraitClient for ClientChen {
         }
     }
 
+    fn set_send_window(&mut self, send_window: usize) {
+        self.communication.send_window = send_window;
+    }
+
     fn run(&mut self) {
+        let retransmission_ticker = tick(RETRANSMISSION_CHECK_INTERVAL);
         loop {
             select_biased! {
                 recv(self.communication_tools.controller_recv) -> command_res => {
@@ -101,7 +117,8 @@ impl TraitClient for ClientChen {
                         self.handle_controller_command(command);
 
                         // Things to do after handling the command
-                        self.handle_fragments_in_buffer_with_checking_status();
+                        self.discard_expired_messages();
+                        self.repair_backup_routes();
                         self.send_packets_in_buffer_with_checking_status();
                     }
                 },
@@ -109,10 +126,14 @@ impl TraitClient for ClientChen {
                     if let Ok(packet) = packet_res {
                         self.handle_received_packet(packet);
                         // Things to do after handling the command
-                        self.handle_fragments_in_buffer_with_checking_status();
+                        self.discard_expired_messages();
+                        self.repair_backup_routes();
                         self.send_packets_in_buffer_with_checking_status();
                     }
                 },
+                recv(retransmission_ticker) -> _ => {
+                    self.retransmit_timed_out_fragments();
+                },
             }
         }
     }
@@ -125,6 +146,20 @@ impl ClientChen{
     pub(crate) fn update_servers(&mut self){
         self.communication.servers = self.get_discovered_servers_from_topology();
     }
+
+    /// Neighbours of every known node, as seen in the path traces.
+    pub(crate) fn topology_graph(&self) -> HashMap<NodeId, HashSet<NodeId>> {
+        self.network_info.topology.iter()
+            .map(|(node_id, node_info)| (*node_id, node_info.specific_info.connected_nodes_ids().clone()))
+            .collect()
+    }
+
+    pub(crate) fn is_drone(&self, node_id: NodeId) -> bool {
+        matches!(
+            self.network_info.topology.get(&node_id).map(|node_info| &node_info.specific_info),
+            Some(SpecificInfo::DroneInfo(_))
+        )
+    }
 }
 
 // Metadata about the client
@@ -147,7 +182,13 @@ pub(crate) struct CommunicationInfo {
     pub(crate) servers: HashSet<ServerId>,
     pub(crate) connected_nodes_ids: HashSet<NodeId>,
     pub(crate) routing_table: HashMap<NodeId, Vec<NodeId>>, // Routing information per protocol
+    pub(crate) backup_routing_table: HashMap<NodeId, Vec<NodeId>>,  // Routes sharing no drone with the primary ones, for the failover
+    pub(crate) backup_routes_to_repair: HashSet<NodeId>,            // Destinations whose backup route has to be computed again
+    pub(crate) link_quality: LinkQuality,
+    pub(crate) send_window: usize,
+    pub(crate) throughput: Throughput,
     pub(super) drops_counter: HashMap<SessionId, HashMap<DroneId, u8>>, // Counter for dropped packets
+    pub(crate) pending_queries: PendingQueries,                         // Sending times of the unanswered queries
 }
 
 
@@ -163,7 +204,8 @@ pub(crate) struct CommunicationTools {
 // Storage-related data
 #[derive(Clone)]
 pub struct NodeStorage {
-    pub(crate) fragment_assembling_buffer: HashMap<SessionId, HashMap<FragmentIndex, Packet>>, // Temporary storage for recombining fragments
+    pub(crate) reassembler: Reassembler,                                        // Responses being recombined, by server and session
+    pub(crate) outgoing_messages: HashMap<SessionId, OutgoingMessage>,          // Fragments of the queries, until the server acks all of them
     pub(crate) output_buffer: HashMap<SessionId, HashMap<FragmentIndex, Packet>>,              // Buffer for outgoing messages
     pub(crate) packets_status: HashMap<SessionId, HashMap<FragmentIndex, PacketStatus>>,       // Map every packet with the status of sending
     pub(crate) current_list_file: Vec<String>,                                  // Files received from media servers
@@ -171,6 +213,7 @@ pub struct NodeStorage {
     pub(crate) current_text_media_list: Vec<MediaRef>,
     pub(crate) current_chosen_media_ref: MediaRef,
     pub(crate) current_received_serialized_media: HashMap<MediaRef, String>,
+    pub(crate) last_server_error: Option<(ServerId, ServerError)>,              // Shown by the UI
     //pub(crate) current_chosen_media: String,
 }
 
@@ -193,6 +236,24 @@ pub enum SpecificInfo {
     DroneInfo(DroneInformation),
 }
 
+impl SpecificInfo {
+    pub(crate) fn connected_nodes_ids(&self) -> &HashSet<NodeId> {
+        match self {
+            SpecificInfo::ClientInfo(client_info) => &client_info.connected_nodes_ids,
+            SpecificInfo::ServerInfo(server_info) => &server_info.connected_nodes_ids,
+            SpecificInfo::DroneInfo(drone_info) => &drone_info.connected_nodes_ids,
+        }
+    }
+
+    pub(crate) fn connected_nodes_ids_mut(&mut self) -> &mut HashSet<NodeId> {
+        match self {
+            SpecificInfo::ClientInfo(client_info) => &mut client_info.connected_nodes_ids,
+            SpecificInfo::ServerInfo(server_info) => &mut server_info.connected_nodes_ids,
+            SpecificInfo::DroneInfo(drone_info) => &mut drone_info.connected_nodes_ids,
+        }
+    }
+}
+
 // Manually implement Default for SpecificInfo
 impl Default for SpecificInfo {
     fn default() -> Self {
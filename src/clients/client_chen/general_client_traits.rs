@@ -1,4 +1,3 @@
-use serde::de::DeserializeOwned;
 use crate::clients::client_chen::prelude::*;
 
 pub trait Sending{
@@ -9,6 +8,10 @@ pub trait Sending{
     fn send_event(&mut self, client_event: ClientEvent);
     fn send_query(&mut self, server_id: ServerId, query: Query);
     fn send_query_by_routing_header(&mut self, source_routing_header: SourceRoutingHeader, query: Query);
+    fn send_packets_in_window(&mut self, packets: Vec<Packet>);
+    fn send_window_fragments(&mut self, session_id: SessionId);
+    fn retransmit_timed_out_fragments(&mut self);
+    fn give_up_session(&mut self, session_id: SessionId);
 
     fn send_packet_to_connected_node(&mut self, target_node_id: NodeId, packet: Packet);
 
@@ -26,6 +29,11 @@ pub trait Router{
     fn do_flooding(&mut self);
     fn update_routing_for_server(&mut self, destination_id: NodeId, path_trace: Vec<(NodeId,NodeType)>);
     fn update_routing_for_client(&mut self, destination_id: NodeId, path_trace: Vec<(NodeId,NodeType)>);
+    fn find_best_route(&self, destination_id: NodeId) -> Option<Vec<NodeId>>;
+    fn refresh_route(&mut self, destination_id: NodeId);
+    fn find_backup_route(&self, destination_id: NodeId, primary: &[NodeId]) -> Option<Vec<NodeId>>;
+    fn repair_backup_routes(&mut self);
+    fn fail_over(&mut self, crashed_node_id: NodeId);
 
     //auxiliary function
     fn get_flood_response_initiator(&mut self, flood_response: FloodResponse) -> NodeId;
@@ -41,7 +49,6 @@ pub trait CommunicationTrait{
 
 pub trait PacketCreator{
     ///creating fragment packet
-    fn divide_string_into_slices(&mut self, string: String, max_slice_length: usize) -> Vec<String>;
     fn msg_to_fragments<T: Serialize>(&mut self, msg: T, destination_id: NodeId) -> Option<Vec<Packet>>;
     fn msg_to_fragments_by_routing_header<T: Serialize>(&mut self, msg: T, source_routing_header: SourceRoutingHeader) -> Option<Vec<Packet>>;
     ///creating ack packet
@@ -58,7 +65,7 @@ pub trait PacketsReceiver{
 }
 
 pub trait PacketResponseHandler:PacketsReceiver{   //Ack Nack
-    fn handle_ack(&mut self, ack_packet_session_id: SessionId, ack: &Ack);
+    fn handle_ack(&mut self, ack_packet_session_id: SessionId, ack: &Ack, route: &[NodeId]);
     fn handle_nack(&mut self, nack_packet: Packet, nack: &Nack);
 
 
@@ -80,13 +87,10 @@ pub trait FloodingPacketsHandler:PacketsReceiver{  //flood request/response
 
 pub trait FragmentsHandler:PacketsReceiver{ //message fragments
     ///auxiliary functions
-    fn get_total_n_fragments(&self, session_id: SessionId) -> Option<u64>;
-    fn get_fragments_quantity_for_session(&self, session_id: SessionId) -> Option<u64>;
-    fn handle_fragments_in_buffer_with_checking_status(&mut self);  //when you run
+    fn discard_expired_messages(&mut self);  //when you run
     fn process_message(&mut self, initiator_id: NodeId, message: Response);
     ///principal methods
-    fn reassemble_fragments<T: Serialize + DeserializeOwned>(&mut self, fragments: Vec<Packet>) -> Result<T, String>;
-    fn reassemble_fragments_in_buffer<T: Serialize + DeserializeOwned>(&mut self, session_id: SessionId) -> Result<T, String>;
+    fn handle_fragment(&mut self, initiator_id: NodeId, session_id: SessionId, fragment: &Fragment);
 }
 
 pub trait CommandHandler{
@@ -13,6 +13,7 @@ impl CommandHandler for ClientChen{
             }
             ClientCommand::RemoveSender(target_node_id) => {
                 self.communication_tools.packet_send.remove(&target_node_id);
+                self.fail_over(target_node_id);
             }
 
             ClientCommand::StartFlooding => {
@@ -55,6 +56,9 @@ impl CommandHandler for ClientChen{
                 self.ask_media(media_ref);
             }
             ClientCommand::DroneFixed(drone_id) => {
+                // The drops seen before the repair say nothing about the fixed drone
+                self.communication.link_quality.forget(drone_id);
+
                 // Collect (session_id, fragment_index) pairs where the status is WaitingForFixing(drone_id)
                 let filtered_pairs: Vec<(SessionId, FragmentIndex)> = self
                     .storage
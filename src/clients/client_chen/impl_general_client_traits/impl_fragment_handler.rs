@@ -1,83 +1,34 @@
-use serde::de::DeserializeOwned;
 use crate::clients::client_chen::{ClientChen, FragmentsHandler};
 use crate::clients::client_chen::prelude::*;
 use crate::clients::client_chen::general_client_traits::*;
 use crate::clients::client_chen::web_browser_client_traits::WebBrowserClientTrait;
 use crate::general_use::DataScope;
+use crate::fragmentation;
 use crate::ui_traits::Monitoring;
 
 impl FragmentsHandler for ClientChen {
-    fn get_total_n_fragments(&self, session_id: SessionId) -> Option<u64> {
-        self.storage
-            .fragment_assembling_buffer
-            .get(&session_id)?
-            .values()
-            .next()  // Get first packet in the session
-            .and_then(|packet| {
-                if let PacketType::MsgFragment(fragment) = &packet.pack_type {
-                    Some(fragment.total_n_fragments)
-                } else {
-                    None
-                }
-            })
-    }
-
-    fn get_fragments_quantity_for_session(&self, session_id: SessionId) -> Option<u64> {
-        Some(
-            self.storage
-                .fragment_assembling_buffer
-                .get(&session_id)?
-                .len() as u64
-        )
+    fn discard_expired_messages(&mut self) {
+        for (server_id, session_id) in self.storage.reassembler.discard_expired() {
+            warn!("CLIENT[{}]: incomplete message {} from server {} discarded", self.metadata.node_id, session_id, server_id);
+        }
     }
 
-    fn handle_fragments_in_buffer_with_checking_status(&mut self) {
-        // Collect session IDs to avoid borrowing issues during iteration
-        let session_ids: Vec<_> = self
-            .storage
-            .fragment_assembling_buffer
-            .keys()
-            .cloned()
-            .collect();
-
-        // Iterate over each session and process fragments
-        for session_id in session_ids {
-            if let Some(total_n_fragments) = self.get_total_n_fragments(session_id) {
-                if let Some(fragments_quantity) = self.get_fragments_quantity_for_session(session_id) {
-                    if fragments_quantity == total_n_fragments {
-                        if let Some(first_packet) = self
-                            .storage
-                            .fragment_assembling_buffer
-                            .get(&session_id)
-                            .and_then(|fragments| fragments.values().next())
-                        {
-                            let initiator_id = first_packet.routing_header.source();
-
-                            // Reassemble fragments and process the message
-                            if let Ok(message) = self.reassemble_fragments_in_buffer(session_id) {
-                                if let Some(id) = initiator_id {
-                                    self.process_message(id, message);
-                                    self.storage.fragment_assembling_buffer.remove(&session_id);
-                                } else {
-                                    warn!("Initiator ID not found for session: {:?}", session_id);
-                                }
-                            } else {
-                                warn!(
-                                    "Failed to reassemble fragments for session: {:?}",
-                                    session_id
-                                );
-                            }
-                        } else {
-                            warn!("No fragments found for session: {:?}", session_id);
-                        }
-                    }
-                }
-            }
+    fn handle_fragment(&mut self, initiator_id: NodeId, session_id: SessionId, fragment: &Fragment) {
+        // The fragments can arrive in any order, the message is processed once all of them are here
+        match self.storage.reassembler.add_fragment(initiator_id, session_id, fragment) {
+            Ok(Some(data)) => match fragmentation::deserialize_message(&data) {
+                Ok(message) => self.process_message(initiator_id, message),
+                Err(e) => warn!("Failed to reassemble fragments for session {:?}: {}", session_id, e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Message of session {:?} from {} dropped: {}", session_id, initiator_id, e),
         }
     }
 
-
     fn process_message(&mut self, initiator_id: NodeId, message: Response) {
+        if let Some((query_kind, latency)) = self.communication.pending_queries.response_received(initiator_id, &message) {
+            self.send_event(ClientEvent::QueryAnswered(self.metadata.node_id, query_kind, latency));
+        }
         match message {
             Response::ServerType(server_type) => {
                 self.update_topology_entry_for_server(initiator_id, server_type);
@@ -99,68 +50,19 @@ impl FragmentsHandler for ClientChen {
                 self.handle_media(media);
                 self.send_display_data(DataScope::UpdateSelf);
             },
+            Response::MediaWithMeta(media_file) => {
+                // The browser gets the type from the data url
+                self.handle_media(media_file.to_data_url());
+                self.send_display_data(DataScope::UpdateSelf);
+            },
             Response::Err(error) => {
-                info!("Error received: {:?}", error);
+                warn!("CLIENT[{}]: error from server {}: {}", self.metadata.node_id, initiator_id, error);
+                self.storage.last_server_error = Some((initiator_id, error));
+                self.send_display_data(DataScope::UpdateSelf);
             },
             _ => {}
         }
 
 
     }
-
-    fn reassemble_fragments<T: Serialize + DeserializeOwned>(&mut self, fragments: Vec<Packet>) -> Result<T, String> {
-        let mut raw_data = Vec::new();
-
-        for packet in fragments {
-            match &packet.pack_type {
-                PacketType::MsgFragment(fragment) => {
-                    // Push only the valid portion of `data`
-                    raw_data.extend_from_slice(&fragment.data[..fragment.length as usize]);
-                }
-                _ => return Err("Non-fragment packet type".to_string()),
-            }
-        }
-
-        // Convert bytes to String once at the end
-        let serialized_msg = String::from_utf8(raw_data)
-            .map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
-
-        // Deserialize the complete message
-        serde_json::from_str(&serialized_msg)
-            .map_err(|e| format!("Deserialization failed: {}", e))
-    }
-    fn reassemble_fragments_in_buffer<T: Serialize + DeserializeOwned>(&mut self, session_id: SessionId) -> Result<T, String> {
-        // Get fragments once to avoid multiple lookups
-        let fragments = self.storage
-            .fragment_assembling_buffer
-            .get(&session_id)
-            .ok_or_else(|| format!("Session {} not found in buffer", session_id))?;
-
-        // Collect and sort fragment indices
-        let mut keys: Vec<FragmentIndex> = fragments.keys().cloned().collect();
-        keys.sort();
-
-        // Pre-allocate buffer for better performance
-        let mut raw_data = Vec::new();
-
-        for key in keys {
-            let packet = fragments.get(&key)
-                .ok_or_else(|| format!("Fragment {} missing in session {}", key, session_id))?;
-
-            match &packet.pack_type {
-                PacketType::MsgFragment(fragment) => {
-                    raw_data.extend_from_slice(&fragment.data[..fragment.length as usize]);
-                }
-                _ => return Err(format!("Non-fragment packet type in session {} at index {}", session_id, key)),
-            }
-        }
-
-        // Convert bytes to String once at the end
-        let serialized_msg = String::from_utf8(raw_data)
-            .map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
-
-        // Deserialize the complete message
-        serde_json::from_str(&serialized_msg)
-            .map_err(|e| format!("Deserialization failed: {}", e))
-    }
 }
@@ -3,7 +3,7 @@ use crate::clients::client_chen::prelude::*;
 use crate::general_use::PacketStatus::{Sent, WaitingForFixing};
 
 impl PacketResponseHandler for ClientChen {
-    fn handle_ack(&mut self, ack_packet_session_id: SessionId, ack: &Ack) {
+    fn handle_ack(&mut self, ack_packet_session_id: SessionId, ack: &Ack, route: &[NodeId]) {
         /*println!(
             "\n==============================================\n\
          ✔ ACK RECEIVED\n\
@@ -16,6 +16,12 @@ impl PacketResponseHandler for ClientChen {
         let session_id = ack_packet_session_id;
         let fragment_index = ack.fragment_index;
 
+        // The drones of the ack route forwarded the fragment, a duplicated ack is not counted (neither in the throughput)
+        if self.storage.outgoing_messages.get(&session_id).is_some_and(|message| !message.is_acked(fragment_index)) {
+            self.communication.link_quality.record_delivery(route);
+            self.communication.throughput.record_ack();
+        }
+
         // Update packets_status using nested HashMap access
         self.update_packet_status(session_id, fragment_index, Sent);
 
@@ -23,6 +29,16 @@ impl PacketResponseHandler for ClientChen {
         if let Some(fragments) = self.storage.output_buffer.get_mut(&session_id) {
             fragments.remove(&fragment_index);
         }
+
+        // Once the server has every fragment, the session is forgotten
+        if let Some(message) = self.storage.outgoing_messages.get_mut(&session_id) {
+            if message.ack(fragment_index) {
+                self.storage.outgoing_messages.remove(&session_id);
+                self.storage.output_buffer.remove(&session_id);
+                self.storage.packets_status.remove(&session_id);
+                self.communication.drops_counter.remove(&session_id);
+            }
+        }
     }
 
 
@@ -43,6 +59,9 @@ impl PacketResponseHandler for ClientChen {
             warn!("Removed broken connection to node {} from packet_send", node_id);
         }
 
+        // The routes through the node switch to their backup, the flooding is only needed without one
+        self.fail_over(node_id);
+
         println!("Routing error encountered for node {}: Drone crashed or sender not found", node_id);
 
         let session_id = nack_packet_session_id;
@@ -115,6 +134,20 @@ impl PacketResponseHandler for ClientChen {
         //println!("query packet_dropped");
         let session_id = nack_packet.session_id;
 
+        self.send_event(ClientEvent::FragmentRetransmitted(self.metadata.node_id, session_id));
+
+        // The drop makes the drone more expensive, the fragment may go around it
+        if let Some(drone) = nack_packet.routing_header.source() {
+            self.communication.link_quality.record_drop(drone);
+        }
+        let destination = self.storage.output_buffer
+            .get(&session_id)
+            .and_then(|fragments| fragments.get(&nack.fragment_index))
+            .and_then(|packet| packet.routing_header.destination());
+        if let Some(destination) = destination {
+            self.refresh_route(destination);
+        }
+
         // When the drone pdr is very high then we need to fix, we give him chance up to 10 times repeating pack drop.
         if let Some(drone) = nack_packet.routing_header.source() {
             let map = self
@@ -136,6 +169,10 @@ impl PacketResponseHandler for ClientChen {
                     .entry(session_id)
                     .or_insert_with(HashMap::new)
                     .insert(nack.fragment_index, WaitingForFixing(drone));
+                // No ack is awaited until the drone is fixed
+                if let Some(message) = self.storage.outgoing_messages.get_mut(&session_id) {
+                    message.stop_timer(nack.fragment_index);
+                }
 
                 return;
             }
@@ -147,13 +184,21 @@ impl PacketResponseHandler for ClientChen {
             PacketStatus::NotSent(NotSentType::Dropped),
         );
 
-        if let Some(map) = self.storage.output_buffer.get(&session_id) {
-            if let Some(packet) = map.get(&nack.fragment_index) {
-                // Notice that the packet status will be automatically updated
-                self.send(packet.clone());
-            } else {
-                println!("Dropped packet not found in output buffer");
-            }
+        // The fragment takes the current route, which may avoid the drone
+        let route = destination.and_then(|destination| self.communication.routing_table.get(&destination)).cloned();
+        let packet_to_resend = self.storage.output_buffer
+            .get_mut(&session_id)
+            .and_then(|fragments| fragments.get_mut(&nack.fragment_index))
+            .map(|packet| {
+                if let Some(route) = route.filter(|route| !route.is_empty()) {
+                    packet.routing_header = SourceRoutingHeader::initialize(route);
+                }
+                packet.clone()
+            });
+
+        if let Some(packet) = packet_to_resend {
+            // Notice that the packet status will be automatically updated
+            self.send(packet);
         } else {
             println!("Dropped packet not found in output buffer");
         }
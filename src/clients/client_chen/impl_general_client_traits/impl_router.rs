@@ -12,6 +12,7 @@ impl Router for ClientChen {
         self.status.session_id += 1;
 
         self.communication.routing_table.clear();
+        self.communication.backup_routing_table.clear();
         self.network_info.topology.clear();
 
         // Initialize the flood request with the current flood_id, id, and node type
@@ -35,10 +36,14 @@ impl Router for ClientChen {
         }
     }
     fn update_routing_for_server(&mut self, destination_id: NodeId, path_trace: Vec<(NodeId, NodeType)>) {
-        // Step 1: Extract hops from the path trace
-        let hops = self.get_hops_from_path_trace(path_trace);
-        // Step 2: Update the routing table of the route of the server
+        // Step 1: Take the cheapest route in the known topology, the path trace was just added to it
+        let hops = match self.find_best_route(destination_id) {
+            Some(hops) => hops,
+            None => self.get_hops_from_path_trace(path_trace),
+        };
+        // Step 2: Update the routing table of the route of the server, the backup route follows it
         self.communication.routing_table.insert(destination_id, hops.clone());
+        self.communication.backup_routes_to_repair.insert(destination_id);
 
         // Step 3: Create a SourceRoutingHeader
         let srh = SourceRoutingHeader::initialize(hops);
@@ -70,12 +75,99 @@ impl Router for ClientChen {
         }
     }
     fn update_routing_for_client(&mut self, destination_id: NodeId, path_trace: Vec<(NodeId, NodeType)>) {
-        let hops = self.get_hops_from_path_trace(path_trace.clone());
+        let hops = match self.find_best_route(destination_id) {
+            Some(hops) => hops,
+            None => self.get_hops_from_path_trace(path_trace.clone()),
+        };
         self.communication.routing_table.insert(destination_id, hops);
+        self.communication.backup_routes_to_repair.insert(destination_id);
         info!("Successfully updated routing table for client {}", destination_id);
         info!("The routing table is: {:?}", self.communication.routing_table);
     }
 
+    /// Route with the least expected transmissions in the known topology, only drones are crossed.
+    /// Without drops it is the shortest one.
+    fn find_best_route(&self, destination_id: NodeId) -> Option<Vec<NodeId>> {
+        let topology = self.topology_graph();
+        self.communication.link_quality.best_route(self.metadata.node_id, destination_id, &topology, |node_id| self.is_drone(node_id))
+    }
+
+    /// Takes a cheaper route to the destination if the drops made the current one too expensive.
+    fn refresh_route(&mut self, destination_id: NodeId) {
+        let (Some(route), Some(best_route)) = (self.communication.routing_table.get(&destination_id), self.find_best_route(destination_id)) else {
+            return;
+        };
+        let cost = self.communication.link_quality.route_cost(route);
+        let best_cost = self.communication.link_quality.route_cost(&best_route);
+        if best_cost < cost {
+            info!("Route to {} changed from {:?} (cost {:.2}) to {:?} (cost {:.2})", destination_id, route, cost, best_route, best_cost);
+            self.communication.routing_table.insert(destination_id, best_route);
+            self.communication.backup_routes_to_repair.insert(destination_id);
+        }
+    }
+
+    /// Route to the destination that shares no drone with the primary one, used if the primary one breaks.
+    fn find_backup_route(&self, destination_id: NodeId, primary: &[NodeId]) -> Option<Vec<NodeId>> {
+        let topology = self.topology_graph();
+        self.communication.link_quality.disjoint_route(self.metadata.node_id, destination_id, &topology, |node_id| self.is_drone(node_id), primary)
+    }
+
+    /// Computes again the backup routes that a new primary route or a crash made invalid.
+    /// Called after each handled packet or command, so that the failover itself does not wait for it.
+    fn repair_backup_routes(&mut self) {
+        for destination_id in std::mem::take(&mut self.communication.backup_routes_to_repair) {
+            let backup = match self.communication.routing_table.get(&destination_id) {
+                Some(primary) if !primary.is_empty() => self.find_backup_route(destination_id, primary),
+                _ => None,
+            };
+            match backup {
+                Some(backup) => {
+                    debug!("Backup route to {}: {:?}", destination_id, backup);
+                    self.communication.backup_routing_table.insert(destination_id, backup);
+                }
+                None => {
+                    debug!("No backup route to {}", destination_id);
+                    self.communication.backup_routing_table.remove(&destination_id);
+                }
+            }
+        }
+    }
+
+    /// The node crashed: it leaves the topology, and the routes through it switch at once to their backup,
+    /// or to another known route. The routes without an alternative are kept, the next flooding replaces them.
+    fn fail_over(&mut self, crashed_node_id: NodeId) {
+        self.network_info.topology.remove(&crashed_node_id);
+        for node_info in self.network_info.topology.values_mut() {
+            node_info.specific_info.connected_nodes_ids_mut().remove(&crashed_node_id);
+        }
+
+        let broken_routes: Vec<NodeId> = self.communication.routing_table.iter()
+            .filter(|(_, route)| route.contains(&crashed_node_id))
+            .map(|(destination_id, _)| *destination_id)
+            .collect();
+        for destination_id in broken_routes {
+            let backup = self.communication.backup_routing_table
+                .remove(&destination_id)
+                .filter(|backup| !backup.contains(&crashed_node_id));
+            match backup.or_else(|| self.find_best_route(destination_id)) {
+                Some(route) => {
+                    info!("Route to {} switched from the crashed node {} to {:?}", destination_id, crashed_node_id, route);
+                    self.communication.routing_table.insert(destination_id, route);
+                }
+                None => warn!("No route to {} avoids the crashed node {}", destination_id, crashed_node_id),
+            }
+            self.communication.backup_routes_to_repair.insert(destination_id);
+        }
+
+        // The backups through the node are of no use anymore
+        for (destination_id, backup) in self.communication.backup_routing_table.iter() {
+            if backup.contains(&crashed_node_id) {
+                self.communication.backup_routes_to_repair.insert(*destination_id);
+            }
+        }
+        self.communication.backup_routing_table.retain(|_, backup| !backup.contains(&crashed_node_id));
+    }
+
     ///auxiliary function
     fn get_flood_response_initiator(&mut self, flood_response: FloodResponse) -> NodeId {
         flood_response.path_trace.last().map(|(id, _)| *id).unwrap()
@@ -1,11 +1,20 @@
 use crate::clients::client_chen::{ClientChen, PacketCreator, Sending};
 use crate::clients::client_chen::prelude::*;
 use crate::general_use::NotSentType::{RoutingError, ToBeSent};
+use crate::general_use::FailedSession;
+use crate::fragmentation::MAX_SEND_ATTEMPTS;
+use std::time::Instant;
 
 impl Sending for ClientChen {
     fn send_packets_in_buffer_with_checking_status(&mut self) {
         //looping in the sessions, but now agreed to have only one session in the buffer.
         //so the first for is pretty useless, but it doesn't hurt the program
+        //first the fragments that entered the send window of their message, as the acks arrived
+        let outgoing_sessions: Vec<SessionId> = self.storage.outgoing_messages.keys().cloned().collect();
+        for session_id in outgoing_sessions {
+            self.send_window_fragments(session_id);
+        }
+
         let sessions: Vec<SessionId> = self.storage.output_buffer.keys().cloned().collect();
         for session_id in sessions {
             if let Some(fragments) = self.storage.output_buffer.get(&session_id) {
@@ -48,25 +57,120 @@ impl Sending for ClientChen {
     }
 
     fn send_query(&mut self, server_id: ServerId, query: Query) {
+        self.communication.pending_queries.query_sent(server_id, &query);
         if let Some(query_packets) = self.msg_to_fragments(query, server_id) {
-            for query_packet in query_packets {
-                self.send(query_packet);
-            }
+            self.send_packets_in_window(query_packets);
         } else {
             warn!("Failed to fragment query");
         }
     }
 
     fn send_query_by_routing_header(&mut self, source_routing_header: SourceRoutingHeader, query: Query) {
+        if let Some(server_id) = source_routing_header.destination() {
+            self.communication.pending_queries.query_sent(server_id, &query);
+        }
         if let Some(query_packets) = self.msg_to_fragments_by_routing_header(query, source_routing_header) {
-            for query_packet in query_packets {
-                self.send(query_packet);
-            }
+            self.send_packets_in_window(query_packets);
         } else {
             warn!("Failed to fragment query");
         }
     }
 
+    /// Sends the packets of a new message that are in its send window, the others are sent as the acks arrive.
+    fn send_packets_in_window(&mut self, packets: Vec<Packet>) {
+        let Some(session_id) = packets.first().map(|packet| packet.session_id) else {
+            return;
+        };
+        let Some(message) = self.storage.outgoing_messages.get_mut(&session_id) else {
+            return;
+        };
+        let sendable = message.take_sendable();
+        for packet in packets {
+            if let PacketType::MsgFragment(fragment) = &packet.pack_type {
+                if sendable.contains(&fragment.fragment_index) {
+                    self.send(packet);
+                }
+            }
+        }
+    }
+
+    /// Sends the fragments that entered the send window of the message, on the current route.
+    /// Without a route they wait, the window is not moved.
+    fn send_window_fragments(&mut self, session_id: SessionId) {
+        let Some(destination_id) = self.storage.outgoing_messages.get(&session_id).map(|message| message.destination()) else {
+            return;
+        };
+        let Some(source_routing_header) = self.get_source_routing_header(destination_id) else {
+            return;
+        };
+        if source_routing_header.is_empty() {
+            return;
+        }
+        let Some(message) = self.storage.outgoing_messages.get_mut(&session_id) else {
+            return;
+        };
+        let packets: Vec<Packet> = message.take_sendable()
+            .into_iter()
+            .filter_map(|fragment_index| message.fragment(fragment_index).cloned())
+            .map(|fragment| Packet::new_fragment(source_routing_header.clone(), session_id, fragment))
+            .collect();
+        for packet in packets {
+            self.send(packet);
+        }
+    }
+
+    /// Sends again the fragments whose ack is overdue, on the current route: the drone or the ack may have been
+    /// lost without a nack. A session whose fragment was sent `MAX_SEND_ATTEMPTS` times is given up.
+    fn retransmit_timed_out_fragments(&mut self) {
+        let now = Instant::now();
+        let sessions: Vec<SessionId> = self.storage.outgoing_messages.keys().cloned().collect();
+        for session_id in sessions {
+            let Some(message) = self.storage.outgoing_messages.get_mut(&session_id) else {
+                continue;
+            };
+            let timed_out = message.take_timed_out(now);
+            if message.is_failed() {
+                self.give_up_session(session_id);
+                continue;
+            }
+            if timed_out.is_empty() {
+                continue;
+            }
+
+            //without a route the timers keep running, the next timeouts count as attempts
+            let destination_id = message.destination();
+            let Some(source_routing_header) = self.get_source_routing_header(destination_id).filter(|header| !header.is_empty()) else {
+                warn!("No route to {}, fragments of session {} not sent again", destination_id, session_id);
+                continue;
+            };
+            let packets: Vec<Packet> = timed_out
+                .into_iter()
+                .filter_map(|fragment_index| self.storage.outgoing_messages.get(&session_id)?.fragment(fragment_index).cloned())
+                .map(|fragment| Packet::new_fragment(source_routing_header.clone(), session_id, fragment))
+                .collect();
+            for packet in packets {
+                self.send_event(ClientEvent::FragmentRetransmitted(self.metadata.node_id, session_id));
+                self.send(packet);
+            }
+        }
+    }
+
+    /// Forgets the message of the session and reports it to the controller.
+    fn give_up_session(&mut self, session_id: SessionId) {
+        let Some(message) = self.storage.outgoing_messages.remove(&session_id) else {
+            return;
+        };
+        self.storage.output_buffer.remove(&session_id);
+        self.storage.packets_status.remove(&session_id);
+        self.communication.drops_counter.remove(&session_id);
+        warn!("Session {} to {} given up, a fragment was sent {} times without ack", session_id, message.destination(), MAX_SEND_ATTEMPTS);
+        self.send_event(ClientEvent::SessionFailed(FailedSession {
+            node_id: self.metadata.node_id,
+            session_id,
+            destination: message.destination(),
+        }));
+    }
+
     fn send_packet_to_connected_node(&mut self, target_node_id: NodeId, mut packet: Packet) {
         // Store packet with proper nested structure
         let (session_id, fragment_index) = match &packet.pack_type {
@@ -93,6 +197,10 @@ impl Sending for ClientChen {
                             }
                             PacketType::MsgFragment(_)=>{
                                 self.update_packet_status(session_id, fragment_index, PacketStatus::InProgress);
+                                //the ack of the fragment is awaited again from now
+                                if let Some(message) = self.storage.outgoing_messages.get_mut(&session_id) {
+                                    message.start_timer(fragment_index);
+                                }
                                 return;
                             }
                             PacketType::FloodRequest(_)=>{
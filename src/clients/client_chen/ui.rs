@@ -1,6 +1,8 @@
 use crate::ui_traits::Monitoring;
-use crate::clients::client_chen::{ClientChen, CommandHandler, CommunicationTrait, FragmentsHandler, PacketsReceiver, Sending};
-use crossbeam_channel::{select_biased};
+use crate::clients::client_chen::{ClientChen, CommandHandler, CommunicationTrait, FragmentsHandler, PacketsReceiver, Router, Sending};
+use crossbeam_channel::{select_biased, tick};
+use crate::fragmentation::RETRANSMISSION_CHECK_INTERVAL;
+use crate::simulation_controller::STATISTICS_INTERVAL;
 use crate::general_use::{DataScope, DisplayDataWebBrowser, SpecificNodeType};
 use crate::general_use::ClientEvent::WebClientData;
 
@@ -16,15 +18,21 @@ impl Monitoring for ClientChen{
             session_id: self.status.session_id,
             connected_node_ids: self.communication.connected_nodes_ids.clone(),
             routing_table: self.communication.routing_table.clone(),
+            route_costs: self.communication.link_quality.route_costs(&self.communication.routing_table),
+            send_window: self.communication.send_window,
+            throughput: self.communication.throughput.fragments_per_second(),
             discovered_text_servers : self.get_text_servers_from_topology().clone(),
             discovered_media_servers : self.get_media_servers_from_topology().clone(),
             curr_received_file_list: self.storage.current_list_file.clone(),
             chosen_file_text: self.storage.current_requested_text_file.clone(),
             serialized_media: self.storage.current_received_serialized_media.clone(),
+            last_server_error: self.storage.last_server_error.clone(),
         };
         self.send_event(WebClientData(self.metadata.node_id, display_data, data_scope));
 }
     fn run_with_monitoring(&mut self) {
+        let retransmission_ticker = tick(RETRANSMISSION_CHECK_INTERVAL);
+        let throughput_ticker = tick(STATISTICS_INTERVAL);
         loop {
             select_biased! {
                 recv(self.communication_tools.controller_recv) -> command_res => {
@@ -32,7 +40,8 @@ impl Monitoring for ClientChen{
                         // Handle the command
                         self.handle_controller_command_with_monitoring(command);
                         // Things to do after handling the command
-                        self.handle_fragments_in_buffer_with_checking_status();
+                        self.discard_expired_messages();
+                        self.repair_backup_routes();
                         self.send_packets_in_buffer_with_checking_status();
 
 
@@ -43,10 +52,19 @@ impl Monitoring for ClientChen{
                         // Handle the packet
                         self.handle_received_packet(packet);
                         // Things to do after handling the packets
-                        self.handle_fragments_in_buffer_with_checking_status();
+                        self.discard_expired_messages();
+                        self.repair_backup_routes();
                         self.send_packets_in_buffer_with_checking_status();
                     }
                 },
+                recv(retransmission_ticker) -> _ => {
+                    self.retransmit_timed_out_fragments();
+                },
+                recv(throughput_ticker) -> _ => {
+                    if self.communication.throughput.has_changed() {
+                        self.send_display_data(DataScope::UpdateSelf);
+                    }
+                },
             }
         }
     }
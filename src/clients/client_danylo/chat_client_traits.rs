@@ -12,14 +12,19 @@ use crate::general_use::{
 
 pub(super) trait PacketHandler {
     fn handle_packet(&mut self, packet: Packet);
-    fn handle_ack(&mut self, fragment_index: FragmentIndex, session_id: SessionId);
+    fn handle_ack(&mut self, fragment_index: FragmentIndex, session_id: SessionId, route: &[NodeId]);
     fn handle_nack(&mut self, nack: Nack, session_id: SessionId, last_node_id: NodeId);
     fn handle_nack_dropped(&mut self, session_id: SessionId, fragment_index: FragmentIndex, last_node_id: NodeId);
     fn wait_for_drone_fix(&mut self, last_node_id: NodeId);
     fn update_topology_and_routes(&mut self, error_node: NodeId);
     fn find_route_to(&self, server_id: ServerId) -> Option<Vec<NodeId>>;
+    fn refresh_route(&mut self, server_id: ServerId);
+    fn find_backup_route(&self, server_id: ServerId, primary: &[NodeId]) -> Option<Vec<NodeId>>;
+    fn repair_backup_routes(&mut self);
     fn update_message_route_and_resend(&mut self, fragment_index: FragmentIndex, session_id: SessionId);
     fn update_message_route(&mut self, session_id: &SessionId) -> Result<(), String>;
+    fn retransmit_timed_out_fragments(&mut self);
+    fn give_up_session(&mut self, session_id: SessionId);
     fn handle_fragment(&mut self, fragment: Fragment, session_id: SessionId, server_id: ServerId);
     fn handle_flood_request(&mut self, flood_request: FloodRequest, session_id: SessionId);
     fn handle_flood_response(&mut self, flood_response: FloodResponse);
@@ -61,6 +66,3 @@ pub(super) trait GeneratorId {
     fn parse_id(&self, id: u64) -> u64;
 }
 
-pub(super) trait Reassembler {
-    fn reassemble(&mut self, session_id: SessionId) -> Option<Response>;
-}
@@ -1,15 +1,18 @@
 use std::collections::{HashMap, HashSet};
 
-use crossbeam_channel::{select_biased, Receiver, Sender};
+use crossbeam_channel::{select_biased, tick, Receiver, Sender};
 use log::info;
 
 use wg_2024::{
     network::NodeId,
-    packet::{Fragment, Packet, NodeType},
+    packet::{Packet, NodeType},
 };
 use crate::{
+    fragmentation::{Reassembler, Throughput, DEFAULT_SEND_WINDOW, MAX_RESPONSE_FRAGMENTS, RETRANSMISSION_CHECK_INTERVAL},
+    link_quality::LinkQuality,
     general_use::{
-        ClientCommand, ClientEvent, ServerType, ClientId, ServerId, SessionId, FloodId, ChatHistory, DroneId
+        ClientCommand, ClientEvent, ServerType, ClientId, ServerId, SessionId, FloodId, ChatHistory, DroneId, PendingQueries,
+        ServerError
     },
     clients::Client
 };
@@ -34,6 +37,7 @@ pub struct ChatClientDanylo {
     pub(super) drops_counter: HashMap<SessionId, HashMap<DroneId, u8>>,      // Counter for dropped packets
     pub(super) session_id_counter: SessionId,                                // Counter for session IDs
     pub(super) flood_id_counter: FloodId,                                    // Counter for flood IDs
+    pub(super) pending_queries: PendingQueries,                              // Sending times of the unanswered queries
 
     // Used IDs
     pub(super) session_ids: Vec<SessionId>,                                  // Used session IDs
@@ -43,13 +47,19 @@ pub struct ChatClientDanylo {
     pub(super) topology: HashMap<NodeId, HashSet<NodeId>>,                   // Nodes and their neighbours
     pub(super) nodes: HashMap<NodeId, NodeType>,                             // Nodes and their types
     pub(super) routes: HashMap<ServerId, Vec<NodeId>>,                       // Routes to the servers
+    pub(super) backup_routes: HashMap<ServerId, Vec<NodeId>>,                // Routes sharing no drone with the primary ones, for the failover
+    pub(super) backup_routes_to_repair: HashSet<ServerId>,                   // Servers whose backup route has to be computed again
+    pub(super) link_quality: LinkQuality,
 
     // Message queues
     pub(super) messages_to_send: HashMap<SessionId, MessageFragments>,       // Queue of messages to be sent for different sessions
-    pub(super) fragments_to_reassemble: HashMap<SessionId, Vec<Fragment>>,   // Queue of fragments to be reassembled for different sessions
+    pub(super) reassembler: Reassembler,                                     // Messages being reassembled for different sessions
+    pub(super) send_window: usize,
+    pub(super) throughput: Throughput,
 
     // Chats
     pub(super) chats: HashMap<ClientId, ChatHistory>,                        // Chat histories with other clients
+    pub(super) last_server_error: Option<(ServerId, ServerError)>,           // Last error answered by a server, shown by the UI
 }
 
 impl Client for ChatClientDanylo {
@@ -76,6 +86,7 @@ impl Client for ChatClientDanylo {
             drops_counter: HashMap::new(),
             session_id_counter: 0,
             flood_id_counter: 0,
+            pending_queries: PendingQueries::default(),
 
             session_ids: Vec::new(),
             flood_ids: Vec::new(),
@@ -83,30 +94,46 @@ impl Client for ChatClientDanylo {
             topology: HashMap::new(),
             nodes: HashMap::new(),
             routes: HashMap::new(),
+            backup_routes: HashMap::new(),
+            backup_routes_to_repair: HashSet::new(),
+            link_quality: LinkQuality::new(),
 
             messages_to_send: HashMap::new(),
-            fragments_to_reassemble: HashMap::new(),
+            reassembler: Reassembler::new(MAX_RESPONSE_FRAGMENTS),
+            send_window: DEFAULT_SEND_WINDOW,
+            throughput: Throughput::new(),
 
             chats: HashMap::new(),
+            last_server_error: None,
         }
     }
 
+    fn set_send_window(&mut self, send_window: usize) {
+        self.send_window = send_window;
+    }
+
     fn run(&mut self) {
         info!("Running ChatClientDanylo with ID: {}", self.id);
+        let retransmission_ticker = tick(RETRANSMISSION_CHECK_INTERVAL);
         loop {
             select_biased! {
                 recv(self.controller_recv) -> command_res => {
                     if let Ok(command) = command_res {
                         info!("Client {}: Received command: {:?}", self.id, command);
                         self.handle_command(command);
+                        self.repair_backup_routes();
                     }
                 },
                 recv(self.packet_recv) -> packet_res => {
                     if let Ok(packet) = packet_res {
                         info!("Client {}: Received packet: {:?}", self.id, packet);
                         self.handle_packet(packet);
+                        self.repair_backup_routes();
                     }
                 },
+                recv(retransmission_ticker) -> _ => {
+                    self.retransmit_timed_out_fragments();
+                },
             }
         }
     }
@@ -44,6 +44,9 @@ impl CommandHandler for ChatClientDanylo {
             ClientCommand::AskListClients(server_id) => {
                 self.request_clients_list(server_id)
             }
+            ClientCommand::DroneFixed(drone_id) => {
+                self.link_quality.forget(drone_id)
+            }
             _ => {}
         }
     }
@@ -83,6 +86,7 @@ impl CommandHandler for ChatClientDanylo {
 
         // Clear all current data structures related to topology.
         self.routes.clear();
+        self.backup_routes.clear();
         self.topology.clear();
 
         // Generate a new flood ID.
@@ -208,7 +212,7 @@ impl CommandHandler for ChatClientDanylo {
     }
 
     /// ###### Creates and sends a message to a specified server.
-    /// Serializes the data, splits it into fragments, and sends the first fragment.
+    /// Serializes the data, splits it into fragments, and sends the fragments of the send window.
     fn create_and_send_message(&mut self, query: Query, server_id: ServerId) -> Result<(), String> {
         debug!("Client {}: Creating and sending message to server {}: {:?}", self.id, server_id, query);
 
@@ -226,14 +230,16 @@ impl CommandHandler for ChatClientDanylo {
         let session_id = self.generate_session_id();
         self.session_ids.push(session_id);
 
-        // Create message (split the query into fragments) and send first fragment.
-        let mut message = MessageFragments::new(session_id, hops);
-        if message.create_message_of(query) {
-            self.messages_to_send.insert(session_id, message.clone());
-            self.drops_counter.insert(session_id, HashMap::new());
-            self.send_to_next_hop(message.get_fragment_packet(0).unwrap())
-        } else {
-            Err("Failed to create message.".to_string())
+        // Create message (split the query into fragments) and send the fragments of the window.
+        self.pending_queries.query_sent(server_id, &query);
+        let mut message = MessageFragments::new(session_id, hops, query, self.send_window)
+            .map_err(|err| format!("Failed to create message: {}", err))?;
+        let packets = message.take_sendable_packets();
+        self.messages_to_send.insert(session_id, message);
+        self.drops_counter.insert(session_id, HashMap::new());
+        for packet in packets {
+            self.send_to_next_hop(packet)?;
         }
+        Ok(())
     }
 }
\ No newline at end of file
@@ -1,6 +1,9 @@
+use crossbeam_channel::tick;
 use log::info;
 
 use crate::{
+    fragmentation::RETRANSMISSION_CHECK_INTERVAL,
+    simulation_controller::STATISTICS_INTERVAL,
     general_use::{
         ClientCommand, ClientEvent, DataScope, DisplayDataChatClient, DataScope::{UpdateAll, UpdateSelf}
     },
@@ -17,11 +20,15 @@ impl Monitoring for ChatClientDanylo {
             node_type: SpecificNodeType::ChatClient,
             flood_ids: self.flood_ids.clone(),
             routes: self.routes.clone(),
+            route_costs: self.link_quality.route_costs(&self.routes),
+            send_window: self.send_window,
+            throughput: self.throughput.fragments_per_second(),
             session_ids: self.session_ids.clone(),
             neighbours: connected_nodes_ids,
             discovered_servers: self.servers.clone(),
             available_clients: self.clients.clone(),
             chats: self.chats.clone(),
+            last_server_error: self.last_server_error.clone(),
         };
 
         self.send_event(ClientEvent::ChatClientData(self.id, display_data, data_scope));
@@ -31,18 +38,30 @@ impl Monitoring for ChatClientDanylo {
     )  {
         info!("Running ChatClientDanylo with ID: {}", self.id);
         self.send_display_data(UpdateAll);
+        let retransmission_ticker = tick(RETRANSMISSION_CHECK_INTERVAL);
+        let throughput_ticker = tick(STATISTICS_INTERVAL);
         loop {
             crossbeam_channel::select_biased! {
                 recv(self.controller_recv) -> command_res => {
                     if let Ok(command) = command_res {
                         info!("Client {}: Received command: {:?}", self.id, command);
                         self.handle_command_with_monitoring(command);
+                        self.repair_backup_routes();
                     }
                 },
                 recv(self.packet_recv) -> packet_res => {
                     if let Ok(packet) = packet_res {
                         info!("Client {}: Received packet: {:?}", self.id, packet);
                         self.handle_packet(packet);
+                        self.repair_backup_routes();
+                        self.send_display_data(UpdateSelf);
+                    }
+                },
+                recv(retransmission_ticker) -> _ => {
+                    self.retransmit_timed_out_fragments();
+                },
+                recv(throughput_ticker) -> _ => {
+                    if self.throughput.has_changed() {
                         self.send_display_data(UpdateSelf);
                     }
                 },
@@ -94,6 +113,10 @@ impl ChatClientDanylo{
                 self.request_clients_list(server_id);
                 self.send_display_data(UpdateSelf);
             }
+            ClientCommand::DroneFixed(drone_id) => {
+                self.link_quality.forget(drone_id);
+                self.send_display_data(UpdateSelf);
+            }
             _ => {}
         }
     }
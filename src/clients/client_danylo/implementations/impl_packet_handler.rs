@@ -1,4 +1,5 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::HashSet;
+use std::time::Instant;
 use log::{debug, error, info, warn};
 
 use wg_2024::{
@@ -6,14 +7,15 @@ use wg_2024::{
     network::NodeId,
 };
 
-use crate::general_use::{FragmentIndex, ServerId, ServerType, SessionId, Node, ClientEvent, ClientCommand};
-use super::{PacketHandler, ChatClientDanylo, Senders, ServerResponseHandler, Reassembler, CommandHandler};
+use crate::fragmentation::{self, MAX_SEND_ATTEMPTS};
+use crate::general_use::{FragmentIndex, ServerId, ServerType, SessionId, Node, ClientEvent, ClientCommand, FailedSession};
+use super::{PacketHandler, ChatClientDanylo, Senders, ServerResponseHandler, CommandHandler};
 
 impl PacketHandler for ChatClientDanylo {
     /// ###### Handles incoming packets and delegates them to the appropriate handler based on the packet type.
     fn handle_packet(&mut self, packet: Packet) {
         match packet.pack_type.clone() {
-            PacketType::Ack(ack) => self.handle_ack(ack.fragment_index, packet.session_id),
+            PacketType::Ack(ack) => self.handle_ack(ack.fragment_index, packet.session_id, &packet.routing_header.hops),
             PacketType::Nack(nack) => {
                 let last_node_id = packet.routing_header.hops[0];
                 self.handle_nack(nack, packet.session_id, last_node_id);
@@ -33,26 +35,38 @@ impl PacketHandler for ChatClientDanylo {
 
     /// ###### Handles the acknowledgment (ACK) for a given session and fragment.
     /// Processes the acknowledgment for a specific fragment in a session.
-    /// If there are more fragments to send, it sends the next fragment.
+    /// The send window slides past the acknowledged fragments, the fragments entering it are sent.
     /// If all fragments are acknowledged, it removes the message from queue.
-    fn handle_ack(&mut self, fragment_index: FragmentIndex, session_id: SessionId) {
+    /// The drones of the ACK route forwarded the fragment, they are counted as deliveries.
+    fn handle_ack(&mut self, fragment_index: FragmentIndex, session_id: SessionId, route: &[NodeId]) {
         debug!("Client {}: Handling ACK for session {} and fragment {}", self.id, session_id, fragment_index);
 
         // Retrieve the message fragments for the given session.
-        let message = self.messages_to_send.get_mut(&session_id).unwrap();
-
-        // Check if there is a next fragment to send.
-        if let Some(next_fragment) = message.get_fragment_packet((fragment_index + 1) as usize) {
-            // Prepare and send the next fragment if available.
-            match self.send_to_next_hop(next_fragment) {
-                Ok(_) => info!("Client {}: Sent next fragment for session {}", self.id, session_id),
-                Err(err) => error!("Client {}: Failed to send next fragment for session {}: {}", self.id, session_id, err),
-            }
-        } else {
+        let Some(message) = self.messages_to_send.get_mut(&session_id) else {
+            warn!("Client {}: ACK for session {} that is not being sent", self.id, session_id);
+            return;
+        };
+
+        // A duplicated ACK must not send the next fragment twice.
+        if message.is_acked(fragment_index) {
+            return;
+        }
+        self.link_quality.record_delivery(route);
+        self.throughput.record_ack();
+
+        if message.ack(fragment_index) {
             // All fragments are acknowledged; remove the message from queue.
             self.messages_to_send.remove(&session_id);
             self.drops_counter.remove(&session_id);
             info!("Client {}: All fragments acknowledged for session {}", self.id, session_id);
+        } else {
+            // Send the fragments that entered the window.
+            for packet in message.take_sendable_packets() {
+                match self.send_to_next_hop(packet) {
+                    Ok(_) => info!("Client {}: Sent next fragment for session {}", self.id, session_id),
+                    Err(err) => error!("Client {}: Failed to send next fragment for session {}: {}", self.id, session_id, err),
+                }
+            }
         }
     }
 
@@ -61,6 +75,12 @@ impl PacketHandler for ChatClientDanylo {
     fn handle_nack(&mut self, nack: Nack, session_id: SessionId, last_node_id: NodeId) {
         debug!("Client {}: Handling NACK for session {}: {:?}", self.id, session_id, nack);
 
+        // The message may be complete or given up already.
+        if !self.messages_to_send.contains_key(&session_id) {
+            warn!("Client {}: NACK for session {} that is not being sent", self.id, session_id);
+            return;
+        }
+
         match nack.nack_type {
             NackType::ErrorInRouting(id) => {
                 self.update_topology_and_routes(id);
@@ -80,15 +100,23 @@ impl PacketHandler for ChatClientDanylo {
     /// ###### Handles the NACK with the "Dropped" type.
     /// Increments the counter for the number of consecutive dropped fragments.
     /// If the counter reaches 10, it sends an event to call technicians to fix the drone.
-    /// Resends the fragment that was dropped.
+    /// Resends the fragment that was dropped, on a cheaper route if the drop made the current one too expensive.
     fn handle_nack_dropped(&mut self, session_id: SessionId, fragment_index: FragmentIndex, last_node_id: NodeId) {
+        self.send_event(ClientEvent::FragmentRetransmitted(self.id, session_id));
+
+        // The drop makes the drone more expensive.
+        self.link_quality.record_drop(last_node_id);
+        if let Some(server_id) = self.messages_to_send.get(&session_id).map(|message| message.destination()) {
+            self.refresh_route(server_id);
+        }
+
         // Retrieve the counter of drops for last_node_id for the given session.
         let drones_and_counters = self.drops_counter.get_mut(&session_id).unwrap();
         let Some(counter) = drones_and_counters.get_mut(&last_node_id) else {
             drones_and_counters.insert(last_node_id, 1);
 
             // Resend the fragment that was dropped.
-            self.resend_fragment(fragment_index, session_id);
+            self.update_message_route_and_resend(fragment_index, session_id);
 
             return;
         };
@@ -104,7 +132,7 @@ impl PacketHandler for ChatClientDanylo {
         }
 
         // Resend the fragment that was dropped.
-        self.resend_fragment(fragment_index, session_id);
+        self.update_message_route_and_resend(fragment_index, session_id);
     }
 
     /// ###### Waits for the drone to be fixed.
@@ -116,6 +144,7 @@ impl PacketHandler for ChatClientDanylo {
                 Ok(command) => {
                     match command {
                         ClientCommand::DroneFixed(node_id) => {
+                            self.link_quality.forget(node_id);
                             if node_id == last_node_id {
                                 break;
                             }
@@ -132,7 +161,8 @@ impl PacketHandler for ChatClientDanylo {
 
     /// ###### Updates the network topology and routes.
     /// Removes the node that caused the error from the topology and routes.
-    /// Finds new routes for the servers that need them.
+    /// The routes through the node switch to their backup at once, if the backup does not cross it too.
+    /// Finds new routes for the servers that are left without one.
     fn update_topology_and_routes(&mut self, error_node: NodeId) {
         // Remove the node that caused the error from the topology.
         for (_, neighbors) in self.topology.iter_mut() {
@@ -141,13 +171,29 @@ impl PacketHandler for ChatClientDanylo {
         self.topology.remove(&error_node);
         debug!("Client {}: Removed node {} from the topology", self.id, error_node);
 
-        // Replace the paths that contain the node that caused the error with an empty vector.
-        for route in self.routes.values_mut() {
+        // Switch the paths that contain the node that caused the error to their backup,
+        // or replace them with an empty vector if the backup contains the node too.
+        for (server_id, route) in self.routes.iter_mut() {
             if route.contains(&error_node) {
-                *route = Vec::new();
+                let backup = self.backup_routes
+                    .remove(server_id)
+                    .filter(|backup| !backup.contains(&error_node));
+                if let Some(backup) = &backup {
+                    info!("Client {}: Route to server {} switched to the backup {:?}", self.id, server_id, backup);
+                }
+                *route = backup.unwrap_or_default();
+                self.backup_routes_to_repair.insert(*server_id);
             }
         }
-        debug!("Client {}: Routes with node {} cleared", self.id, error_node);
+
+        // The backups that contain the node are of no use anymore.
+        for (server_id, backup) in self.backup_routes.iter() {
+            if backup.contains(&error_node) {
+                self.backup_routes_to_repair.insert(*server_id);
+            }
+        }
+        self.backup_routes.retain(|_, backup| !backup.contains(&error_node));
+        debug!("Client {}: Routes with node {} switched or cleared", self.id, error_node);
 
         // Collect server IDs that need new routes.
         let servers_to_update: Vec<ServerId> = self
@@ -170,52 +216,56 @@ impl PacketHandler for ChatClientDanylo {
         }
     }
 
-    /// ###### Finds a route from the current node to the specified server using breadth-first search.
+    /// ###### Finds the route from the current node to the specified server with the least expected transmissions.
     ///
-    /// This method explores the network topology starting from the current node, and returns the shortest path
-    /// (in terms of hops) to the specified server if one exists. It uses a queue to explore nodes level by level,
-    /// ensuring that the first valid path found is the shortest. If no path is found, it returns `None`.
+    /// The cost of a drone grows with the drops observed on it, so the route avoids the lossy drones
+    /// when a detour is cheaper. Without drops it is the shortest path (in terms of hops).
+    /// Only drones can be crossed. If no path is found, it returns `None`.
     fn find_route_to(&self, server_id: ServerId) -> Option<Vec<NodeId>> {
-        // Initialize a queue for breadth-first search and a set to track visited nodes.
-        let mut queue: VecDeque<(NodeId, Vec<NodeId>)> = VecDeque::new();
-        let mut visited: HashSet<NodeId> = HashSet::new();
-
-        // Start from the current node with an initial path containing just the current node.
-        queue.push_back((self.id, vec![self.id]));
-
-        // Perform breadth-first search.
-        while let Some((current, path)) = queue.pop_front() {
-            // If the destination node is reached, return the path.
-            if current == server_id {
-                return Some(path);
-            }
-
-            // Mark the current node as visited.
-            visited.insert(current);
+        let can_cross = |node_id: NodeId| !matches!(self.nodes.get(&node_id), Some(NodeType::Server) | Some(NodeType::Client));
+        self.link_quality.best_route(self.id, server_id, &self.topology, can_cross)
+    }
 
-            // Explore the neighbors of the current node.
-            if let Some(neighbors) = self.topology.get(&current) {
-                for &neighbor in neighbors {
-                    // Only visit unvisited neighbors.
-                    if visited.contains(&neighbor) {
-                        continue;
-                    }
+    /// ###### Takes a cheaper route to the server if the drops made the current one too expensive.
+    fn refresh_route(&mut self, server_id: ServerId) {
+        let (Some(route), Some(best_route)) = (self.routes.get(&server_id), self.find_route_to(server_id)) else {
+            return;
+        };
+        let cost = self.link_quality.route_cost(route);
+        let best_cost = self.link_quality.route_cost(&best_route);
+        if best_cost < cost {
+            info!("Client {}: Route to server {} changed from {:?} (cost {:.2}) to {:?} (cost {:.2})", self.id, server_id, route, cost, best_route, best_cost);
+            self.routes.insert(server_id, best_route);
+            self.backup_routes_to_repair.insert(server_id);
+        }
+    }
 
-                    // Skip servers and clients in the search.
-                    if neighbor != server_id {
-                        match self.nodes.get(&neighbor) {
-                            Some(NodeType::Server) | Some(NodeType::Client) => continue,
-                            _ => {},
-                        }
-                    }
+    /// ###### Finds the route to the server that shares no drone with the primary one.
+    /// It is used as soon as the primary route breaks, without waiting for a new search.
+    fn find_backup_route(&self, server_id: ServerId, primary: &[NodeId]) -> Option<Vec<NodeId>> {
+        let can_cross = |node_id: NodeId| !matches!(self.nodes.get(&node_id), Some(NodeType::Server) | Some(NodeType::Client));
+        self.link_quality.disjoint_route(self.id, server_id, &self.topology, can_cross, primary)
+    }
 
-                    let mut new_path = path.clone();
-                    new_path.push(neighbor); // Extend the path to include the neighbor.
-                    queue.push_back((neighbor, new_path)); // Add the neighbor to the queue.
+    /// ###### Computes again the backup routes that a new primary route or a crash made invalid.
+    /// Called after each handled packet or command, so that the failover itself does not wait for it.
+    fn repair_backup_routes(&mut self) {
+        for server_id in std::mem::take(&mut self.backup_routes_to_repair) {
+            let backup = match self.routes.get(&server_id) {
+                Some(primary) if !primary.is_empty() => self.find_backup_route(server_id, primary),
+                _ => None,
+            };
+            match backup {
+                Some(backup) => {
+                    debug!("Client {}: Backup route to server {}: {:?}", self.id, server_id, backup);
+                    self.backup_routes.insert(server_id, backup);
+                }
+                None => {
+                    debug!("Client {}: No backup route to server {}", self.id, server_id);
+                    self.backup_routes.remove(&server_id);
                 }
             }
         }
-        None    // Return None if no path to the server is found.
     }
 
     /// ###### Updates the message route and resends the fragment if possible.
@@ -232,12 +282,13 @@ impl PacketHandler for ChatClientDanylo {
 
     /// ###### Updates the route for the message with the specified session ID.
     /// If a new route is found, it updates the message with the new route.
-    /// If no route is found, it returns an error message.
+    /// If no route is found, it returns an error message and the message keeps waiting for its timers.
     fn update_message_route(&mut self, session_id: &SessionId) -> Result<(), String> {
         let message = self.messages_to_send.get_mut(session_id).unwrap();
-        let dest_id = message.get_route().last().unwrap();
+        let dest_id = message.destination();
 
-        if let Some(new_route) = self.routes.get(dest_id) {
+        // A route broken by a crash, without a backup, stays empty until a new one is found.
+        if let Some(new_route) = self.routes.get(&dest_id).filter(|route| !route.is_empty()) {
             message.update_route(new_route.clone());
             Ok(())
         } else {
@@ -245,23 +296,64 @@ impl PacketHandler for ChatClientDanylo {
         }
     }
 
+    /// ###### Resends the fragments whose acknowledgment is overdue.
+    /// The drone or the ACK may have been lost without a NACK, the wait doubles at each retransmission.
+    /// A session whose fragment was sent `MAX_SEND_ATTEMPTS` times is given up.
+    fn retransmit_timed_out_fragments(&mut self) {
+        let now = Instant::now();
+        let session_ids: Vec<SessionId> = self.messages_to_send.keys().copied().collect();
+        for session_id in session_ids {
+            let Some(message) = self.messages_to_send.get_mut(&session_id) else {
+                continue;
+            };
+            let timed_out = message.take_timed_out(now);
+            if message.is_failed() {
+                self.give_up_session(session_id);
+                continue;
+            }
+            for fragment_index in timed_out {
+                debug!("Client {}: No ACK for fragment {} of session {}, resending it", self.id, fragment_index, session_id);
+                self.send_event(ClientEvent::FragmentRetransmitted(self.id, session_id));
+                self.update_message_route_and_resend(fragment_index, session_id);
+            }
+        }
+    }
+
+    /// ###### Forgets the message of the session and reports it to the controller.
+    fn give_up_session(&mut self, session_id: SessionId) {
+        let Some(message) = self.messages_to_send.remove(&session_id) else {
+            return;
+        };
+        self.drops_counter.remove(&session_id);
+        let server_id = message.destination();
+        warn!("Client {}: Session {} to server {} given up, a fragment was sent {} times without ACK", self.id, session_id, server_id, MAX_SEND_ATTEMPTS);
+        self.send_event(ClientEvent::SessionFailed(FailedSession { node_id: self.id, session_id, destination: server_id }));
+    }
+
     /// ###### Handles received message fragment.
-    /// Adds the fragment to the collection for the session and checks if it is the last fragment.
-    /// If it is the last fragment, reassembles the message and processes the server response.
+    /// Adds the fragment to the message of the session, the fragments can arrive in any order.
+    /// Once every fragment has arrived, deserializes the message and processes the server response.
     fn handle_fragment(&mut self, fragment: Fragment, session_id: SessionId, server_id: ServerId) {
         debug!("Client {}: Handling fragment for session {}: {:?}", self.id, session_id, fragment);
 
-        // Retrieve or create a vector to store fragments for the session.
-        let fragments = self.fragments_to_reassemble.entry(session_id).or_insert_with(Vec::new);
-
-        // Add the current fragment to the collection.
-        fragments.push(fragment.clone());
+        // Forget the messages whose fragments stopped arriving.
+        for (server_id, session_id) in self.reassembler.discard_expired() {
+            warn!("Client {}: Incomplete message {} from server {} discarded", self.id, session_id, server_id);
+        }
 
-        // Check if the current fragment is the last one in the sequence.
-        if fragment.fragment_index == fragment.total_n_fragments - 1 {
-            // Reassemble the fragments into a complete message and process it.
-            let message = self.reassemble(session_id);
-            self.handle_server_response(message, server_id);
+        match self.reassembler.add_fragment(server_id, session_id, &fragment) {
+            Ok(Some(data)) => {
+                let response = match fragmentation::deserialize_message(&data) {
+                    Ok(response) => Some(response),
+                    Err(err) => {
+                        error!("Client {}: Failed to read the message {} from server {}: {}", self.id, session_id, server_id, err);
+                        None
+                    }
+                };
+                self.handle_server_response(response, server_id);
+            }
+            Ok(None) => {}
+            Err(err) => error!("Client {}: Message {} from server {} dropped: {}", self.id, session_id, server_id, err),
         }
     }
 
@@ -327,10 +419,15 @@ impl PacketHandler for ChatClientDanylo {
     /// If there are queries waiting for the route to the server, it resends them.
     fn update_routes_and_servers(&mut self, path: &[Node]) {
         if let Some((id, NodeType::Server)) = path.last() {
+            // The new path may give a backup route, or the primary route may change.
+            self.backup_routes_to_repair.insert(*id);
+
+            let route: Vec<NodeId> = path.iter().map(|entry| entry.0).collect();
+            let cost = self.link_quality.route_cost(&route);
             if self
                 .routes
                 .get(id)
-                .map_or(true, |prev_path| prev_path.len() > path.len())
+                .map_or(true, |prev_path| self.link_quality.route_cost(prev_path) > cost)
             {
                 let mut ask_type = false;
 
@@ -340,11 +437,8 @@ impl PacketHandler for ChatClientDanylo {
                     ask_type = true;
                 }
 
-                // Update the routing table with the new, shorter path.
-                self.routes.insert(
-                    *id,
-                    path.iter().map(|entry| entry.0.clone()).collect(),
-                );
+                // Update the routing table with the new, cheaper path.
+                self.routes.insert(*id, route);
                 info!("Client {}: Updated route to server {}: {:?}", self.id, id, path);
 
                 // Request the server type if it is undefined and the server is new.
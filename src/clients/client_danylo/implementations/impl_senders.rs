@@ -71,6 +71,9 @@ impl Senders for ChatClientDanylo {
             ClientEvent::ChatClientData(_, _, _) => "ChatClientData",
             ClientEvent::CallTechniciansToFixDrone(_, _) => "CallTechniciansToFixDrone",
             ClientEvent::ControllerShortcut(_) => "ControllerShortcut",
+            ClientEvent::FragmentRetransmitted(_, _) => "FragmentRetransmitted",
+            ClientEvent::QueryAnswered(_, _, _) => "QueryAnswered",
+            ClientEvent::SessionFailed(_) => "SessionFailed",
             _ => "Unknown",
         };
 
@@ -88,8 +91,12 @@ impl Senders for ChatClientDanylo {
         let message = self.messages_to_send.get(&session_id).unwrap();
         let packet = message.get_fragment_packet(fragment_index as usize).unwrap();
         match self.send_to_next_hop(packet) {
-            Ok(_) =>
-                info!("Client {}: Resent fragment {} for session {}", self.id, fragment_index, session_id),
+            Ok(_) => {
+                info!("Client {}: Resent fragment {} for session {}", self.id, fragment_index, session_id);
+                if let Some(message) = self.messages_to_send.get_mut(&session_id) {
+                    message.start_timer(fragment_index);
+                }
+            }
             Err(err) =>
                 error!("Client {}: Failed to resend fragment {} for session {}: {}", self.id, fragment_index, session_id, err),
         }
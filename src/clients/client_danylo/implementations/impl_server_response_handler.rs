@@ -1,7 +1,7 @@
 use log::{debug, error, info};
-use crate::general_use::{ClientId, DataScope, Message, Response, ServerId, ServerType, Speaker::HimOrHer};
+use crate::general_use::{ClientEvent, ClientId, DataScope, Message, Response, ServerId, ServerType, Speaker::HimOrHer};
 use crate::ui_traits::Monitoring;
-use super::{ServerResponseHandler, ChatClientDanylo, CommandHandler};
+use super::{ServerResponseHandler, ChatClientDanylo, CommandHandler, Senders};
 
 impl ServerResponseHandler for ChatClientDanylo {
     /// ###### Handles the server response.
@@ -10,6 +10,9 @@ impl ServerResponseHandler for ChatClientDanylo {
         debug!("Client {}: Handling response from server {}: {:?}", self.id, server_id, response);
 
         if let Some(response) = response {
+            if let Some((query_kind, latency)) = self.pending_queries.response_received(server_id, &response) {
+                self.send_event(ClientEvent::QueryAnswered(self.id, query_kind, latency));
+            }
             match response {
                 Response::ServerType(server_type) => {
                     self.handle_server_type(server_id, server_type);
@@ -27,8 +30,11 @@ impl ServerResponseHandler for ChatClientDanylo {
                     self.handle_message(message, server_id);
                     self.send_display_data(DataScope::UpdateSelf);
                 }
-                Response::Err(error) =>
-                    error!("Client {}: Error received from server {}: {:?}", self.id, server_id, error),
+                Response::Err(error) => {
+                    error!("Client {}: Error received from server {}: {}", self.id, server_id, error);
+                    self.last_server_error = Some((server_id, error));
+                    self.send_display_data(DataScope::UpdateSelf);
+                }
                 _ => {}
             }
         }
@@ -1,7 +1,6 @@
 mod impl_packet_handler;
 mod impl_monitoring;
 mod impl_command_handler;
-mod impl_reassembler;
 mod impl_generator_id;
 mod impl_senders;
 mod impl_server_response_handler;
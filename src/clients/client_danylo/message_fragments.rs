@@ -1,58 +1,41 @@
+use std::time::Instant;
 use serde::Serialize;
 
 use wg_2024::{
     network::{NodeId, SourceRoutingHeader},
-    packet::{Fragment, Packet, PacketType},
+    packet::{Packet, PacketType},
 };
 
+use crate::fragmentation::{self, OutgoingMessage};
+use crate::general_use::FragmentIndex;
+
 #[derive(Clone, Debug)]
 /// ###### Represents a message that is fragmented into smaller pieces for transmission.
 pub struct MessageFragments {
-    fragments: Vec<Fragment>,
+    message: OutgoingMessage,
     session_id: u64,
     route: Vec<NodeId>,
 }
 
 impl MessageFragments {
-    /// ###### Creates a new `MessageFragments` with the given session ID and route.
-    pub fn new(session_id: u64, route: Vec<NodeId>) -> MessageFragments {
-        Self {
-            fragments: Vec::new(),
-            session_id,
-            route,
-        }
-    }
-
-    /// ###### Serializes the provided data and splits it into smaller fragments for sending.
-    pub fn create_message_of<T: Serialize>(&mut self, data: T) -> bool {
-        let serialized_message = match serde_json::to_string(&data) {
-            Ok(string) => string,
-            Err(_) => return false,
+    /// ###### Serializes the provided data and splits it into fragments to be sent along the route.
+    /// At most `window` fragments wait for their acknowledgment at the same time.
+    pub fn new<T: Serialize>(session_id: u64, route: Vec<NodeId>, data: T, window: usize) -> Result<MessageFragments, String> {
+        let Some(&destination) = route.last() else {
+            return Err("Empty route".to_string());
         };
+        let fragments = fragmentation::serialize_to_fragments(&data)?;
 
-        self.fragments = self.fragment(&serialized_message);
-        true
-    }
-
-    /// ###### Splits a serialized message into fragments of a fixed size.
-    pub fn fragment(&mut self, serialized_msg: &str) -> Vec<Fragment> {
-        let serialized_msg_in_bytes = serialized_msg.as_bytes();
-        let length_response = serialized_msg_in_bytes.len();
-
-        let n_fragments = (length_response + 127) / 128;
-        (0..n_fragments)
-            .map(|i| {
-                let start = i * 128;
-                let end = ((i + 1) * 128).min(length_response);
-                let fragment_data = &serialized_msg[start..end];
-                Fragment::from_string(i as u64, n_fragments as u64, fragment_data.to_string())
-            })
-            .collect()
+        Ok(Self {
+            message: OutgoingMessage::new(destination, fragments, window),
+            session_id,
+            route,
+        })
     }
 
     /// ###### Retrieves the packet for the specified fragment index.
     pub fn get_fragment_packet(&self, fragment_index: usize) -> Option<Packet> {
-        if let Some(fragment) = self.fragments.get(fragment_index).cloned() {
+        if let Some(fragment) = self.message.fragment(fragment_index as FragmentIndex).cloned() {
             let hops = self.route.clone();
             let routing_header = SourceRoutingHeader {
                 hop_index: 0,
@@ -71,9 +54,44 @@ impl MessageFragments {
         }
     }
 
-    /// ###### Retrieves the route for the message fragments.
-    pub fn get_route(&self) -> &Vec<NodeId> {
-        &self.route
+    /// ###### Retrieves the packets of the fragments that entered the send window and were never sent.
+    /// From now on they count as sent.
+    pub fn take_sendable_packets(&mut self) -> Vec<Packet> {
+        self.message.take_sendable()
+            .into_iter()
+            .filter_map(|fragment_index| self.get_fragment_packet(fragment_index as usize))
+            .collect()
+    }
+
+    /// ###### Restarts the timer of the fragment, after it was sent again.
+    pub fn start_timer(&mut self, fragment_index: FragmentIndex) {
+        self.message.start_timer(fragment_index);
+    }
+
+    /// ###### Retrieves the fragments whose acknowledgment is overdue, their backoff doubles.
+    pub fn take_timed_out(&mut self, now: Instant) -> Vec<FragmentIndex> {
+        self.message.take_timed_out(now)
+    }
+
+    /// ###### Checks if a fragment was sent too many times without acknowledgment.
+    pub fn is_failed(&self) -> bool {
+        self.message.is_failed()
+    }
+
+    /// ###### Marks the fragment as received by the server.
+    /// Returns true once every fragment of the message is acknowledged.
+    pub fn ack(&mut self, fragment_index: FragmentIndex) -> bool {
+        self.message.ack(fragment_index)
+    }
+
+    /// ###### Checks if the fragment was already acknowledged.
+    pub fn is_acked(&self, fragment_index: FragmentIndex) -> bool {
+        self.message.is_acked(fragment_index)
+    }
+
+    /// ###### Retrieves the server the message is sent to, it does not change with the route.
+    pub fn destination(&self) -> NodeId {
+        self.message.destination()
     }
 
     /// ###### Updates the route for the message fragments.
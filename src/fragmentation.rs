@@ -0,0 +1,608 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Fragment, FRAGMENT_DSIZE};
+use crate::general_use::{FragmentIndex, SessionId};
+
+/// Longest query accepted by the servers, in fragments.
+pub const MAX_QUERY_FRAGMENTS: u64 = 1024;
+
+/// Longest response accepted by the clients, in fragments. The media can be big.
+pub const MAX_RESPONSE_FRAGMENTS: u64 = 1 << 17;
+
+/// Messages whose fragments stop arriving for this long are discarded.
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fragments of a message that can wait for their ack at the same time, when `[send_window]` does not say otherwise.
+pub const DEFAULT_SEND_WINDOW: usize = 8;
+
+/// The throughput shown by the monitoring counts the acks of this last period.
+pub const THROUGHPUT_PERIOD: Duration = Duration::from_secs(5);
+
+/// First wait for the ack of a fragment before sending it again, doubled at each retransmission.
+pub const RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The wait for an ack does not grow beyond this.
+pub const MAX_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(16);
+
+/// Sends of a fragment without an ack, after which its message is given up.
+pub const MAX_SEND_ATTEMPTS: u32 = 6;
+
+/// How often the nodes look for the acks that are overdue.
+pub const RETRANSMISSION_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+//FRAGMENTER
+
+/// Number of fragments of a message of `length` bytes. An empty message still takes one fragment.
+pub fn n_fragments(length: usize) -> u64 {
+    length.div_ceil(FRAGMENT_DSIZE).max(1) as u64
+}
+
+/// Splits the bytes in fragments, every fragment but the last is full.
+pub fn fragment_bytes(bytes: &[u8]) -> Vec<Fragment> {
+    let total_n_fragments = n_fragments(bytes.len());
+    (0..total_n_fragments)
+        .map(|fragment_index| {
+            let start = fragment_index as usize * FRAGMENT_DSIZE;
+            let end = (start + FRAGMENT_DSIZE).min(bytes.len());
+            let mut data = [0; FRAGMENT_DSIZE];
+            data[..end - start].copy_from_slice(&bytes[start..end]);
+            Fragment {
+                fragment_index,
+                total_n_fragments,
+                length: (end - start) as u8,
+                data,
+            }
+        })
+        .collect()
+}
+
+/// Serializes the message as json and splits it in fragments.
+pub fn serialize_to_fragments<T: Serialize>(message: &T) -> Result<Vec<Fragment>, String> {
+    let serialized_message = serde_json::to_vec(message)
+        .map_err(|e| format!("Unable to serialize the message: {}", e))?;
+    Ok(fragment_bytes(&serialized_message))
+}
+
+/// Inverse of `serialize_to_fragments`, for the bytes given by the `Reassembler`.
+pub fn deserialize_message<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    let serialized_message = std::str::from_utf8(bytes)
+        .map_err(|e| format!("Invalid UTF-8 sequence: {}", e))?;
+    serde_json::from_str(serialized_message)
+        .map_err(|e| format!("Deserialization failed: {}", e))
+}
+
+//REASSEMBLER
+
+/// Why a fragment cannot be part of its message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    NoFragments,
+    TooManyFragments { total: u64, max: u64 },
+    IndexOutOfRange { index: u64, total: u64 },
+    WrongLength { index: u64, length: u8 },
+    TotalChanged { expected: u64, found: u64 },
+}
+
+impl Display for FragmentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FragmentError::NoFragments => write!(f, "the message has no fragments"),
+            FragmentError::TooManyFragments { total, max } => write!(f, "the message has {} fragments, at most {} are accepted", total, max),
+            FragmentError::IndexOutOfRange { index, total } => write!(f, "fragment {} of a message of {} fragments", index, total),
+            FragmentError::WrongLength { index, length } => write!(f, "fragment {} has length {}", index, length),
+            FragmentError::TotalChanged { expected, found } => write!(f, "fragment of a message of {} fragments, expected {}", found, expected),
+        }
+    }
+}
+
+/// Message being reassembled. The fragments can arrive in any order, the duplicates are ignored.
+/// Only the fragments that arrived are stored, so a forged `total_n_fragments` costs no memory up front.
+#[derive(Debug, Clone)]
+pub struct ReassemblingMessage {
+    total_n_fragments: u64,
+    fragments: HashMap<u64, Vec<u8>>,   // Data of the fragments that arrived, by index
+    last_update: Instant,
+}
+
+impl ReassemblingMessage {
+    pub fn new(total_n_fragments: u64, max_fragments: u64) -> Result<Self, FragmentError> {
+        if total_n_fragments == 0 {
+            return Err(FragmentError::NoFragments);
+        }
+        if total_n_fragments > max_fragments {
+            return Err(FragmentError::TooManyFragments { total: total_n_fragments, max: max_fragments });
+        }
+        Ok(Self {
+            total_n_fragments,
+            fragments: HashMap::new(),
+            last_update: Instant::now(),
+        })
+    }
+
+    pub fn total_n_fragments(&self) -> u64 {
+        self.total_n_fragments
+    }
+
+    /// Adds the fragment to the message, returns true once every fragment has arrived.
+    /// Every fragment but the last must be full.
+    pub fn add_fragment(&mut self, fragment: &Fragment) -> Result<bool, FragmentError> {
+        let total = self.total_n_fragments();
+        if fragment.total_n_fragments != total {
+            return Err(FragmentError::TotalChanged { expected: total, found: fragment.total_n_fragments });
+        }
+        if fragment.fragment_index >= total {
+            return Err(FragmentError::IndexOutOfRange { index: fragment.fragment_index, total });
+        }
+        let is_last = fragment.fragment_index == total - 1;
+        let length = fragment.length as usize;
+        if length > FRAGMENT_DSIZE || (!is_last && length != FRAGMENT_DSIZE) {
+            return Err(FragmentError::WrongLength { index: fragment.fragment_index, length: fragment.length });
+        }
+
+        self.last_update = Instant::now();
+        self.fragments
+            .entry(fragment.fragment_index)
+            .or_insert_with(|| fragment.data[..length].to_vec());
+        Ok(self.is_complete())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.fragments.len() as u64 == self.total_n_fragments
+    }
+
+    /// No fragment arrived for `REASSEMBLY_TIMEOUT`.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.last_update) > REASSEMBLY_TIMEOUT
+    }
+
+    /// The bytes of the message, without the padding of the last fragment.
+    pub fn into_data(mut self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.fragments.len() * FRAGMENT_DSIZE);
+        for index in 0..self.total_n_fragments {
+            if let Some(fragment_data) = self.fragments.remove(&index) {
+                data.extend_from_slice(&fragment_data);
+            }
+        }
+        data
+    }
+}
+
+/// Messages being reassembled, by sender and session.
+/// The completed and the refused messages are remembered for `REASSEMBLY_TIMEOUT`, so that the late
+/// fragments of their session are not taken for a new message and a refused message gives a single error.
+#[derive(Debug, Clone)]
+pub struct Reassembler {
+    max_fragments: u64,
+    messages: HashMap<(NodeId, SessionId), ReassemblingMessage>,
+    completed: HashMap<(NodeId, SessionId), Instant>,          // Completed or refused
+}
+
+impl Reassembler {
+    pub fn new(max_fragments: u64) -> Self {
+        Self { max_fragments, messages: HashMap::new(), completed: HashMap::new() }
+    }
+
+    /// Adds the fragment to its message, returns the bytes of the message once every fragment has arrived.
+    /// A message with a wrong fragment is dropped as a whole, the error is returned only for the first wrong
+    /// fragment and the sender has to send the message again in a new session.
+    pub fn add_fragment(&mut self, src_id: NodeId, session_id: SessionId, fragment: &Fragment) -> Result<Option<Vec<u8>>, FragmentError> {
+        let key = (src_id, session_id);
+        if self.completed.contains_key(&key) {
+            return Ok(None);
+        }
+
+        let result = match self.messages.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut().add_fragment(fragment),
+            Entry::Vacant(entry) => ReassemblingMessage::new(fragment.total_n_fragments, self.max_fragments)
+                .and_then(|message| entry.insert(message).add_fragment(fragment)),
+        };
+
+        match result {
+            Ok(true) => {
+                self.completed.insert(key, Instant::now());
+                Ok(self.messages.remove(&key).map(ReassemblingMessage::into_data))
+            }
+            Ok(false) => Ok(None),
+            Err(e) => {
+                self.completed.insert(key, Instant::now());
+                self.messages.remove(&key);
+                Err(e)
+            }
+        }
+    }
+
+    /// Forgets the messages whose fragments stopped arriving, returns their sender and session.
+    pub fn discard_expired(&mut self) -> Vec<(NodeId, SessionId)> {
+        let now = Instant::now();
+        self.completed.retain(|_, completion| now.duration_since(*completion) <= REASSEMBLY_TIMEOUT);
+
+        let expired: Vec<(NodeId, SessionId)> = self.messages.iter()
+            .filter(|(_, message)| message.is_expired(now))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &expired {
+            self.messages.remove(key);
+        }
+        expired
+    }
+}
+
+//RETRANSMISSION TRACKER
+
+/// Fragments of a message being sent, kept until the destination acks each of them.
+/// Only the fragments of the send window, that starts at the first fragment not acked, can be sent:
+/// the window slides as the acks arrive, and a lost fragment is sent again alone.
+/// Every sent fragment has a timer: when its ack is overdue it is sent again, waiting twice as long each time.
+#[derive(Debug, Clone)]
+pub struct OutgoingMessage {
+    destination: NodeId,
+    fragments: Vec<Fragment>,
+    acked: Vec<bool>,                   // One for each fragment index
+    n_acked: usize,
+    window: usize,
+    base: usize,                        // First fragment not acked
+    next_to_send: usize,                // First fragment never sent
+    ack_due: Vec<Option<Instant>>,      // When the ack of each fragment is overdue, None if not sent or parked
+    timeouts: Vec<u32>,                 // Acks of each fragment that never arrived
+}
+
+impl OutgoingMessage {
+    pub fn new(destination: NodeId, fragments: Vec<Fragment>, window: usize) -> Self {
+        let acked = vec![false; fragments.len()];
+        let ack_due = vec![None; fragments.len()];
+        let timeouts = vec![0; fragments.len()];
+        Self { destination, fragments, acked, n_acked: 0, window: window.max(1), base: 0, next_to_send: 0, ack_due, timeouts }
+    }
+
+    pub fn destination(&self) -> NodeId {
+        self.destination
+    }
+
+    /// The fragment to send again.
+    pub fn fragment(&self, fragment_index: FragmentIndex) -> Option<&Fragment> {
+        self.fragments.get(fragment_index as usize)
+    }
+
+    /// The fragments that entered the send window and were never sent. From now on they count as sent,
+    /// and their timers run.
+    pub fn take_sendable(&mut self) -> Vec<FragmentIndex> {
+        let end = (self.base + self.window).min(self.fragments.len());
+        let sendable: Vec<FragmentIndex> = (self.next_to_send..end).map(|fragment_index| fragment_index as FragmentIndex).collect();
+        self.next_to_send = self.next_to_send.max(end);
+        for fragment_index in &sendable {
+            self.start_timer(*fragment_index);
+        }
+        sendable
+    }
+
+    /// The fragment was sent again, its ack is awaited for the current backoff.
+    pub fn start_timer(&mut self, fragment_index: FragmentIndex) {
+        let backoff = self.backoff(fragment_index);
+        if let Some(ack_due) = self.ack_due.get_mut(fragment_index as usize) {
+            *ack_due = Some(Instant::now() + backoff);
+        }
+    }
+
+    /// The fragment is parked, no ack is awaited until it is sent again.
+    pub fn stop_timer(&mut self, fragment_index: FragmentIndex) {
+        if let Some(ack_due) = self.ack_due.get_mut(fragment_index as usize) {
+            *ack_due = None;
+        }
+    }
+
+    /// The fragments whose ack is overdue, to be sent again: their backoff doubles and their timer restarts.
+    pub fn take_timed_out(&mut self, now: Instant) -> Vec<FragmentIndex> {
+        let timed_out: Vec<FragmentIndex> = (0..self.fragments.len())
+            .filter(|&index| !self.acked[index] && self.ack_due[index].is_some_and(|ack_due| now >= ack_due))
+            .map(|index| index as FragmentIndex)
+            .collect();
+        for fragment_index in &timed_out {
+            self.timeouts[*fragment_index as usize] += 1;
+            self.ack_due[*fragment_index as usize] = Some(now + self.backoff(*fragment_index));
+        }
+        timed_out
+    }
+
+    /// A fragment was sent `MAX_SEND_ATTEMPTS` times and never acked, the message is given up.
+    pub fn is_failed(&self) -> bool {
+        self.timeouts.iter().any(|timeouts| *timeouts >= MAX_SEND_ATTEMPTS)
+    }
+
+    /// Wait for the next ack of the fragment, doubled by each of its timeouts.
+    fn backoff(&self, fragment_index: FragmentIndex) -> Duration {
+        let timeouts = self.timeouts.get(fragment_index as usize).copied().unwrap_or(0);
+        RETRANSMISSION_TIMEOUT.saturating_mul(1 << timeouts.min(16)).min(MAX_RETRANSMISSION_TIMEOUT)
+    }
+
+    /// Marks the fragment as received by the destination, returns true once every fragment is acked.
+    pub fn ack(&mut self, fragment_index: FragmentIndex) -> bool {
+        if let Some(acked) = self.acked.get_mut(fragment_index as usize) {
+            if !*acked {
+                *acked = true;
+                self.n_acked += 1;
+            }
+        }
+        while self.base < self.acked.len() && self.acked[self.base] {
+            self.base += 1;
+        }
+        self.is_complete()
+    }
+
+    pub fn is_acked(&self, fragment_index: FragmentIndex) -> bool {
+        self.acked.get(fragment_index as usize).copied().unwrap_or(false)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.n_acked == self.fragments.len()
+    }
+
+    /// The fragments still waiting for their ack.
+    pub fn unacked(&self) -> impl Iterator<Item = &Fragment> + '_ {
+        self.fragments.iter().filter(|fragment| !self.is_acked(fragment.fragment_index))
+    }
+}
+
+/// Fragments acked per second over the last `THROUGHPUT_PERIOD`, the `throughput` of the monitoring data
+/// of the clients and the servers. It also falls while no ack arrives, so the nodes check it on every
+/// statistics tick and send their monitoring data again when it changed.
+#[derive(Debug, Clone, Default)]
+pub struct Throughput {
+    acks: VecDeque<Instant>,
+    shown_acks: usize,                  // Acks of the period when the throughput was last shown
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A fragment was acked for the first time.
+    pub fn record_ack(&mut self) {
+        let now = Instant::now();
+        self.acks.push_back(now);
+        self.forget_old_acks(now);
+    }
+
+    /// The throughput to show, it is remembered by `has_changed`.
+    pub fn fragments_per_second(&mut self) -> f64 {
+        self.forget_old_acks(Instant::now());
+        self.shown_acks = self.acks.len();
+        self.acks.len() as f64 / THROUGHPUT_PERIOD.as_secs_f64()
+    }
+
+    /// The throughput is not the one last shown.
+    pub fn has_changed(&mut self) -> bool {
+        self.forget_old_acks(Instant::now());
+        self.acks.len() != self.shown_acks
+    }
+
+    fn forget_old_acks(&mut self, now: Instant) {
+        while self.acks.front().is_some_and(|ack| now.duration_since(*ack) > THROUGHPUT_PERIOD) {
+            self.acks.pop_front();
+        }
+    }
+}
+
+/// The round trips go through every message length up to ten fragments, with the fragments shuffled
+/// and duplicated by a seeded rng: this exhaustive loop takes the place of property tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    fn fragment(fragment_index: u64, total_n_fragments: u64, length: u8) -> Fragment {
+        Fragment { fragment_index, total_n_fragments, length, data: [0; FRAGMENT_DSIZE] }
+    }
+
+    /// The fragments shuffled, with some of them sent twice.
+    fn shuffled_with_duplicates(fragments: Vec<Fragment>, rng: &mut StdRng) -> Vec<Fragment> {
+        let n_duplicates = rng.gen_range(0..=fragments.len());
+        let duplicates: Vec<Fragment> = fragments.choose_multiple(rng, n_duplicates).cloned().collect();
+        let mut fragments: Vec<Fragment> = fragments.into_iter().chain(duplicates).collect();
+        fragments.shuffle(rng);
+        fragments
+    }
+
+    /// Gives every fragment to a new reassembler, returns the message it completed.
+    fn reassemble(fragments: &[Fragment]) -> Vec<u8> {
+        let mut reassembler = Reassembler::new(MAX_RESPONSE_FRAGMENTS);
+        let mut messages = Vec::new();
+        for fragment in fragments {
+            if let Some(message) = reassembler.add_fragment(1, 7, fragment).expect("valid fragment") {
+                messages.push(message);
+            }
+        }
+        assert_eq!(messages.len(), 1, "the message must be completed exactly once");
+        messages.remove(0)
+    }
+
+    fn lengths() -> impl Iterator<Item = usize> {
+        (0..=10 * FRAGMENT_DSIZE + 1).chain([32, 64, 100].map(|k| k * FRAGMENT_DSIZE))
+    }
+
+    #[test]
+    fn fragments_give_back_the_bytes_of_every_length() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for length in lengths() {
+            let bytes: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            let fragments = fragment_bytes(&bytes);
+            assert_eq!(fragments.len() as u64, n_fragments(length));
+
+            let fragments = shuffled_with_duplicates(fragments, &mut rng);
+            assert_eq!(reassemble(&fragments), bytes, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn serialized_messages_give_back_the_message_of_every_length() {
+        let mut rng = StdRng::seed_from_u64(1);
+        // The quotes of the json string take two bytes
+        for length in lengths().filter(|&length| length >= 2) {
+            let message: String = (0..length - 2).map(|_| rng.gen_range('a'..='z')).collect();
+            let fragments = serialize_to_fragments(&message).unwrap();
+            assert_eq!(fragments.len() as u64, n_fragments(length));
+
+            let fragments = shuffled_with_duplicates(fragments, &mut rng);
+            let bytes = reassemble(&fragments);
+            assert_eq!(deserialize_message::<String>(&bytes).unwrap(), message, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn n_fragments_rounds_up() {
+        for (length, expected) in [(0, 1), (1, 1), (127, 1), (128, 1), (129, 2), (256, 2), (257, 3), (128 * 1000, 1000)] {
+            assert_eq!(n_fragments(length), expected, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn message_without_fragments_is_refused() {
+        let mut reassembler = Reassembler::new(4);
+        assert_eq!(reassembler.add_fragment(1, 7, &fragment(0, 0, 0)), Err(FragmentError::NoFragments));
+    }
+
+    #[test]
+    fn message_with_too_many_fragments_is_refused() {
+        let mut reassembler = Reassembler::new(4);
+        assert_eq!(reassembler.add_fragment(1, 7, &fragment(0, 5, 128)), Err(FragmentError::TooManyFragments { total: 5, max: 4 }));
+        assert_eq!(reassembler.add_fragment(1, 8, &fragment(0, 4, 128)), Ok(None));
+    }
+
+    #[test]
+    fn fragment_out_of_range_is_refused() {
+        let mut reassembler = Reassembler::new(4);
+        assert_eq!(reassembler.add_fragment(1, 7, &fragment(3, 3, 10)), Err(FragmentError::IndexOutOfRange { index: 3, total: 3 }));
+    }
+
+    #[test]
+    fn fragment_of_wrong_length_is_refused() {
+        let mut reassembler = Reassembler::new(4);
+        // Only the last fragment can be shorter, and no fragment is longer than FRAGMENT_DSIZE
+        assert_eq!(reassembler.add_fragment(1, 7, &fragment(0, 2, 10)), Err(FragmentError::WrongLength { index: 0, length: 10 }));
+        assert_eq!(reassembler.add_fragment(1, 8, &fragment(1, 2, 200)), Err(FragmentError::WrongLength { index: 1, length: 200 }));
+    }
+
+    #[test]
+    fn fragment_with_another_total_is_refused() {
+        let mut reassembler = Reassembler::new(4);
+        assert_eq!(reassembler.add_fragment(1, 7, &fragment(0, 3, 128)), Ok(None));
+        assert_eq!(reassembler.add_fragment(1, 7, &fragment(1, 4, 128)), Err(FragmentError::TotalChanged { expected: 3, found: 4 }));
+    }
+
+    #[test]
+    fn refused_message_gives_a_single_error() {
+        let mut reassembler = Reassembler::new(4);
+        assert!(reassembler.add_fragment(1, 7, &fragment(0, 2, 10)).is_err());
+        assert_eq!(reassembler.add_fragment(1, 7, &fragment(1, 2, 10)), Ok(None));
+        assert_eq!(reassembler.add_fragment(1, 7, &fragment(0, 2, 128)), Ok(None));
+
+        // The other sessions are not affected
+        assert_eq!(reassembler.add_fragment(1, 8, &fragment(0, 1, 10)), Ok(Some(vec![0; 10])));
+        assert_eq!(reassembler.add_fragment(2, 7, &fragment(0, 1, 10)), Ok(Some(vec![0; 10])));
+    }
+
+    #[test]
+    fn forged_total_stores_only_the_fragments_that_arrived() {
+        let mut message = ReassemblingMessage::new(MAX_RESPONSE_FRAGMENTS, MAX_RESPONSE_FRAGMENTS).expect("accepted total");
+        assert_eq!(message.fragments.capacity(), 0);
+        assert_eq!(message.add_fragment(&fragment(MAX_RESPONSE_FRAGMENTS - 1, MAX_RESPONSE_FRAGMENTS, 10)), Ok(false));
+        assert_eq!(message.add_fragment(&fragment(MAX_RESPONSE_FRAGMENTS - 1, MAX_RESPONSE_FRAGMENTS, 10)), Ok(false));
+        assert_eq!(message.fragments.len(), 1);
+        assert_eq!(message.fragments.values().map(Vec::len).sum::<usize>(), 10);
+    }
+
+    fn outgoing_message(n_fragments: u64, window: usize) -> OutgoingMessage {
+        let fragments = (0..n_fragments).map(|fragment_index| fragment(fragment_index, n_fragments, 128)).collect();
+        OutgoingMessage::new(3, fragments, window)
+    }
+
+    #[test]
+    fn window_slides_with_the_acks() {
+        let mut message = outgoing_message(6, 3);
+        assert_eq!(message.take_sendable(), vec![0, 1, 2]);
+        assert_eq!(message.take_sendable(), Vec::<FragmentIndex>::new());
+
+        // The window starts at the first fragment not acked
+        assert!(!message.ack(1));
+        assert_eq!(message.take_sendable(), Vec::<FragmentIndex>::new());
+        assert!(!message.ack(0));
+        assert_eq!(message.take_sendable(), vec![3, 4]);
+        assert!(!message.ack(0));
+        assert_eq!(message.take_sendable(), Vec::<FragmentIndex>::new());
+
+        for fragment_index in [2, 3, 4] {
+            assert!(!message.ack(fragment_index));
+        }
+        assert_eq!(message.take_sendable(), vec![5]);
+        assert!(message.ack(5));
+        assert!(message.is_complete());
+        assert_eq!(message.unacked().count(), 0);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let mut message = outgoing_message(1, 1);
+        message.take_sendable();
+        let start = Instant::now();
+        assert_eq!(message.take_timed_out(start), Vec::<FragmentIndex>::new());
+
+        let mut now = start + RETRANSMISSION_TIMEOUT;
+        let mut waits = Vec::new();
+        for _ in 0..8 {
+            assert_eq!(message.take_timed_out(now), vec![0]);
+            let ack_due = message.ack_due[0].unwrap();
+            waits.push(ack_due - now);
+            // Not again before the new timer expires
+            assert_eq!(message.take_timed_out(ack_due - Duration::from_millis(1)), Vec::<FragmentIndex>::new());
+            now = ack_due;
+        }
+        let expected: Vec<Duration> = [2, 4, 8, 16, 16, 16, 16, 16].into_iter().map(Duration::from_secs).collect();
+        assert_eq!(waits, expected);
+        assert!(waits.iter().all(|wait| *wait <= MAX_RETRANSMISSION_TIMEOUT));
+    }
+
+    #[test]
+    fn message_fails_after_the_last_attempt() {
+        let mut message = outgoing_message(2, 2);
+        message.take_sendable();
+        message.ack(1);
+
+        let mut now = Instant::now();
+        for _ in 0..MAX_SEND_ATTEMPTS {
+            assert!(!message.is_failed());
+            now += MAX_RETRANSMISSION_TIMEOUT;
+            assert_eq!(message.take_timed_out(now), vec![0]);
+        }
+        assert!(message.is_failed());
+    }
+
+    #[test]
+    fn throughput_changes_with_the_acks() {
+        let mut throughput = Throughput::new();
+        assert!(!throughput.has_changed());
+        throughput.record_ack();
+        throughput.record_ack();
+        assert!(throughput.has_changed());
+        assert_eq!(throughput.fragments_per_second(), 2.0 / THROUGHPUT_PERIOD.as_secs_f64());
+        assert!(!throughput.has_changed());
+    }
+
+    #[test]
+    fn parked_and_acked_fragments_do_not_time_out() {
+        let mut message = outgoing_message(2, 2);
+        message.take_sendable();
+        message.stop_timer(0);
+        message.ack(1);
+        assert_eq!(message.take_timed_out(Instant::now() + MAX_RETRANSMISSION_TIMEOUT), Vec::<FragmentIndex>::new());
+
+        message.start_timer(0);
+        assert_eq!(message.take_timed_out(Instant::now() + MAX_RETRANSMISSION_TIMEOUT), vec![0]);
+    }
+}
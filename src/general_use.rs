@@ -1,5 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
 use crossbeam_channel::Sender;
 use serde::{Deserialize, Serialize};
 
@@ -58,11 +60,15 @@ pub struct DisplayDataWebBrowser {
     pub session_id: SessionId,
     pub connected_node_ids: HashSet<NodeId>,
     pub routing_table: HashMap<NodeId, Vec<NodeId>>,
+    pub route_costs: HashMap<NodeId, f64>,
+    pub send_window: usize,
+    pub throughput: f64,
     pub discovered_text_servers: HashSet<ServerId>,
     pub discovered_media_servers: HashSet<ServerId>,
     pub curr_received_file_list: Vec<String>,
     pub chosen_file_text: String,
     pub serialized_media: HashMap<MediaRef, String>,
+    pub last_server_error: Option<(ServerId, ServerError)>,
 }
 #[derive(Debug, Serialize)]
 pub struct DisplayDataSimulationController{
@@ -75,6 +81,49 @@ pub struct DisplayDataSimulationController{
     pub media_servers_data: HashMap<NodeId, DisplayDataMediaServer>,
     pub drones_data: HashMap<NodeId, DisplayDataDrone>,
     pub topology: HashMap<NodeId, (Vec<NodeId>, SpecificNodeType)>,
+    pub seed: u64,                      // Replay the run with --seed
+}
+
+///rolling metrics of the whole network, sent periodically by the controller
+#[derive(Debug, Clone, Serialize)]
+pub struct DisplayDataStatistics {
+    pub elapsed_ms: u64,
+    pub drones: HashMap<DroneId, DroneStatistics>,
+    pub retransmissions: Vec<SessionRetransmissions>,
+    pub latencies: HashMap<QueryKind, LatencyStatistics>,
+    pub failed_sessions: u64,           // Messages given up by their sender
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DroneStatistics {
+    pub forwarded: u64,                 // Every kind of packet
+    pub dropped: u64,                   // Only fragments can be dropped
+    pub observed_pdr: Option<f32>,      // None until the drone has seen a fragment
+    pub configured_pdr: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionRetransmissions {
+    pub node_id: NodeId,
+    pub session_id: SessionId,
+    pub count: u64,
+}
+
+/// Message given up by its sender, a fragment was sent `MAX_SEND_ATTEMPTS` times without an ack.
+/// Sent to the GUI as `{ "SessionFailed": ... }`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedSession {
+    pub node_id: NodeId,                // Sender of the message
+    pub session_id: SessionId,
+    pub destination: NodeId,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyStatistics {
+    pub count: u64,
+    pub average_ms: f64,                // Over the last samples only
+    pub min_ms: u64,
+    pub max_ms: u64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -98,6 +147,9 @@ pub struct DisplayDataChatClient {
 
     // Network
     pub routes: HashMap<ServerId, Vec<NodeId>>,
+    pub route_costs: HashMap<ServerId, f64>,
+    pub send_window: usize,
+    pub throughput: f64,
 
     // Connections
     pub neighbours: HashSet<NodeId>,
@@ -106,6 +158,9 @@ pub struct DisplayDataChatClient {
 
     // Chats
     pub chats: HashMap<ClientId, ChatHistory>,
+
+    // Last error answered by a server
+    pub last_server_error: Option<(ServerId, ServerError)>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -117,6 +172,9 @@ pub struct DisplayDataCommunicationServer{
     pub connected_node_ids: HashSet<NodeId>,
     pub known_clients: HashSet<NodeId>,
     pub routing_table: HashMap<NodeId, Vec<NodeId>>,
+    pub route_costs: HashMap<NodeId, f64>,
+    pub send_window: usize,
+    pub throughput: f64,
     pub registered_clients: Vec<NodeId>,
 }
 
@@ -129,6 +187,9 @@ pub struct DisplayDataMediaServer{
     pub connected_node_ids: HashSet<NodeId>,
     pub known_clients: HashSet<NodeId>,
     pub routing_table: HashMap<NodeId, Vec<NodeId>>,
+    pub route_costs: HashMap<NodeId, f64>,
+    pub send_window: usize,
+    pub throughput: f64,
     pub media: HashMap<String, String>,
 }
 
@@ -141,6 +202,9 @@ pub struct DisplayDataTextServer{
     pub connected_node_ids: HashSet<NodeId>,
     pub known_clients: HashSet<NodeId>,
     pub routing_table: HashMap<NodeId, Vec<NodeId>>,
+    pub route_costs: HashMap<NodeId, f64>,
+    pub send_window: usize,
+    pub throughput: f64,
     pub text_files: Vec<String>,
 }
 
@@ -159,6 +223,46 @@ pub enum TechnicalOperationOnDrone{
     DroneCrashed(DroneId),
     NotCrashed(DroneId),
     PdrChanged(DroneId, f32),
+    PdrNotChanged(DroneId, String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TopologyOperation{
+    DroneAdded(DroneId, DroneBrand),
+    DroneNotAdded(DroneId, TopologyChangeError),
+    LinkAdded(NodeId, NodeId),
+    LinkRemoved(NodeId, NodeId),
+    LinkNotChanged(NodeId, NodeId, TopologyChangeError),
+}
+
+///why a change of the topology asked at runtime was refused
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TopologyChangeError{
+    UnknownNode(NodeId),
+    NodeAlreadyExists(NodeId),
+    SameNode(NodeId),
+    AlreadyConnected(NodeId, NodeId),
+    NotConnected(NodeId, NodeId),
+    InvalidPdr(f32),
+    BrandNotInPool(DroneBrand),     // Forced away or excluded by [drone_brands]
+    InvalidTopology(String),        // A rule of the initialization file checker would be broken
+    CommandFailed(String),
+}
+
+impl Display for TopologyChangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TopologyChangeError::UnknownNode(id) => write!(f, "node {} is not in the network", id),
+            TopologyChangeError::NodeAlreadyExists(id) => write!(f, "node {} already exists", id),
+            TopologyChangeError::SameNode(id) => write!(f, "node {} cannot be connected to itself", id),
+            TopologyChangeError::AlreadyConnected(a, b) => write!(f, "nodes {} and {} are already connected", a, b),
+            TopologyChangeError::NotConnected(a, b) => write!(f, "nodes {} and {} are not connected", a, b),
+            TopologyChangeError::InvalidPdr(pdr) => write!(f, "PDR {} is not between 0 and 1", pdr),
+            TopologyChangeError::BrandNotInPool(brand) => write!(f, "drones of brand {:?} are not allowed by [drone_brands]", brand),
+            TopologyChangeError::InvalidTopology(reason) => write!(f, "{}", reason),
+            TopologyChangeError::CommandFailed(reason) => write!(f, "{}", reason),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -204,6 +308,10 @@ pub enum ServerEvent {
     // Node - the node that sent the event.
     CallTechniciansToFixDrone(DroneId, Node),
     ControllerShortcut(Packet),
+
+    //for the statistics
+    FragmentRetransmitted(InitiatorId, SessionId),
+    SessionFailed(FailedSession),
 }
 
 #[derive(Debug, Clone, PartialEq, Copy)]
@@ -253,6 +361,11 @@ pub enum ClientEvent {
     // Node - the node that sent the event.
     CallTechniciansToFixDrone(DroneId, Node),
     ControllerShortcut(Packet),
+
+    //for the statistics
+    FragmentRetransmitted(InitiatorId, SessionId),
+    QueryAnswered(InitiatorId, QueryKind, Duration),    // Time between the query and its complete response
+    SessionFailed(FailedSession),
 }
 
 //Queries (Client -> Server)
@@ -275,6 +388,49 @@ pub enum Query {
     AskMedia(String), // String is the reference found in the files
 }
 
+///Binary media file, base64 on the wire
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MediaFile {
+    pub mime: String,
+    pub size: usize,                    // Bytes before the encoding
+    pub base64: String,
+}
+
+impl MediaFile {
+    pub fn new(mime: &str, bytes: &[u8]) -> Self {
+        Self {
+            mime: mime.to_string(),
+            size: bytes.len(),
+            base64: BASE64_STANDARD.encode(bytes),
+        }
+    }
+
+    ///Usable as the source of an image or audio element
+    pub fn to_data_url(&self) -> String {
+        format!("data:{};base64,{}", self.mime, self.base64)
+    }
+}
+
+///Why a server could not answer a query
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum ServerError {
+    FileNotFound(FileRef),
+    MediaNotFound(MediaRef),
+    MalformedMessage(String),           // Fragments that cannot be reassembled
+    InvalidQuery(String),               // Reassembled message that is not a query
+}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::FileNotFound(file_ref) => write!(f, "file {} not found", file_ref),
+            ServerError::MediaNotFound(media_ref) => write!(f, "media {} not found", media_ref),
+            ServerError::MalformedMessage(reason) => write!(f, "malformed message: {}", reason),
+            ServerError::InvalidQuery(reason) => write!(f, "invalid query: {}", reason),
+        }
+    }
+}
+
 //Server -> Client
 #[derive(Deserialize, Serialize, Debug)]
 pub enum Response {
@@ -292,14 +448,82 @@ pub enum Response {
     File(String),
     //(Media)
     Media(String),
+    MediaWithMeta(MediaFile),
 
     //General Error
-    Err(String),
+    Err(ServerError),
 
     //Ack for flood response
     FloodAck(FragmentIndex),
 }
 
+///Queries waiting for a response, used for the latency statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum QueryKind {
+    AskType,
+    RegisterClient,
+    AskListClients,
+    AskListFiles,
+    AskFile,
+    AskMedia,
+}
+
+impl QueryKind {
+    /// Kind of the query, None if the server does not answer it.
+    pub fn of_query(query: &Query) -> Option<Self> {
+        match query {
+            Query::AskType => Some(QueryKind::AskType),
+            Query::RegisterClient(_) => Some(QueryKind::RegisterClient),
+            Query::AskListClients => Some(QueryKind::AskListClients),
+            Query::AskListFiles => Some(QueryKind::AskListFiles),
+            Query::AskFile(_) => Some(QueryKind::AskFile),
+            Query::AskMedia(_) => Some(QueryKind::AskMedia),
+            Query::UnregisterClient(_) | Query::SendMessage(_) => None,
+        }
+    }
+
+    /// Kind of the query answered by the response, None if it is not an answer.
+    pub fn answered_by(response: &Response) -> Option<Self> {
+        match response {
+            Response::ServerType(_) => Some(QueryKind::AskType),
+            Response::ClientRegistered => Some(QueryKind::RegisterClient),
+            Response::ListClients(_) => Some(QueryKind::AskListClients),
+            Response::ListFiles(_) => Some(QueryKind::AskListFiles),
+            Response::File(_) => Some(QueryKind::AskFile),
+            Response::Media(_) | Response::MediaWithMeta(_) => Some(QueryKind::AskMedia),
+            Response::MessageReceived(_) | Response::Err(_) | Response::FloodAck(_) => None,
+        }
+    }
+}
+
+const MAX_PENDING_QUERIES: usize = 32;
+
+///Sending times of the queries still without a response, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct PendingQueries {
+    sent_at: HashMap<(ServerId, QueryKind), VecDeque<Instant>>,
+}
+
+impl PendingQueries {
+    pub fn query_sent(&mut self, server_id: ServerId, query: &Query) {
+        if let Some(kind) = QueryKind::of_query(query) {
+            let pending = self.sent_at.entry((server_id, kind)).or_default();
+            // Queries that never got a response are forgotten
+            if pending.len() == MAX_PENDING_QUERIES {
+                pending.pop_front();
+            }
+            pending.push_back(Instant::now());
+        }
+    }
+
+    /// Returns the kind of the oldest query answered by the response and its latency.
+    pub fn response_received(&mut self, server_id: ServerId, response: &Response) -> Option<(QueryKind, Duration)> {
+        let kind = QueryKind::answered_by(response)?;
+        let sent_at = self.sent_at.get_mut(&(server_id, kind))?.pop_front()?;
+        Some((kind, sent_at.elapsed()))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Copy)]
 pub enum SpecificNodeType{
     WebBrowser,
@@ -1,7 +1,168 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
 use wg_2024::config::{Client, Config, Drone, Server};
+use wg_2024::network::NodeId;
 use crate::general_use::DroneId;
 
+/// Entry of the initialization file, `index` is the position of the entry in its `[[drone]]`,
+/// `[[client]]` or `[[server]]` array, starting from 1 like a reader counts them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeEntry {
+    Drone { index: usize, id: NodeId },
+    Client { index: usize, id: NodeId },
+    Server { index: usize, id: NodeId },
+}
+
+impl NodeEntry {
+    pub fn id(&self) -> NodeId {
+        match self {
+            NodeEntry::Drone { id, .. } | NodeEntry::Client { id, .. } | NodeEntry::Server { id, .. } => *id,
+        }
+    }
+}
+
+impl Display for NodeEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeEntry::Drone { index, id } => write!(f, "[[drone]] #{} (id {})", index, id),
+            NodeEntry::Client { index, id } => write!(f, "[[client]] #{} (id {})", index, id),
+            NodeEntry::Server { index, id } => write!(f, "[[server]] #{} (id {})", index, id),
+        }
+    }
+}
+
+/// One rule of the initialization file broken by the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitializationError {
+    DuplicateId { entry: NodeEntry, first: NodeEntry },
+    SelfLoop { entry: NodeEntry },
+    DuplicateConnection { entry: NodeEntry, neighbour: NodeId },
+    PdrOutOfRange { entry: NodeEntry, pdr: f32 },
+    ClientDegree { entry: NodeEntry, degree: usize },        // Clients have one or two drones
+    ServerDegree { entry: NodeEntry, degree: usize },        // Servers have at least two drones
+    NotADrone { entry: NodeEntry, neighbour: NodeId },       // Clients and servers are connected only to drones
+    NotBidirectional { entry: NodeEntry, a: DroneId, b: DroneId },
+    DroneGraphDisconnected { components: Vec<Vec<DroneId>> },
+}
+
+impl InitializationError {
+    /// Returns the entry of the file to fix, if the error is about a single one.
+    pub fn entry(&self) -> Option<NodeEntry> {
+        match self {
+            InitializationError::DuplicateId { entry, .. }
+            | InitializationError::SelfLoop { entry }
+            | InitializationError::DuplicateConnection { entry, .. }
+            | InitializationError::PdrOutOfRange { entry, .. }
+            | InitializationError::ClientDegree { entry, .. }
+            | InitializationError::ServerDegree { entry, .. }
+            | InitializationError::NotADrone { entry, .. }
+            | InitializationError::NotBidirectional { entry, .. } => Some(*entry),
+            InitializationError::DroneGraphDisconnected { .. } => None,
+        }
+    }
+}
+
+impl Display for InitializationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InitializationError::DuplicateId { entry, first } => write!(f, "{}: id {} already used by {}", entry, entry.id(), first),
+            InitializationError::SelfLoop { entry } => write!(f, "{}: connected to itself", entry),
+            InitializationError::DuplicateConnection { entry, neighbour } => write!(f, "{}: connected more than once to {}", entry, neighbour),
+            InitializationError::PdrOutOfRange { entry, pdr } => write!(f, "{}: PDR {} is not between 0 and 1", entry, pdr),
+            InitializationError::ClientDegree { entry, degree } => write!(f, "{}: connected to {} drones, a client needs one or two", entry, degree),
+            InitializationError::ServerDegree { entry, degree } => write!(f, "{}: connected to {} drones, a server needs at least two", entry, degree),
+            InitializationError::NotADrone { entry, neighbour } => write!(f, "{}: connected to node {}, which is not a drone", entry, neighbour),
+            InitializationError::NotBidirectional { entry, a, b } => write!(f, "{}: drone {} lists drone {}, but drone {} does not list drone {}", entry, a, b, b, a),
+            InitializationError::DroneGraphDisconnected { components } if components.is_empty() => write!(f, "there are no drones"),
+            InitializationError::DroneGraphDisconnected { components } => {
+                let components: Vec<String> = components.iter().map(|component| format!("{:?}", component)).collect();
+                write!(f, "the drones are split in {} groups: {}", components.len(), components.join(", "))
+            }
+        }
+    }
+}
+
+/// Every violation found in a configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitializationReport {
+    pub errors: Vec<InitializationError>,
+}
+
+impl Display for InitializationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} error(s) in the initialization file", self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Why an initialization file cannot be used.
+#[derive(Debug, Clone)]
+pub enum InitializationFileError {
+    Read { path: PathBuf, error: String },
+    Parse { path: PathBuf, error: String },
+    Invalid { path: PathBuf, report: InitializationReport },
+    Extensions { path: PathBuf, error: String },        // Sections read by the network initializer only
+    UnknownPinnedClient { path: PathBuf, client_id: NodeId },   // Its type is pinned by a scenario
+}
+
+impl Display for InitializationFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InitializationFileError::Read { path, error } => write!(f, "unable to read {}: {}", path.display(), error),
+            InitializationFileError::Parse { path, error } => write!(f, "unable to parse {}: {}", path.display(), error),
+            InitializationFileError::Invalid { path, report } => write!(f, "{}: {}", path.display(), report),
+            InitializationFileError::Extensions { path, error } => write!(f, "{}: {}", path.display(), error),
+            InitializationFileError::UnknownPinnedClient { path, client_id } => {
+                write!(f, "{}: the scenario pins the type of client {}, which is not a client of this network", path.display(), client_id)
+            }
+        }
+    }
+}
+
+/// Reads an initialization file without checking it.
+pub fn read_config<P: AsRef<Path>>(path: P) -> Result<Config, InitializationFileError> {
+    let path = path.as_ref().to_path_buf();
+    let config_data = fs::read_to_string(&path)
+        .map_err(|e| InitializationFileError::Read { path: path.clone(), error: e.to_string() })?;
+    toml::from_str(&config_data)
+        .map_err(|e| InitializationFileError::Parse { path, error: e.to_string() })
+}
+
+/// Returns the configuration as an initialization file, with the seed that replays the simulation.
+pub fn config_to_toml(config: &Config, seed: u64) -> Result<String, String> {
+    let toml = toml::to_string(config).map_err(|e| format!("Unable to serialize the network: {}", e))?;
+
+    // The PDRs are f32, written as f64 0.05 would become 0.05000000074505806
+    let lines: Vec<String> = toml.lines()
+        .map(|line| match line.strip_prefix("pdr = ").and_then(|pdr| pdr.parse::<f32>().ok()) {
+            Some(pdr) => format!("pdr = {:?}", pdr),
+            None => line.to_string(),
+        })
+        .collect();
+    // TOML integers are signed, such a seed can only be given with --seed
+    let seed_line = match i64::try_from(seed) {
+        Ok(seed) => format!("seed = {}", seed),
+        Err(_) => format!("# seed {} does not fit in a TOML integer, use --seed", seed),
+    };
+    Ok(format!("{}\n\n{}\n", seed_line, lines.join("\n")))
+}
+
+/// Reads an initialization file and checks it, the configuration is returned only if it is valid.
+pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Config, InitializationFileError> {
+    let path = path.as_ref().to_path_buf();
+    let config = read_config(&path)?;
+
+    InitializationFileChecker::new(&config)
+        .check()
+        .map_err(|report| InitializationFileError::Invalid { path, report })?;
+    Ok(config)
+}
+
 pub struct InitializationFileChecker<'a> {
     drones: &'a Vec<Drone>,
     clients: &'a Vec<Client>,
@@ -17,126 +178,140 @@ impl<'a> InitializationFileChecker<'a> {
         }
     }
 
-    pub fn check(&self) -> Result<(), String> {
-        let mut seen_ids = HashSet::new();
-
-        // Validate each drone: check for duplicate IDs and individual drone fields.
-        for drone in self.drones {
-            if !seen_ids.insert(drone.id) {
-                return Err(format!("Duplicate ID detected: {}", drone.id));
-            }
-            self.is_valid_drone(drone)?;
+    /// Checks the whole configuration, the report contains every violation and not only the first one.
+    pub fn check(&self) -> Result<(), InitializationReport> {
+        let errors = self.errors();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(InitializationReport { errors })
         }
+    }
+
+    /// Returns every violation of the configuration, in the order of the file.
+    pub fn errors(&self) -> Vec<InitializationError> {
+        let mut errors = Vec::new();
 
-        // Validate each client.
-        for client in self.clients {
-            if !seen_ids.insert(client.id) {
-                return Err(format!("Duplicate ID detected: {}", client.id));
+        // Check for duplicate IDs, the first entry with an id is the one the others are compared to.
+        let mut seen_ids: HashMap<NodeId, NodeEntry> = HashMap::new();
+        for entry in self.entries() {
+            if let Some(first) = seen_ids.get(&entry.id()) {
+                errors.push(InitializationError::DuplicateId { entry, first: *first });
+            } else {
+                seen_ids.insert(entry.id(), entry);
             }
-            self.is_valid_client(client)?;
         }
 
-        // Validate each server.
-        for server in self.servers {
-            if !seen_ids.insert(server.id) {
-                return Err(format!("Duplicate ID detected: {}", server.id));
-            }
-            self.is_valid_server(server)?;
+        // Validate the fields of each drone, client and server.
+        for (i, drone) in self.drones.iter().enumerate() {
+            self.check_drone(NodeEntry::Drone { index: i + 1, id: drone.id }, drone, &mut errors);
+        }
+        for (i, client) in self.clients.iter().enumerate() {
+            self.check_client(NodeEntry::Client { index: i + 1, id: client.id }, client, &mut errors);
+        }
+        for (i, server) in self.servers.iter().enumerate() {
+            self.check_server(NodeEntry::Server { index: i + 1, id: server.id }, server, &mut errors);
         }
 
         // Check that all drone-to-drone connections are bidirectional.
-        self.check_bidirectional()?;
+        self.check_bidirectional(&mut errors);
 
         // Check that the drone network is fully connected.
-        if !self.is_network_connected() {
-            return Err("The network is not fully connected after removing clients and servers.".to_string());
+        let components = self.drone_components();
+        if components.len() != 1 {
+            errors.push(InitializationError::DroneGraphDisconnected { components });
         }
 
-        Ok(())
+        errors
     }
 
-    fn is_valid_drone(&self, drone: &Drone) -> Result<(), String> {
-        if !(0.0..=1.0).contains(&drone.pdr) {
-            return Err(format!("Drone {} has invalid PDR: {}", drone.id, drone.pdr));
-        }
-
-        if drone.connected_node_ids.contains(&drone.id) {
-            return Err(format!("Drone {} is connected to itself", drone.id));
-        }
+    fn entries(&self) -> impl Iterator<Item = NodeEntry> + '_ {
+        let drones = self.drones.iter().enumerate().map(|(i, d)| NodeEntry::Drone { index: i + 1, id: d.id });
+        let clients = self.clients.iter().enumerate().map(|(i, c)| NodeEntry::Client { index: i + 1, id: c.id });
+        let servers = self.servers.iter().enumerate().map(|(i, s)| NodeEntry::Server { index: i + 1, id: s.id });
+        drones.chain(clients).chain(servers)
+    }
 
-        if drone.connected_node_ids.len() != drone.connected_node_ids.iter().collect::<HashSet<_>>().len() {
-            return Err(format!("Drone {} has duplicate connections", drone.id));
+    fn check_drone(&self, entry: NodeEntry, drone: &Drone, errors: &mut Vec<InitializationError>) {
+        if !(0.0..=1.0).contains(&drone.pdr) {
+            errors.push(InitializationError::PdrOutOfRange { entry, pdr: drone.pdr });
         }
-
-        Ok(())
+        Self::check_connections(entry, &drone.connected_node_ids, errors);
     }
 
-    fn is_valid_client(&self, client: &Client) -> Result<(), String> {
+    fn check_client(&self, entry: NodeEntry, client: &Client, errors: &mut Vec<InitializationError>) {
         let num_of_connections = client.connected_drone_ids.len();
-
         if num_of_connections == 0 || num_of_connections > 2 {
-            return Err(format!(
-                "Client {} has an invalid number of connected drones: {}",
-                client.id, num_of_connections
-            ));
+            errors.push(InitializationError::ClientDegree { entry, degree: num_of_connections });
         }
-
-        if client.connected_drone_ids.contains(&client.id) {
-            return Err(format!("Client {} is connected to itself", client.id));
-        }
-
-        Ok(())
+        Self::check_connections(entry, &client.connected_drone_ids, errors);
+        self.check_only_drones(entry, &client.connected_drone_ids, errors);
     }
 
-    fn is_valid_server(&self, server: &Server) -> Result<(), String> {
+    fn check_server(&self, entry: NodeEntry, server: &Server, errors: &mut Vec<InitializationError>) {
         let num_of_connections = server.connected_drone_ids.len();
-
         if num_of_connections < 2 {
-            return Err(format!(
-                "Server {} must have at least 2 connected drones, found: {}",
-                server.id, num_of_connections
-            ));
+            errors.push(InitializationError::ServerDegree { entry, degree: num_of_connections });
         }
+        Self::check_connections(entry, &server.connected_drone_ids, errors);
+        self.check_only_drones(entry, &server.connected_drone_ids, errors);
+    }
 
-        if server.connected_drone_ids.contains(&server.id) {
-            return Err(format!("Server {} is connected to itself", server.id));
+    /// Checks that a node is not connected to itself and lists each neighbour once.
+    fn check_connections(entry: NodeEntry, connected_ids: &[NodeId], errors: &mut Vec<InitializationError>) {
+        if connected_ids.contains(&entry.id()) {
+            errors.push(InitializationError::SelfLoop { entry });
         }
+        let mut seen = HashSet::new();
+        let mut reported = HashSet::new();
+        for &neighbour in connected_ids {
+            if !seen.insert(neighbour) && reported.insert(neighbour) {
+                errors.push(InitializationError::DuplicateConnection { entry, neighbour });
+            }
+        }
+    }
 
-        Ok(())
+    /// Checks that clients and servers are connected only to drones of the configuration,
+    /// so there are no client-client, client-server or server-server links.
+    fn check_only_drones(&self, entry: NodeEntry, connected_ids: &[NodeId], errors: &mut Vec<InitializationError>) {
+        for &neighbour in connected_ids {
+            if neighbour != entry.id() && !self.drones.iter().any(|d| d.id == neighbour) {
+                errors.push(InitializationError::NotADrone { entry, neighbour });
+            }
+        }
     }
 
     /// Checks that every drone-to-drone connection is bidirectional.
     ///
     /// For each drone, if it lists a connection to another drone (i.e. a neighbor that is also defined as a drone),
     /// then the neighbor must also list the original drone in its `connected_node_ids`.
-    fn check_bidirectional(&self) -> Result<(), String> {
+    fn check_bidirectional(&self, errors: &mut Vec<InitializationError>) {
         // Build a map of drone IDs to Drone objects for quick lookup.
         let drone_map: HashMap<DroneId, &Drone> = self.drones.iter().map(|d| (d.id, d)).collect();
 
         // Iterate over each drone and its connections.
-        for drone in self.drones {
+        for (i, drone) in self.drones.iter().enumerate() {
             for &neighbor in &drone.connected_node_ids {
                 // Only check bidirectionality if the neighbor is also a drone in the configuration.
                 if let Some(neighbor_drone) = drone_map.get(&neighbor) {
                     // If the neighbor drone does not have a reciprocal connection, report an error.
-                    if !neighbor_drone.connected_node_ids.contains(&drone.id) {
-                        return Err(format!(
-                            "Drone {} is not bidirectionally connected with drone {}",
-                            drone.id, neighbor
-                        ));
+                    if neighbor != drone.id && !neighbor_drone.connected_node_ids.contains(&drone.id) {
+                        errors.push(InitializationError::NotBidirectional {
+                            entry: NodeEntry::Drone { index: i + 1, id: drone.id },
+                            a: drone.id,
+                            b: neighbor,
+                        });
                     }
                 }
             }
         }
-        Ok(())
     }
 
-    /// Checks if the drone network is fully connected.
+    /// Returns the connected components of the drone network, each one sorted, the smallest ids first.
     ///
-    /// This method constructs a symmetric graph from the drone connections.
-    /// Since bidirectionality has been validated above, we add the edges in both directions
-    /// for drones that exist in the configuration.
-    fn is_network_connected(&self) -> bool {
+    /// This method constructs a symmetric graph from the drone connections,
+    /// a one-way connection is already reported by `check_bidirectional`.
+    fn drone_components(&self) -> Vec<Vec<DroneId>> {
         let mut graph: HashMap<DroneId, HashSet<DroneId>> = HashMap::new();
 
         // Build the graph using only drones.
@@ -144,24 +319,30 @@ impl<'a> InitializationFileChecker<'a> {
             graph.entry(drone.id).or_default();
             // Add edges only for neighbors that are also drones.
             for &node in &drone.connected_node_ids {
-                if self.drones.iter().any(|d| d.id == node) {
+                if node != drone.id && self.drones.iter().any(|d| d.id == node) {
                     graph.entry(node).or_default().insert(drone.id);
                     graph.entry(drone.id).or_default().insert(node);
                 }
             }
         }
 
-        if graph.is_empty() {
-            return false;
-        }
-
-        // Use DFS to check connectivity.
-        let start = *graph.keys().next().unwrap();
+        // Use DFS from every drone not visited yet.
+        let mut drone_ids: Vec<DroneId> = graph.keys().copied().collect();
+        drone_ids.sort_unstable();
         let mut visited = HashSet::new();
-        self.dfs(start, &graph, &mut visited);
-
-        // The network is fully connected if all nodes in the graph are visited.
-        visited.len() == graph.len()
+        let mut components = Vec::new();
+        for drone_id in drone_ids {
+            if visited.contains(&drone_id) {
+                continue;
+            }
+            let mut component = HashSet::new();
+            self.dfs(drone_id, &graph, &mut component);
+            let mut component: Vec<DroneId> = component.into_iter().collect();
+            component.sort_unstable();
+            visited.extend(component.iter().copied());
+            components.push(component);
+        }
+        components
     }
 
     /// Depth-first search helper function.
@@ -175,3 +356,110 @@ impl<'a> InitializationFileChecker<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BROKEN_CONFIG: &str = r#"
+        [[drone]]
+        id = 1
+        connected_node_ids = [2, 1, 11]
+        pdr = 0.1
+
+        [[drone]]
+        id = 2
+        connected_node_ids = [1, 3, 20]
+        pdr = 1.5
+
+        [[drone]]
+        id = 3
+        connected_node_ids = []
+        pdr = 0.0
+
+        [[drone]]
+        id = 4
+        connected_node_ids = [5, 20]
+        pdr = 0.0
+
+        [[drone]]
+        id = 5
+        connected_node_ids = [4]
+        pdr = 0.0
+
+        [[client]]
+        id = 11
+        connected_drone_ids = [1]
+
+        [[server]]
+        id = 20
+        connected_drone_ids = [2, 4]
+    "#;
+
+    #[test]
+    fn every_violation_is_reported_with_its_entry() {
+        let config: Config = toml::from_str(BROKEN_CONFIG).expect("valid toml");
+        let report = InitializationFileChecker::new(&config).check().expect_err("broken configuration");
+        assert_eq!(report.errors, vec![
+            InitializationError::SelfLoop { entry: NodeEntry::Drone { index: 1, id: 1 } },
+            InitializationError::PdrOutOfRange { entry: NodeEntry::Drone { index: 2, id: 2 }, pdr: 1.5 },
+            InitializationError::NotBidirectional { entry: NodeEntry::Drone { index: 2, id: 2 }, a: 2, b: 3 },
+            InitializationError::DroneGraphDisconnected { components: vec![vec![1, 2, 3], vec![4, 5]] },
+        ]);
+        assert_eq!(report.errors[2].entry(), Some(NodeEntry::Drone { index: 2, id: 2 }));
+        assert_eq!(report.errors[3].entry(), None);
+    }
+
+    #[test]
+    fn entries_of_clients_and_servers_are_counted_in_their_own_array() {
+        let config: Config = toml::from_str(r#"
+            [[drone]]
+            id = 1
+            connected_node_ids = [2]
+            pdr = 0.0
+
+            [[drone]]
+            id = 2
+            connected_node_ids = [1]
+            pdr = 0.0
+
+            [[client]]
+            id = 11
+            connected_drone_ids = [1, 1]
+
+            [[server]]
+            id = 1
+            connected_drone_ids = [11]
+        "#).expect("valid toml");
+        assert_eq!(InitializationFileChecker::new(&config).errors(), vec![
+            InitializationError::DuplicateId { entry: NodeEntry::Server { index: 1, id: 1 }, first: NodeEntry::Drone { index: 1, id: 1 } },
+            InitializationError::DuplicateConnection { entry: NodeEntry::Client { index: 1, id: 11 }, neighbour: 1 },
+            InitializationError::ServerDegree { entry: NodeEntry::Server { index: 1, id: 1 }, degree: 1 },
+            InitializationError::NotADrone { entry: NodeEntry::Server { index: 1, id: 1 }, neighbour: 11 },
+        ]);
+    }
+
+    #[test]
+    fn unreadable_files_are_errors_and_not_exits() {
+        let missing = Path::new("no_such_directory/network.toml");
+        assert!(matches!(read_config(missing), Err(InitializationFileError::Read { .. })));
+
+        let path = std::env::temp_dir().join(format!("broken_network_{}.toml", std::process::id()));
+        fs::write(&path, "[[drone]]\nid = \"one\"\n").expect("writable temp dir");
+        let result = read_config(&path);
+        fs::remove_file(&path).ok();
+        assert!(matches!(result, Err(InitializationFileError::Parse { .. })));
+    }
+
+    #[test]
+    fn invalid_files_are_not_loaded() {
+        let path = std::env::temp_dir().join(format!("invalid_network_{}.toml", std::process::id()));
+        fs::write(&path, BROKEN_CONFIG).expect("writable temp dir");
+        let result = load_config(&path);
+        fs::remove_file(&path).ok();
+        match result {
+            Err(InitializationFileError::Invalid { report, .. }) => assert_eq!(report.errors.len(), 4),
+            other => panic!("expected an invalid file, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+pub mod network_initializer;
+pub mod servers;
+pub mod simulation_controller;
+pub mod general_use;
+pub mod clients;
+pub mod ui_traits;
+pub mod websocket;
+pub mod initialization_file_checker;
+pub mod network_extensions;
+pub mod topology_analysis;
+pub mod topology_generator;
+pub mod terminal_messages;
+pub mod cli;
+pub mod scenario;
+pub mod randomness;
+pub mod fragmentation;
+pub mod link_quality;
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+use wg_2024::network::NodeId;
+
+/// Fragments a drone is assumed to have forwarded before anything is observed.
+/// It keeps one unlucky drop from making a drone look worse than a long detour.
+const PRIOR_FORWARDED: f64 = 4.0;
+
+/// What a node observed of the fragments crossing a drone.
+#[derive(Debug, Clone, Copy, Default)]
+struct DroneLinkStats {
+    forwarded: u64,                     // Fragments acked by their destination
+    dropped: u64,                       // Nack::Dropped sent by the drone
+}
+
+/// Link-quality estimator: turns the observed drops into a loss estimate for each drone
+/// and chooses the routes with the least expected transmissions (ETX) instead of the fewest hops.
+/// The drones never seen dropping cost one transmission each, so without drops the routes are the shortest ones.
+#[derive(Debug, Clone, Default)]
+pub struct LinkQuality {
+    drones: HashMap<NodeId, DroneLinkStats>,
+}
+
+impl LinkQuality {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The drone answered a fragment with `Nack::Dropped`.
+    pub fn record_drop(&mut self, drone_id: NodeId) {
+        self.drones.entry(drone_id).or_default().dropped += 1;
+    }
+
+    /// A fragment sent on the route was acked, every drone of the route forwarded it.
+    /// The route can be the one of the ack, the endpoints are not counted.
+    pub fn record_delivery(&mut self, route: &[NodeId]) {
+        for drone_id in intermediate_hops(route) {
+            self.drones.entry(*drone_id).or_default().forwarded += 1;
+        }
+    }
+
+    /// Forgets what was observed of the drone, when it is fixed or replaced.
+    pub fn forget(&mut self, drone_id: NodeId) {
+        self.drones.remove(&drone_id);
+    }
+
+    /// Estimated probability that the drone drops a fragment.
+    pub fn loss(&self, drone_id: NodeId) -> f64 {
+        let stats = self.drones.get(&drone_id).copied().unwrap_or_default();
+        let dropped = stats.dropped as f64;
+        dropped / (dropped + stats.forwarded as f64 + PRIOR_FORWARDED)
+    }
+
+    /// Expected transmissions for a fragment to get past the drone, at least 1.
+    pub fn etx(&self, drone_id: NodeId) -> f64 {
+        1.0 / (1.0 - self.loss(drone_id))
+    }
+
+    /// Sum of the ETX of the drones of the route, the endpoints are not counted.
+    pub fn route_cost(&self, route: &[NodeId]) -> f64 {
+        intermediate_hops(route).iter().map(|drone_id| self.etx(*drone_id)).sum()
+    }
+
+    /// Expected transmissions of every route of a routing table, the `route_costs` shown next to the routes
+    /// in the monitoring data of the clients and the servers.
+    pub fn route_costs(&self, routes: &HashMap<NodeId, Vec<NodeId>>) -> HashMap<NodeId, f64> {
+        routes.iter()
+            .filter(|(_, route)| !route.is_empty())
+            .map(|(destination, route)| (*destination, self.route_cost(route)))
+            .collect()
+    }
+
+    /// Route from `source` to `destination` with the least cost (Dijkstra), only through the nodes
+    /// that `can_cross` allows. Among routes of the same cost the one with the fewest hops wins,
+    /// then the one reaching the lowest ids first, so the choice does not depend on the hashing.
+    pub fn best_route(
+        &self,
+        source: NodeId,
+        destination: NodeId,
+        topology: &HashMap<NodeId, HashSet<NodeId>>,
+        can_cross: impl Fn(NodeId) -> bool,
+    ) -> Option<Vec<NodeId>> {
+        // Best known (cost, hops) and predecessor of every reached node
+        let mut best: HashMap<NodeId, (f64, usize, Option<NodeId>)> = HashMap::new();
+        let mut done: HashSet<NodeId> = HashSet::new();
+        best.insert(source, (0.0, 0, None));
+
+        loop {
+            // The cheapest node not done yet
+            let current = best.iter()
+                .filter(|(node_id, _)| !done.contains(node_id))
+                .min_by(|(a_id, (a_cost, a_hops, _)), (b_id, (b_cost, b_hops, _))| {
+                    a_cost.total_cmp(b_cost).then(a_hops.cmp(b_hops)).then(a_id.cmp(b_id))
+                })
+                .map(|(node_id, (cost, hops, _))| (*node_id, *cost, *hops));
+            let Some((current, cost, hops)) = current else {
+                return None;
+            };
+            if current == destination {
+                break;
+            }
+            done.insert(current);
+
+            // Only the source and the drones forward the fragments
+            if current != source && !can_cross(current) {
+                continue;
+            }
+
+            let mut neighbors: Vec<NodeId> = topology.get(&current).into_iter().flatten().copied().collect();
+            neighbors.sort_unstable();
+            for neighbor in neighbors {
+                if done.contains(&neighbor) {
+                    continue;
+                }
+                let neighbor_cost = if neighbor == destination { cost } else { cost + self.etx(neighbor) };
+                let is_better = best.get(&neighbor).map_or(true, |(known_cost, known_hops, _)| {
+                    neighbor_cost.total_cmp(known_cost).then((hops + 1).cmp(known_hops)).is_lt()
+                });
+                if is_better {
+                    best.insert(neighbor, (neighbor_cost, hops + 1, Some(current)));
+                }
+            }
+        }
+
+        // Walking back the predecessors
+        let mut route = vec![destination];
+        let mut node_id = destination;
+        while let Some((_, _, Some(previous))) = best.get(&node_id) {
+            route.push(*previous);
+            node_id = *previous;
+        }
+        route.reverse();
+        Some(route)
+    }
+
+    /// Backup of the `primary` route: the best route that shares no drone with it, so that a single crash
+    /// cannot break both. None if there is no such route, or if the primary route crosses no drone.
+    pub fn disjoint_route(
+        &self,
+        source: NodeId,
+        destination: NodeId,
+        topology: &HashMap<NodeId, HashSet<NodeId>>,
+        can_cross: impl Fn(NodeId) -> bool,
+        primary: &[NodeId],
+    ) -> Option<Vec<NodeId>> {
+        let primary_drones = intermediate_hops(primary);
+        if primary_drones.is_empty() {
+            return None;
+        }
+        self.best_route(source, destination, topology, |node_id| can_cross(node_id) && !primary_drones.contains(&node_id))
+    }
+}
+
+fn intermediate_hops(route: &[NodeId]) -> &[NodeId] {
+    if route.len() > 2 { &route[1..route.len() - 1] } else { &[] }
+}
@@ -1,57 +1,122 @@
-pub mod network_initializer;
-pub mod servers;
-pub mod simulation_controller;
-pub mod general_use;
-pub mod clients;
-pub mod ui_traits;
-pub mod websocket;
-pub mod initialization_file_checker;
-pub mod terminal_messages;
-
 extern crate rouille;
 
-use std::thread;
+use std::{env, process, thread};
 use std::time::Duration;
 use crossbeam_channel::{unbounded};
-use crate::ui_traits::{SimulationControllerMonitoring};
+use krusty_crap::{cli, network_initializer, randomness, terminal_messages, websocket};
+use krusty_crap::cli::{CliArgs, CliCommand};
+use krusty_crap::scenario::{Scenario, ScenarioRunner};
+use krusty_crap::ui_traits::{SimulationControllerMonitoring};
 
 // Modified main function
 fn main() {
+    // Parse and validate the command line before starting anything
+    let cli_args = match CliArgs::parse(env::args().skip(1)) {
+        Ok(CliCommand::Run(cli_args)) => cli_args,
+        Ok(CliCommand::Help) => {
+            println!("{}", cli::usage());
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, cli::usage());
+            process::exit(2);
+        }
+    };
+
     // Initialize the logger
-    env_logger::init();
+    match cli_args.log_level {
+        Some(level) => env_logger::Builder::from_default_env().filter_level(level).init(),
+        None => env_logger::init(),
+    }
+
+    // Read the scenario before building the network, so a bad file does not start anything
+    let scenario = match &cli_args.scenario_path {
+        Some(path) => match Scenario::from_file(path) {
+            Ok(scenario) => Some(scenario),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(2);
+            }
+        },
+        None => None,
+    };
+
+    // The seed must be fixed before anything random is created: command line, then scenario, then topology file
+    let seed = match cli_args.seed.or(scenario.as_ref().and_then(|scenario| scenario.seed)) {
+        Some(seed) => seed,
+        None => match randomness::seed_from_topology(&cli_args.topology_path) {
+            Ok(seed) => seed.unwrap_or_else(randomness::random_seed),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(2);
+            }
+        },
+    };
+    randomness::set_seed(seed).expect("The seed is set only once");
+    println!("Simulation seed: {} (replay with --seed {})", seed, seed);
 
     // Create channels for communication
     let (tx, rx) = unbounded();
     let (sender_from_ws, receiver_from_ws) = unbounded();
 
+    // Initialize the network
+    let mut my_net = network_initializer::NetworkInitializer::new(receiver_from_ws);
+    if let Some(scenario) = &scenario {
+        my_net.pinned_client_types = scenario.pinned_client_types();
+    }
+    if let Err(e) = my_net.initialize_from_file(&cli_args.topology_path) {
+        terminal_messages::network_not_valid(e.to_string());
+        terminal_messages::network_stopped();
+        process::exit(1);
+    }
+    let mut simulation_controller = my_net.simulation_controller;
+
+    // The scenario runner takes the place of the web interface
+    if let Some(scenario) = scenario {
+        let mut runner = ScenarioRunner::new(sender_from_ws, rx);
+        thread::spawn(move || simulation_controller.run_with_monitoring(tx));
+
+        let report = runner.run(&scenario);
+        println!("{}", report);
+        process::exit(if report.all_passed() { 0 } else { 1 });
+    }
+
     // Run the simulation controller
-    thread::spawn(move || {
-        // Initialize the network
-        let mut my_net = network_initializer::NetworkInitializer::new(receiver_from_ws);
-        my_net.initialize_from_file("topologies/tree.toml");
-        // Clone the shared simulation controller
-        let mut simulation_controller = my_net.simulation_controller;
+    let (headless, http_port, ws_port) = (cli_args.headless, cli_args.http_port, cli_args.ws_port);
+    if headless {
+        println!("\nRunning {} in headless mode\n", cli_args.topology_path.display());
+    } else {
         println!(
             "\n\
     ┌──────────────────────────────────────────────────┐\n\
     │   🚀 To use the application, visit:              │\n\
-    │   🌍 http://localhost:8000/index.html            │\n\
-    └──────────────────────────────────────────────────┘\n"
+    │   🌍 http://localhost:{}/index.html?wsPort={}\n\
+    └──────────────────────────────────────────────────┘\n",
+            http_port, ws_port
         );
+    }
+    thread::spawn(move || {
         simulation_controller.run_with_monitoring(tx.clone());
     });
 
+    // In headless mode the command sender is kept alive, otherwise the controller would spin on a closed channel
+    let _ws_command_sender = if headless {
+        // Nobody is listening for the display data, just consume it
+        thread::spawn(move || while rx.recv().is_ok() {});
+        Some(sender_from_ws)
+    } else {
+        // Start WebSocket server
+        websocket::start_websocket_server(rx, sender_from_ws, ws_port);
 
-    // Start WebSocket server
-    websocket::start_websocket_server(rx, sender_from_ws);
-
-    // Start HTTP server for web interface
-    thread::spawn(|| {
-        //println!("HTTP server started on http://0.0.0.0:8000");
-        rouille::start_server("0.0.0.0:8000", move |request| {
-            rouille::match_assets(&request, "static")
+        // Start HTTP server for web interface
+        thread::spawn(move || {
+            //println!("HTTP server started on http://0.0.0.0:{}", http_port);
+            rouille::start_server(format!("0.0.0.0:{}", http_port), move |request| {
+                rouille::match_assets(&request, "static")
+            });
         });
-    });
+        None
+    };
 
     // Keep main thread alive
     loop {
@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Deserialize;
+use wg_2024::config::Config;
+use crate::general_use::{DroneId, ServerId, ServerType};
+use crate::network_initializer::DroneBrand;
+use crate::fragmentation::DEFAULT_SEND_WINDOW;
+use crate::servers::content;
+use crate::servers::drone_repair::DEFAULT_DRONE_REPAIR_TIMEOUT;
+
+/// Type and content of a server, from a `[[server_role]]` entry of the initialization file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerRole {
+    pub id: ServerId,
+    #[serde(rename = "type")]
+    pub server_type: ServerType,
+    #[serde(default)]
+    pub texts: Option<Vec<String>>,             // Text servers, titles of the built-in texts, random if missing
+    #[serde(default)]
+    pub directory: Option<PathBuf>,             // Text and media servers, serves the files of this directory instead
+    #[serde(default)]
+    pub media_for: Option<Vec<ServerId>>,       // Media servers, text servers whose media are served, all if missing
+}
+
+/// Brand of a single drone, from the `pin` list of `[drone_brands]`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PinnedBrand {
+    pub id: DroneId,
+    pub brand: DroneBrand,
+}
+
+/// How the brands are given to the drones, from the `[drone_brands]` section of the initialization file.
+/// The drones that are not pinned get the least used brands of the pool, chosen with the seeded brand generator.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DroneBrands {
+    #[serde(default)]
+    pub force: Option<DroneBrand>,          // The only brand of the pool
+    #[serde(default)]
+    pub exclude: Vec<DroneBrand>,           // Removed from the pool
+    #[serde(default)]
+    pub pin: Vec<PinnedBrand>,
+}
+
+impl DroneBrands {
+    /// Brands given to the drones that are not pinned, also the ones added at runtime.
+    pub fn brand_pool(&self) -> Vec<DroneBrand> {
+        match self.force {
+            Some(brand) => vec![brand],
+            None => DroneBrand::iter().filter(|brand| !self.exclude.contains(brand)).collect(),
+        }
+    }
+
+    pub fn pinned_brand(&self, id: DroneId) -> Option<DroneBrand> {
+        self.pin.iter().find(|pinned| pinned.id == id).map(|pinned| pinned.brand)
+    }
+
+    fn validate(&self, config: &Config) -> Result<(), String> {
+        let drone_ids: HashSet<DroneId> = config.drone.iter().map(|drone| drone.id).collect();
+        let spawnable = |brand: DroneBrand| !matches!(brand, DroneBrand::KrustyDrone | DroneBrand::Undefined);
+
+        if let Some(brand) = self.force {
+            if !spawnable(brand) {
+                return Err(format!("[drone_brands] force: drones of brand {:?} cannot be spawned", brand));
+            }
+            if self.exclude.contains(&brand) {
+                return Err(format!("[drone_brands] force: {:?} is also excluded", brand));
+            }
+        }
+        if self.brand_pool().is_empty() {
+            return Err("[drone_brands] exclude: every brand is excluded".to_string());
+        }
+
+        let mut seen_ids = HashSet::new();
+        for pinned in &self.pin {
+            if !drone_ids.contains(&pinned.id) {
+                return Err(format!("[drone_brands] pin {}: there is no drone with this id", pinned.id));
+            }
+            if !seen_ids.insert(pinned.id) {
+                return Err(format!("[drone_brands] pin {}: the drone is pinned more than once", pinned.id));
+            }
+            if !spawnable(pinned.brand) {
+                return Err(format!("[drone_brands] pin {}: drones of brand {:?} cannot be spawned", pinned.id, pinned.brand));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How long the servers wait for the technicians, from the `[drone_repair]` section of the initialization file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DroneRepairSettings {
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,            // Then the parked fragments are sent anyway
+}
+
+impl DroneRepairSettings {
+    pub fn timeout(&self) -> Duration {
+        self.timeout_ms.map_or(DEFAULT_DRONE_REPAIR_TIMEOUT, Duration::from_millis)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.timeout_ms == Some(0) {
+            return Err("[drone_repair] timeout_ms: the timeout must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How many fragments of a message wait for their ack at the same time, from the `[send_window]` section of the initialization file.
+/// Used by the clients and the servers, it is the `send_window` of their monitoring data.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SendWindowSettings {
+    #[serde(default)]
+    pub size: Option<usize>,                // 1 sends a fragment at a time
+}
+
+impl SendWindowSettings {
+    pub fn size(&self) -> usize {
+        self.size.unwrap_or(DEFAULT_SEND_WINDOW)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.size == Some(0) {
+            return Err("[send_window] size: the window must hold at least 1 fragment".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Sections of the initialization file that are not part of the `wg_2024` configuration.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NetworkExtensions {
+    #[serde(default)]
+    pub server_role: Vec<ServerRole>,
+    #[serde(default)]
+    pub drone_brands: DroneBrands,
+    #[serde(default)]
+    pub drone_repair: DroneRepairSettings,
+    #[serde(default)]
+    pub send_window: SendWindowSettings,
+}
+
+impl NetworkExtensions {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        toml::from_str(&data).map_err(|e| format!("Unable to parse {}: {}", path.display(), e))
+    }
+
+    /// Checks the extensions against the nodes of the configuration.
+    pub fn validate(&self, config: &Config) -> Result<(), String> {
+        let server_ids: HashSet<ServerId> = config.server.iter().map(|server| server.id).collect();
+        let mut seen_ids = HashSet::new();
+
+        for role in &self.server_role {
+            if !server_ids.contains(&role.id) {
+                return Err(format!("[[server_role]] {}: there is no server with this id", role.id));
+            }
+            if !seen_ids.insert(role.id) {
+                return Err(format!("[[server_role]] {}: the role is given more than once", role.id));
+            }
+            match role.server_type {
+                ServerType::Communication | ServerType::Text | ServerType::Media => {}
+                other => return Err(format!("[[server_role]] {}: {:?} is not a server type", role.id, other)),
+            }
+            if role.texts.is_some() && role.server_type != ServerType::Text {
+                return Err(format!("[[server_role]] {}: only text servers have texts", role.id));
+            }
+            if role.media_for.is_some() && role.server_type != ServerType::Media {
+                return Err(format!("[[server_role]] {}: only media servers have media_for", role.id));
+            }
+            if let Some(directory) = &role.directory {
+                if role.server_type == ServerType::Communication {
+                    return Err(format!("[[server_role]] {}: only text and media servers have a directory", role.id));
+                }
+                if role.texts.is_some() || role.media_for.is_some() {
+                    return Err(format!("[[server_role]] {}: the content is given both by the directory and by the role", role.id));
+                }
+                if !directory.is_dir() {
+                    return Err(format!("[[server_role]] {}: {} is not a directory", role.id, directory.display()));
+                }
+            }
+            for title in role.texts.iter().flatten() {
+                if !content::TEXT.iter().any(|(text_title, _)| text_title == title) {
+                    return Err(format!("[[server_role]] {}: there is no text '{}'", role.id, title));
+                }
+            }
+            for text_server in role.media_for.iter().flatten() {
+                if self.server_type(*text_server) != Some(ServerType::Text) {
+                    return Err(format!("[[server_role]] {}: {} is not a text server with a [[server_role]]", role.id, text_server));
+                }
+            }
+        }
+        self.drone_brands.validate(config)?;
+        self.drone_repair.validate()?;
+        self.send_window.validate()
+    }
+
+    pub fn server_role(&self, id: ServerId) -> Option<&ServerRole> {
+        self.server_role.iter().find(|role| role.id == id)
+    }
+
+    pub fn server_type(&self, id: ServerId) -> Option<ServerType> {
+        self.server_role(id).map(|role| role.server_type)
+    }
+}
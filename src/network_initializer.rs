@@ -1,14 +1,13 @@
 //Outside libraries
-use std::{collections::HashMap, env, fs, process, thread};
+use std::{collections::HashMap, env, path::Path, thread};
 use crossbeam_channel::*;
 use rand::prelude::*;
 //Wg libraries
 use wg_2024::{
-    config::{Client, Config, Drone, Server},
-    controller::{DroneCommand, DroneEvent},
+    config::{Client, Drone, Server},
+    controller::DroneCommand,
     network::NodeId,
     packet::{NodeType, Packet},
-    drone::Drone as TraitDrone,
 };
 
 //Inner libraries
@@ -18,34 +17,23 @@ use crate::{
         client_chen::ClientChen,
         client_danylo::ChatClientDanylo,
     },
-    general_use::{ClientId, ClientCommand, ClientEvent, ClientType, ServerType, DroneId, UsingTimes, DisplayDataDrone},
+    general_use::{ClientId, ClientCommand, ClientEvent, ClientType, ServerId, ServerType, UsingTimes},
     servers::{content, communication_server::CommunicationServer, text_server::TextServer, media_server::MediaServer},
     simulation_controller::SimulationController,
-    initialization_file_checker::InitializationFileChecker,
+    initialization_file_checker::{self, InitializationFileError},
+    network_extensions::NetworkExtensions,
+    randomness,
 };
 
-
-//Drones
-use rusty_drones::RustyDrone;
-use rolling_drone::RollingDrone;
-use rustable_drone::RustableDrone;
-use rustbusters_drone::RustBustersDrone;
-use rusteze_drone::RustezeDrone;
-use fungi_drone::FungiDrone;
-use bagel_bomber::BagelBomber;
-use skylink::SkyLinkDrone;
-use RF_drone::RustAndFurious;
-use bobry_w_locie::drone::BoberDrone;
 use log::info;
-use crate::clients::client_chen::Serialize;
-use crate::general_use::SpecificNodeType;
-use crate::terminal_messages::{building_network, network_not_valid, network_stopped, network_valid};
+use serde::{Deserialize, Serialize};
+use crate::terminal_messages::{building_network, drone_brands, network_valid};
 //UI
 use crate::ui_traits::Monitoring;
 use crate::websocket::WsCommand;
 
 //Drone Enum + iterator over it
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize)]
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum DroneBrand {
     //KrustyDrone,
     RustyDrone,
@@ -83,14 +71,14 @@ impl DroneBrand {
 }
 
 pub struct NetworkInitializer {
-    pub(crate) simulation_controller: SimulationController,
+    pub simulation_controller: SimulationController,
     pub drone_channels: HashMap<NodeId, Sender<Packet>>,
     pub client_channels: HashMap<NodeId, (Sender<Packet>, ClientType)>,
     pub server_channels: HashMap<NodeId, (Sender<Packet>, ServerType)>,
-    pub drone_brand_usage: HashMap<DroneBrand, UsingTimes>,
     pub client_type_usage: HashMap<ClientType, UsingTimes>,
+    pub pinned_client_types: HashMap<ClientId, ClientType>,     // Chosen by a scenario, the other clients are chosen evenly
     pub command_senders: HashMap<NodeId, Sender<DroneCommand>>, // Add this field
-
+    rng: StdRng,                                                // Seeded, brands, client types and texts
 }
 
 impl NetworkInitializer {
@@ -117,12 +105,14 @@ impl NetworkInitializer {
             drone_channels:HashMap::new(),
             client_channels:HashMap::new(),
             server_channels:HashMap::new(),
-            drone_brand_usage: DroneBrand::iter().map(|brand| (brand, 0)).collect(),
             client_type_usage: ClientType::iter().map(|client_type| (client_type, 0)).collect(),
+            pinned_client_types: HashMap::new(),
             command_senders: HashMap::new(),
+            rng: randomness::seeded_rng("network_initializer"),
         }
     }
-    pub fn initialize_from_file(&mut self, config_path: &str) {
+    /// Builds the network described by the file, nothing is spawned if the file is not valid.
+    pub fn initialize_from_file<P: AsRef<Path>>(&mut self, config_path: P) -> Result<(), InitializationFileError> {
 
         // Log the current directory for debugging purposes
         info!("Current directory: {:?}", env::current_dir().expect("Failed to get current directory"));
@@ -132,21 +122,15 @@ impl NetworkInitializer {
             .expect("Failed to get current directory")
             .join(config_path);
 
-        // Read and parse the configuration file
-        let config_data = fs::read_to_string(config_path).expect("Unable to read config file");
-        let config: Config = toml::from_str(&config_data).expect("Failed to parse TOML config");
-
-        // Check the configuration file for errors
-        match InitializationFileChecker::new(&config).check() {
-            Ok(_) => {
-                network_valid();
-            },
-            Err(e) => {
-                network_not_valid(e);
-                network_stopped();
-                process::exit(1);
-            },
+        // Read, parse and check the configuration file
+        let config = initialization_file_checker::load_config(&config_path)?;
+        let extensions = NetworkExtensions::from_file(&config_path)
+            .and_then(|extensions| extensions.validate(&config).map(|()| extensions))
+            .map_err(|error| InitializationFileError::Extensions { path: config_path.clone(), error })?;
+        if let Some(&client_id) = self.pinned_client_types.keys().find(|&&id| !config.client.iter().any(|client| client.id == id)) {
+            return Err(InitializationFileError::UnknownPinnedClient { path: config_path, client_id });
         }
+        network_valid();
 
         // Build the network topology
         let mut topology = HashMap::new();
@@ -169,211 +153,54 @@ impl NetworkInitializer {
         self.simulation_controller.state.nodes = nodes;
 
         // Initialize drones, clients, and servers
-        self.create_drones(config.drone);
-        self.create_clients(config.client);
-        self.create_servers(config.server);
+        self.create_drones(config.drone, &extensions);
+        self.create_clients(config.client, &extensions);
+        self.create_servers(config.server, &extensions)
+            .map_err(|error| InitializationFileError::Extensions { path: config_path.clone(), error })?;
 
         //Connecting the network
         self.connect_nodes(topology);
+        Ok(())
     }
 
     ///DRONES GENERATION
-
+    /// The pinned drones get their brand, the others the least used brands of the `[drone_brands]` pool.
     fn create_drones(
         &mut self,
         drones: Vec<Drone>,
+        extensions: &NetworkExtensions,
     ) {
-        for drone in drones {
-            // Adding channel to controller
-            let (command_sender, command_receiver) = unbounded();
-            self.simulation_controller.register_drone(drone.id, command_sender);
+        // The drones added at runtime use the same pool
+        self.simulation_controller.set_drone_brands(extensions.drone_brands.brand_pool());
 
-            // Creating channels with the connected nodes
-            let (packet_sender, packet_receiver) = unbounded();
+        let mut chosen_brands = Vec::new();
+        for drone in drones {
+            // The controller spawns the drones, so it can also do it at runtime
+            let drone_brand = match extensions.drone_brands.pinned_brand(drone.id) {
+                Some(drone_brand) => drone_brand,
+                None => self.simulation_controller.choose_drone_brand_evenly(),
+            };
+            if let Err(e) = self.simulation_controller.spawn_drone(drone.id, drone.pdr, drone_brand) {
+                panic!("Failed to spawn drone {}: {}", drone.id, e);
+            }
+            chosen_brands.push((drone.id, drone_brand));
 
             // Storing it for future usages
-            self.drone_channels.insert(drone.id, packet_sender);
-
-            // Clone sender for drone events
-            let drone_events_sender_clone = self.simulation_controller.drone_event_sender.clone();
-
-            // Prepare parameters array for the macro
-
-            let drone_params = (
-                drone.id.clone(),
-                drone_events_sender_clone,
-                command_receiver,
-                packet_receiver,
-                HashMap::new(),
-                drone.pdr,
-            );
-
-            // Use helper function or macro (in this case function) to create and spawn drones based on their brand
-            match self.choose_drone_brand_evenly() {
-                //DroneBrand::KrustyDrone => self.create_and_spawn_drone::<RustyDrone>(drone_params),
-                DroneBrand::RustyDrone => {
-                    self.create_and_spawn_drone::<RustyDrone>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::RustyDrone,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::RollingDrones => {
-                    self.create_and_spawn_drone::<RollingDrone>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::RollingDrones,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::Rustable => {
-                    self.create_and_spawn_drone::<RustableDrone>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::Rustable,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::RustBusters => {
-                    self.create_and_spawn_drone::<RustBustersDrone>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone{
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::RustBusters,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::RustEze => {
-                    self.create_and_spawn_drone::<RustezeDrone>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::RustEze,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::Fungi => {
-                    self.create_and_spawn_drone::<FungiDrone>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::Fungi,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::BagelBomber => {
-                    self.create_and_spawn_drone::<BagelBomber>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::BagelBomber,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::SkyLink => {
-                    self.create_and_spawn_drone::<SkyLinkDrone>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::SkyLink,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::RustAndFurious => {
-                    self.create_and_spawn_drone::<RustAndFurious>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::RustAndFurious,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                DroneBrand::BobryWLucie => {
-                    self.create_and_spawn_drone::<BoberDrone>(drone_params.clone());
-                    self.simulation_controller.drones_data.insert(drone_params.0, DisplayDataDrone {
-                        node_id: drone_params.0,
-                        node_type: SpecificNodeType::Drone,
-                        drone_brand: DroneBrand::BobryWLucie,
-                        connected_nodes_ids: Vec::new(),
-                        pdr: drone_params.5,
-                    });
-                },
-                _ => {}
+            if let Some(packet_sender) = self.simulation_controller.packet_senders.get(&drone.id) {
+                self.drone_channels.insert(drone.id, packet_sender.clone());
             }
         }
-    }
-    fn create_and_spawn_drone<T>(
-        &mut self,
-        drone_params: (
-            DroneId,
-            Sender<DroneEvent>,
-            Receiver<DroneCommand>,
-            Receiver<Packet>,
-            HashMap<NodeId, Sender<Packet>>,
-            f32,
-        ),
-    ) where
-        T: TraitDrone + Send + 'static, // Ensure T implements the Drone trait and is Sendable
-    {
-        let (drone_id, event_sender, cmd_receiver, pkt_receiver, pkt_senders, pdr) = drone_params;
-
-        let drone_instance = self.simulation_controller.create_drone::<T>(
-            drone_id,
-            event_sender,
-            cmd_receiver,
-            pkt_receiver,
-            pkt_senders,
-            pdr,
-        );
-
-        thread::spawn(move || {
-            match drone_instance {
-                Ok(mut drone) => drone.run(),
-                Err(e) => panic!("Failed to run drone {}: {}", drone_id, e),
-            }
-        });
-    }
 
-    fn choose_drone_brand_evenly(&mut self) -> DroneBrand {
-        // Transform the DroneBrand enum into iterator and then collect into a vector
-        let drone_brands = DroneBrand::iter().collect::<Vec<_>>();
-        // We retain the Brands that are least used.
-        if let Some(&min_usage) = self.drone_brand_usage.values().min() {
-            let min_usage_drone_brands: Vec<_> = drone_brands
-                .iter()
-                .filter(|&&drone_brand| self.drone_brand_usage.get(&drone_brand) == Some(&min_usage))
-                .cloned()
-                .collect();
-            // From those we choose randomly one Brand and we use it
-            if let Some(&chosen_brand) = min_usage_drone_brands.choose(&mut thread_rng()) {
-                // Update usage count
-                if let Some(usage) = self.drone_brand_usage.get_mut(&chosen_brand) {
-                    *usage += 1;
-                }
-                return chosen_brand;
-            }
-        }
-        //Shouldn't happen
-        DroneBrand::Fungi
+        chosen_brands.sort_by_key(|(drone_id, _)| *drone_id);
+        drone_brands(&chosen_brands);
     }
     ///CLIENTS GENERATION
     fn create_clients(
         &mut self,
         clients: Vec<Client>,
+        extensions: &NetworkExtensions,
     ) {
+        let send_window = extensions.send_window.size();
         for client in clients {
             // Create command channel between controller and clients
             let (command_sender, command_receiver) = unbounded();
@@ -392,17 +219,27 @@ impl NetworkInitializer {
                 HashMap::new(),
                 );
 
+            // The controller needs it to connect the nodes added at runtime
+            self.simulation_controller.packet_senders.insert(client.id, packet_sender.clone());
+
+            let chosen_type = match self.pinned_client_types.get(&client.id).copied() {
+                Some(pinned_type) => {
+                    *self.client_type_usage.entry(pinned_type).or_default() += 1;
+                    pinned_type
+                }
+                None => self.choose_client_type_evenly(),
+            };
             let client_type;
-            match self.choose_client_type_evenly() {
+            match chosen_type {
                 ClientType::Web => {
                     client_type = ClientType::Web;
-                    self.create_and_spawn_client_with_monitoring::<ClientChen>(client_params);
+                    self.create_and_spawn_client_with_monitoring::<ClientChen>(client_params, send_window);
                     self.client_channels.insert(client.id, (packet_sender , ClientType::Web));
                 },
 
                 ClientType::Chat=> {
                     client_type = ClientType::Chat;
-                    self.create_and_spawn_client_with_monitoring::<ChatClientDanylo>(client_params);
+                    self.create_and_spawn_client_with_monitoring::<ChatClientDanylo>(client_params, send_window);
                     self.client_channels.insert(client.id, (packet_sender , ClientType::Chat));
                 }
             };
@@ -423,7 +260,7 @@ impl NetworkInitializer {
                 .cloned()
                 .collect();
             // From those we choose randomly one Brand and we use it
-            if let Some(&chosen_type) = min_usage_client_types.choose(&mut thread_rng()) {
+            if let Some(&chosen_type) = min_usage_client_types.choose(&mut self.rng) {
                 // Update usage count
                 if let Some(usage) = self.client_type_usage.get_mut(&chosen_type) {
                     *usage += 1;
@@ -444,6 +281,7 @@ impl NetworkInitializer {
             Receiver<Packet>,
             HashMap<NodeId, Sender<Packet>>,
         ),
+        send_window: usize,
     ) where
         T: TraitClient + Send + 'static, // Ensure T implements the Client trait and is Sendable
     {
@@ -456,6 +294,7 @@ impl NetworkInitializer {
             event_sender,
             cmd_receiver,
         );
+        client_instance.set_send_window(send_window);
 
         thread::spawn(move || {
             client_instance.run();
@@ -471,6 +310,7 @@ impl NetworkInitializer {
             Receiver<Packet>,
             HashMap<NodeId, Sender<Packet>>,
         ),
+        send_window: usize,
     ) {
         let (client_id, event_sender, cmd_receiver, pkt_receiver, pkt_senders) = client_params;
 
@@ -481,6 +321,7 @@ impl NetworkInitializer {
             event_sender,
             cmd_receiver,
         );
+        client_instance.set_send_window(send_window);
 
         thread::spawn( move|| {
             client_instance.run_with_monitoring();
@@ -488,14 +329,38 @@ impl NetworkInitializer {
     }
 
     /// SERVERS GENERATION
+    /// The servers without a `[[server_role]]` get Communication, Media and Text in turn.
+    /// Fails if the directory of a `[[server_role]]` cannot be loaded.
     pub fn create_servers(
         &mut self,
         servers: Vec<Server>,
-    ) {
-        let mut vec_files = Vec::new();
-        let mut counter = 0;
+        extensions: &NetworkExtensions,
+    ) -> Result<(), String> {
+        let server_types: Vec<ServerType> = servers.iter()
+            .enumerate()
+            .map(|(counter, server)| extensions.server_type(server.id).unwrap_or(match counter % 3 {
+                0 => ServerType::Communication,
+                1 => ServerType::Media,
+                _ => ServerType::Text,
+            }))
+            .collect();
+
+        // The texts are chosen first, the media servers serve the media of the texts
+        let mut texts: HashMap<ServerId, Vec<(String, String)>> = HashMap::new();
+        for (server, _) in servers.iter().zip(&server_types).filter(|(_, server_type)| **server_type == ServerType::Text) {
+            let role = extensions.server_role(server.id);
+            // The files of a directory are loaded by the server itself
+            if role.is_some_and(|role| role.directory.is_some()) {
+                continue;
+            }
+            let vec_files = match role.and_then(|role| role.texts.as_ref()) {
+                Some(titles) => content::get_texts(titles),
+                None => content::choose_random_texts(&mut self.rng),
+            };
+            texts.insert(server.id, vec_files);
+        }
 
-        for server in servers {
+        for (server, server_type) in servers.into_iter().zip(server_types) {
             let (command_sender, command_receiver) = unbounded();
 
             // Creating sender to this server and receiver of this server
@@ -503,16 +368,14 @@ impl NetworkInitializer {
 
             // Clone sender for server events
             let server_events_sender_clone = self.simulation_controller.server_event_sender.clone();
-            //Choosing type
-            let server_type;
             //Fast fix on many servers
             let mut server_instance_comm: Option<CommunicationServer> = None;
             let mut server_instance_text: Option<TextServer>= None;
             let mut server_instance_media: Option<MediaServer>= None;
 
-            if counter % 3 == 0 {
-                server_type = ServerType::Communication;
-
+            // The text and media servers with a directory load their files themselves
+            let directory = extensions.server_role(server.id).and_then(|role| role.directory.as_ref());
+            if server_type == ServerType::Communication {
                 server_instance_comm = Some(CommunicationServer::new(
                     server.id,
                     server_events_sender_clone,
@@ -521,9 +384,25 @@ impl NetworkInitializer {
                     HashMap::new(),
                 ));
 
-            } else if counter % 3 == 1 {
-                let content = content::get_media(vec_files.clone());
-                server_type = ServerType::Media;
+            } else if let (ServerType::Media, Some(directory)) = (server_type, directory) {
+                match MediaServer::from_directory(
+                    server.id,
+                    directory,
+                    server_events_sender_clone,
+                    command_receiver,
+                    packet_receiver,
+                    HashMap::new(),
+                ) {
+                    Ok(server_instance) => server_instance_media = Some(server_instance),
+                    Err(e) => panic!("Failed to create media server {}: {}", server.id, e),
+                }
+            } else if server_type == ServerType::Media {
+                let media_for = extensions.server_role(server.id).and_then(|role| role.media_for.clone());
+                let vec_files: Vec<(String, String)> = texts.iter()
+                    .filter(|(text_server, _)| media_for.as_ref().map_or(true, |media_for| media_for.contains(text_server)))
+                    .flat_map(|(_, vec_files)| vec_files.iter().cloned())
+                    .collect();
+                let content = content::get_media(vec_files);
 
                 server_instance_media = Some(MediaServer::new(
                     server.id,
@@ -533,13 +412,22 @@ impl NetworkInitializer {
                     packet_receiver,
                     HashMap::new(),
                 ));
+            } else if let Some(directory) = directory {
+                match TextServer::from_directory(
+                    server.id,
+                    directory,
+                    server_events_sender_clone,
+                    command_receiver,
+                    packet_receiver,
+                    HashMap::new(),
+                ) {
+                    Ok(server_instance) => server_instance_text = Some(server_instance),
+                    Err(e) => return Err(format!("[[server_role]] {}: unable to create the text server: {}", server.id, e)),
+                }
             } else{
-                vec_files = content::choose_random_texts();
-                server_type = ServerType::Text;
-
                 server_instance_text = Some(TextServer::new(
                     server.id,
-                    vec_files.iter().cloned().collect::<HashMap<String, String>>(),
+                    texts.get(&server.id).cloned().unwrap_or_default().into_iter().collect::<HashMap<String, String>>(),
                     server_events_sender_clone,
                     command_receiver,
                     packet_receiver,
@@ -547,7 +435,23 @@ impl NetworkInitializer {
                 ));
             };
 
+            let drone_repair_timeout = extensions.drone_repair.timeout();
+            let send_window = extensions.send_window.size();
+            if let Some(server_instance) = server_instance_comm.as_mut() {
+                server_instance.drone_repair_timeout = drone_repair_timeout;
+                server_instance.send_window = send_window;
+            }
+            if let Some(server_instance) = server_instance_media.as_mut() {
+                server_instance.drone_repair_timeout = drone_repair_timeout;
+                server_instance.send_window = send_window;
+            }
+            if let Some(server_instance) = server_instance_text.as_mut() {
+                server_instance.drone_repair_timeout = drone_repair_timeout;
+                server_instance.send_window = send_window;
+            }
+
             self.simulation_controller.register_server(server.id, command_sender, server_type);
+            self.simulation_controller.packet_senders.insert(server.id, packet_sender.clone());
             self.server_channels.insert(server.id, (packet_sender, server_type));
 
             // Create and run server
@@ -571,10 +475,10 @@ impl NetworkInitializer {
                     _=> panic!("what?")
                 }
             );
-            counter += 1;
         }
 
         //Comment: when you are running the run_with_monitoring use the tokio:spawn
+        Ok(())
     }
 
 
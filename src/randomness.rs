@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Seed of the whole simulation, every generator the project controls is derived from it.
+/// The drones of the other groups use their own `thread_rng`, so their drops cannot be replayed.
+static SEED: OnceLock<u64> = OnceLock::new();
+
+/// Sets the global seed, it must be called before the network is built.
+/// Returns the seed already in use if it was set (or read) before.
+pub fn set_seed(seed: u64) -> Result<(), u64> {
+    SEED.set(seed).map_err(|_| self::seed())
+}
+
+/// Returns the global seed, choosing a random one if nobody has set it.
+pub fn seed() -> u64 {
+    *SEED.get_or_init(random_seed)
+}
+
+/// Chooses a seed that can be written back in a topology file, TOML integers are signed.
+pub fn random_seed() -> u64 {
+    rand::random::<u64>() & i64::MAX as u64
+}
+
+/// Returns a generator for one consumer of randomness.
+/// Each stream gets its own sequence, so adding a consumer does not change the values of the others.
+pub fn seeded_rng(stream: &str) -> StdRng {
+    StdRng::seed_from_u64(seed() ^ stream_hash(stream))
+}
+
+/// Reads the optional top level `seed = <u64>` of a network initialization file.
+pub fn seed_from_topology<P: AsRef<Path>>(path: P) -> Result<Option<u64>, String> {
+    let path = path.as_ref();
+    let data = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let table = data.parse::<toml::Table>()
+        .map_err(|e| format!("Unable to parse {}: {}", path.display(), e))?;
+
+    match table.get("seed") {
+        None => Ok(None),
+        Some(toml::Value::Integer(seed)) if *seed >= 0 => Ok(Some(*seed as u64)),
+        Some(value) => Err(format!("Invalid seed {} in {}, expected a non negative integer", value, path.display())),
+    }
+}
+
+//FNV-1a, stable between builds unlike the std hasher
+fn stream_hash(stream: &str) -> u64 {
+    stream.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
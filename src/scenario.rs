@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use wg_2024::{
+    network::NodeId,
+};
+use crate::general_use::{ClientId, ClientType, DroneId, FileRef, MediaRef, ServerId};
+use crate::simulation_controller::PacketLogFilter;
+use crate::websocket::WsCommand;
+
+const DEFAULT_WITHIN_MS: u64 = 5000;
+
+fn default_within_ms() -> u64 {
+    DEFAULT_WITHIN_MS
+}
+
+/// A list of timed steps read from a TOML file.
+///
+/// ```toml
+/// name = "crash a drone and ask a file list"
+/// seed = 42
+///
+/// [[client_type]]
+/// id = 5
+/// type = "Web"
+///
+/// [[step]]
+/// at_ms = 1000
+/// action = { type = "CrashDrone", drone_id = 3 }
+/// expect = { type = "DroneCrashed", drone_id = 3, within_ms = 500 }
+///
+/// [[step]]
+/// at_ms = 2000
+/// action = { type = "AskFileList", client_id = 5, server_id = 8 }
+/// expect = { type = "FileListReceived", client_id = 5, within_ms = 3000 }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    pub seed: Option<u64>,              // Used when --seed is not given
+    #[serde(default, rename = "client_type")]
+    pub client_types: Vec<PinnedClientType>,
+    #[serde(default, rename = "step")]
+    pub steps: Vec<ScenarioStep>,
+}
+
+/// Type given to a client instead of the random one, so the steps do not depend on the seed.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PinnedClientType {
+    pub id: ClientId,
+    #[serde(rename = "type")]
+    pub client_type: ClientType,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScenarioStep {
+    pub at_ms: u64,                     // Time from the start of the scenario
+    pub action: ScenarioAction,
+    pub expect: Option<Expectation>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum ScenarioAction {
+    CrashDrone { drone_id: DroneId },
+    SetPdr { drone_id: DroneId, pdr: f32 },
+    SendMessage { from: ClientId, to: ClientId, message: String },
+    AskFileList { client_id: ClientId, server_id: ServerId },
+    AskFile { client_id: ClientId, server_id: ServerId, file: FileRef },
+    AskMedia { client_id: ClientId, media_ref: MediaRef },
+    ExportPacketLog { file_name: String },
+    Wait,
+}
+
+/// Assertion checked against the data sent to the GUI after the action of the step.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum Expectation {
+    DroneCrashed {
+        drone_id: DroneId,
+        #[serde(default = "default_within_ms")]
+        within_ms: u64,
+    },
+    DroneNotCrashed {
+        drone_id: DroneId,
+        #[serde(default = "default_within_ms")]
+        within_ms: u64,
+    },
+    FileListReceived {
+        client_id: ClientId,
+        file: Option<FileRef>,
+        #[serde(default = "default_within_ms")]
+        within_ms: u64,
+    },
+    FileReceived {
+        client_id: ClientId,
+        contains: Option<String>,
+        #[serde(default = "default_within_ms")]
+        within_ms: u64,
+    },
+    MediaReceived {
+        client_id: ClientId,
+        media_ref: MediaRef,
+        #[serde(default = "default_within_ms")]
+        within_ms: u64,
+    },
+    MessageReceived {
+        client_id: ClientId,
+        from: ClientId,
+        message: String,
+        #[serde(default = "default_within_ms")]
+        within_ms: u64,
+    },
+}
+
+impl Scenario {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read scenario file {}: {}", path.display(), e))?;
+        let mut scenario: Scenario = toml::from_str(&data)
+            .map_err(|e| format!("Unable to parse scenario file {}: {}", path.display(), e))?;
+
+        // Steps are executed in time order, steps with the same time keep the file order
+        scenario.steps.sort_by_key(|step| step.at_ms);
+        Ok(scenario)
+    }
+
+    /// The client types pinned by the scenario, by client.
+    pub fn pinned_client_types(&self) -> HashMap<ClientId, ClientType> {
+        self.client_types.iter().map(|pinned| (pinned.id, pinned.client_type)).collect()
+    }
+}
+
+impl Display for ScenarioAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioAction::CrashDrone { drone_id } => write!(f, "crash drone {}", drone_id),
+            ScenarioAction::SetPdr { drone_id, pdr } => write!(f, "set PDR of drone {} to {}", drone_id, pdr),
+            ScenarioAction::SendMessage { from, to, .. } => write!(f, "client {} sends a message to client {}", from, to),
+            ScenarioAction::AskFileList { client_id, server_id } => write!(f, "client {} asks the file list to server {}", client_id, server_id),
+            ScenarioAction::AskFile { client_id, server_id, file } => write!(f, "client {} asks file {} to server {}", client_id, file, server_id),
+            ScenarioAction::AskMedia { client_id, media_ref } => write!(f, "client {} asks media {}", client_id, media_ref),
+            ScenarioAction::ExportPacketLog { file_name } => write!(f, "export the packet log to {}", file_name),
+            ScenarioAction::Wait => write!(f, "wait"),
+        }
+    }
+}
+
+impl Display for Expectation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expectation::DroneCrashed { drone_id, within_ms } => write!(f, "drone {} crashed within {} ms", drone_id, within_ms),
+            Expectation::DroneNotCrashed { drone_id, within_ms } => write!(f, "drone {} refused to crash within {} ms", drone_id, within_ms),
+            Expectation::FileListReceived { client_id, file, within_ms } => match file {
+                Some(file) => write!(f, "client {} received a file list with {} within {} ms", client_id, file, within_ms),
+                None => write!(f, "client {} received a file list within {} ms", client_id, within_ms),
+            },
+            Expectation::FileReceived { client_id, within_ms, .. } => write!(f, "client {} received the file within {} ms", client_id, within_ms),
+            Expectation::MediaReceived { client_id, media_ref, within_ms } => write!(f, "client {} received media {} within {} ms", client_id, media_ref, within_ms),
+            Expectation::MessageReceived { client_id, from, within_ms, .. } => write!(f, "client {} received the message of client {} within {} ms", client_id, from, within_ms),
+        }
+    }
+}
+
+impl Expectation {
+    fn within(&self) -> Duration {
+        let within_ms = match self {
+            Expectation::DroneCrashed { within_ms, .. }
+            | Expectation::DroneNotCrashed { within_ms, .. }
+            | Expectation::FileListReceived { within_ms, .. }
+            | Expectation::FileReceived { within_ms, .. }
+            | Expectation::MediaReceived { within_ms, .. }
+            | Expectation::MessageReceived { within_ms, .. } => *within_ms,
+        };
+        Duration::from_millis(within_ms)
+    }
+
+    /// Checks one of the json messages sent by the controller to the GUI.
+    fn is_satisfied_by(&self, json: &Value) -> bool {
+        match self {
+            Expectation::DroneCrashed { drone_id, .. } => {
+                json.get("DroneCrashed").and_then(Value::as_u64) == Some(*drone_id as u64)
+            }
+            Expectation::DroneNotCrashed { drone_id, .. } => {
+                json.get("NotCrashed").and_then(Value::as_u64) == Some(*drone_id as u64)
+            }
+            Expectation::FileListReceived { client_id, file, .. } => {
+                node_data(json, *client_id).iter().any(|data| {
+                    let Some(list) = data.get("curr_received_file_list").and_then(Value::as_array) else {
+                        return false;
+                    };
+                    match file {
+                        Some(file) => list.iter().any(|entry| entry.as_str() == Some(file.as_str())),
+                        None => !list.is_empty(),
+                    }
+                })
+            }
+            Expectation::FileReceived { client_id, contains, .. } => {
+                node_data(json, *client_id).iter().any(|data| {
+                    let Some(text) = data.get("chosen_file_text").and_then(Value::as_str) else {
+                        return false;
+                    };
+                    match contains {
+                        Some(contains) => text.contains(contains.as_str()),
+                        None => !text.is_empty(),
+                    }
+                })
+            }
+            Expectation::MediaReceived { client_id, media_ref, .. } => {
+                node_data(json, *client_id).iter().any(|data| {
+                    data.get("serialized_media")
+                        .and_then(|media| media.get(media_ref.as_str()))
+                        .is_some()
+                })
+            }
+            Expectation::MessageReceived { client_id, from, message, .. } => {
+                node_data(json, *client_id).iter().any(|data| {
+                    data.get("chats")
+                        .and_then(|chats| chats.get(from.to_string()))
+                        .and_then(Value::as_array)
+                        .map_or(false, |chat| chat.iter().any(|entry| {
+                            entry.get(0).and_then(Value::as_str) == Some("HimOrHer")
+                                && entry.get(1).and_then(Value::as_str) == Some(message.as_str())
+                        }))
+                })
+            }
+        }
+    }
+}
+
+/// Returns the monitoring data of a node found in a json message, both when the node sent it alone
+/// (UpdateSelf) and when it is inside the data of the whole network.
+fn node_data(json: &Value, node_id: NodeId) -> Vec<&Value> {
+    let mut found = Vec::new();
+    if json.get("node_id").and_then(Value::as_u64) == Some(node_id as u64) {
+        found.push(json);
+    }
+    for key in ["web_clients_data", "chat_clients_data"] {
+        if let Some(data) = json.get(key).and_then(|nodes| nodes.get(node_id.to_string())) {
+            found.push(data);
+        }
+    }
+    found
+}
+
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub index: usize,
+    pub description: String,
+    pub passed: bool,
+    pub elapsed: Option<Duration>,      // Time needed to satisfy the expectation
+    pub detail: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScenarioReport {
+    pub name: String,
+    pub results: Vec<StepResult>,
+}
+
+impl ScenarioReport {
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|result| result.passed)
+    }
+}
+
+impl Display for ScenarioReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Scenario: {}", self.name)?;
+        for result in &self.results {
+            let status = if result.passed { "PASS" } else { "FAIL" };
+            write!(f, "  [{}] step {}: {}", status, result.index + 1, result.description)?;
+            if let Some(elapsed) = result.elapsed {
+                write!(f, " ({} ms)", elapsed.as_millis())?;
+            }
+            if !result.detail.is_empty() {
+                write!(f, " - {}", result.detail)?;
+            }
+            writeln!(f)?;
+        }
+        let passed = self.results.iter().filter(|result| result.passed).count();
+        write!(f, "{}/{} steps passed", passed, self.results.len())
+    }
+}
+
+/// Drives a running `SimulationController` through the same commands used by the web interface,
+/// and checks the expectations on the data that the controller sends to the GUI.
+pub struct ScenarioRunner {
+    ws_sender: Sender<WsCommand>,
+    gui_receiver: Receiver<String>,
+}
+
+impl ScenarioRunner {
+    pub fn new(ws_sender: Sender<WsCommand>, gui_receiver: Receiver<String>) -> Self {
+        Self {
+            ws_sender,
+            gui_receiver,
+        }
+    }
+
+    pub fn run(&mut self, scenario: &Scenario) -> ScenarioReport {
+        info!("Running scenario '{}' with {} steps", scenario.name, scenario.steps.len());
+        let start = Instant::now();
+        let mut results = Vec::new();
+
+        for (index, step) in scenario.steps.iter().enumerate() {
+            // Wait for the time of the step, discarding what happened before it
+            self.drain_until(start + Duration::from_millis(step.at_ms));
+
+            let mut description = step.action.to_string();
+            if let Some(expectation) = &step.expect {
+                description = format!("{}, expecting {}", description, expectation);
+            }
+
+            debug!("Scenario step {}: {}", index + 1, description);
+            if let Err(e) = self.execute(&step.action) {
+                results.push(StepResult { index, description, passed: false, elapsed: None, detail: e });
+                continue;
+            }
+
+            let result = match &step.expect {
+                Some(expectation) => match self.wait_for(expectation) {
+                    Some(elapsed) => StepResult { index, description, passed: true, elapsed: Some(elapsed), detail: String::new() },
+                    None => StepResult { index, description, passed: false, elapsed: None, detail: "timed out".to_string() },
+                },
+                None => StepResult { index, description, passed: true, elapsed: None, detail: String::new() },
+            };
+            results.push(result);
+        }
+
+        ScenarioReport { name: scenario.name.clone(), results }
+    }
+
+    fn execute(&self, action: &ScenarioAction) -> Result<(), String> {
+        let command = match action.clone() {
+            ScenarioAction::CrashDrone { drone_id } => WsCommand::WsCrashDrone { drone_id },
+            ScenarioAction::SetPdr { drone_id, pdr } => WsCommand::WsSetPdr { drone_id, pdr },
+            ScenarioAction::SendMessage { from, to, message } => WsCommand::WsSendMessage {
+                source_client_id: from,
+                dest_client_id: to,
+                message,
+            },
+            ScenarioAction::AskFileList { client_id, server_id } => WsCommand::WsAskFileList { client_id, server_id },
+            ScenarioAction::AskFile { client_id, server_id, file } => WsCommand::WsAskFileContent {
+                client_id,
+                server_id,
+                file_ref: file,
+            },
+            ScenarioAction::AskMedia { client_id, media_ref } => WsCommand::WsAskMedia { client_id, media_ref },
+            ScenarioAction::ExportPacketLog { file_name } => WsCommand::WsExportPacketLog { file_name, filter: PacketLogFilter::default() },
+            ScenarioAction::Wait => return Ok(()),
+        };
+        self.ws_sender.send(command).map_err(|e| format!("Failed to send command to the controller: {}", e))
+    }
+
+    /// Returns the time needed to satisfy the expectation, or None if it was not satisfied in time.
+    fn wait_for(&self, expectation: &Expectation) -> Option<Duration> {
+        let start = Instant::now();
+        let deadline = start + expectation.within();
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.gui_receiver.recv_timeout(timeout) {
+                Ok(data) => {
+                    let Ok(json) = serde_json::from_str::<Value>(&data) else {
+                        continue;
+                    };
+                    if expectation.is_satisfied_by(&json) {
+                        return Some(start.elapsed());
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return None,
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("Scenario runner: the controller stopped sending data");
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn drain_until(&self, deadline: Instant) {
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            if let Err(RecvTimeoutError::Disconnected) = self.gui_receiver.recv_timeout(timeout) {
+                thread::sleep(timeout);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+
+    fn parse(data: &str) -> Scenario {
+        toml::from_str(data).expect("valid scenario")
+    }
+
+    #[test]
+    fn shipped_scenario_is_parsed_in_time_order() {
+        let scenario = Scenario::from_file("scenarios/tree_smoke.toml").unwrap();
+        assert_eq!(scenario.seed, Some(7));
+        assert_eq!(scenario.pinned_client_types().get(&15), Some(&ClientType::Web));
+        assert!(scenario.steps.windows(2).all(|pair| pair[0].at_ms <= pair[1].at_ms));
+    }
+
+    #[test]
+    fn steps_and_expectations_are_parsed() {
+        let scenario = parse(r#"
+            name = "parsing"
+
+            [[client_type]]
+            id = 5
+            type = "Chat"
+
+            [[step]]
+            at_ms = 100
+            action = { type = "SendMessage", from = 5, to = 6, message = "hi" }
+            expect = { type = "MessageReceived", client_id = 6, from = 5, message = "hi", within_ms = 250 }
+
+            [[step]]
+            at_ms = 50
+            action = { type = "AskFile", client_id = 6, server_id = 8, file = "a.txt" }
+            expect = { type = "FileReceived", client_id = 6 }
+
+            [[step]]
+            at_ms = 200
+            action = { type = "Wait" }
+        "#);
+        assert_eq!(scenario.name, "parsing");
+        assert_eq!(scenario.seed, None);
+        assert_eq!(scenario.pinned_client_types().get(&5), Some(&ClientType::Chat));
+        assert_eq!(scenario.steps.len(), 3);
+
+        assert!(matches!(&scenario.steps[0].action, ScenarioAction::SendMessage { from: 5, to: 6, message } if message == "hi"));
+        assert!(matches!(scenario.steps[0].expect, Some(Expectation::MessageReceived { client_id: 6, from: 5, within_ms: 250, .. })));
+        assert!(matches!(&scenario.steps[1].action, ScenarioAction::AskFile { client_id: 6, server_id: 8, file } if file == "a.txt"));
+        // Without within_ms the default wait is used
+        assert!(matches!(scenario.steps[1].expect, Some(Expectation::FileReceived { client_id: 6, contains: None, within_ms: DEFAULT_WITHIN_MS })));
+        assert!(matches!(scenario.steps[2].action, ScenarioAction::Wait));
+        assert!(scenario.steps[2].expect.is_none());
+    }
+
+    #[test]
+    fn unknown_actions_are_refused() {
+        let result = toml::from_str::<Scenario>(r#"
+            [[step]]
+            at_ms = 0
+            action = { type = "Explode" }
+        "#);
+        assert!(result.is_err());
+    }
+
+    /// A runner fed with the given GUI messages, the commands it sends are returned by the receiver.
+    fn runner_with_gui_messages(messages: &[&str]) -> (ScenarioRunner, Receiver<WsCommand>, Sender<String>) {
+        let (ws_sender, ws_receiver) = unbounded();
+        let (gui_sender, gui_receiver) = unbounded();
+        for message in messages {
+            gui_sender.send(message.to_string()).unwrap();
+        }
+        (ScenarioRunner::new(ws_sender, gui_receiver), ws_receiver, gui_sender)
+    }
+
+    #[test]
+    fn expectation_is_met_by_a_later_gui_message() {
+        let (runner, _, _gui_sender) = runner_with_gui_messages(&[
+            "not json",
+            r#"{"DroneCrashed": 4}"#,
+            r#"{"web_clients_data": {"15": {"node_id": 15, "curr_received_file_list": ["a.txt", "b.txt"]}}}"#,
+        ]);
+        let expectation = Expectation::FileListReceived { client_id: 15, file: Some("b.txt".to_string()), within_ms: 1000 };
+        let elapsed = runner.wait_for(&expectation).expect("the file list was received");
+        assert!(elapsed < Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn expectation_times_out_after_within_ms() {
+        let (runner, _, _gui_sender) = runner_with_gui_messages(&[
+            r#"{"DroneCrashed": 4}"#,
+            r#"{"node_id": 15, "curr_received_file_list": []}"#,
+        ]);
+        let expectation = Expectation::FileListReceived { client_id: 15, file: None, within_ms: 50 };
+        let start = Instant::now();
+        assert_eq!(runner.wait_for(&expectation), None);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn steps_pass_and_fail_on_the_gui_messages() {
+        let scenario = parse(r#"
+            name = "crashes"
+
+            [[step]]
+            at_ms = 0
+            action = { type = "CrashDrone", drone_id = 3 }
+            expect = { type = "DroneCrashed", drone_id = 3, within_ms = 1000 }
+
+            [[step]]
+            at_ms = 0
+            action = { type = "CrashDrone", drone_id = 4 }
+            expect = { type = "DroneCrashed", drone_id = 4, within_ms = 50 }
+        "#);
+        let (mut runner, ws_receiver, gui_sender) = runner_with_gui_messages(&[]);
+
+        // Only drone 3 crashes
+        let controller = thread::spawn(move || {
+            while let Ok(command) = ws_receiver.recv() {
+                if let WsCommand::WsCrashDrone { drone_id } = command {
+                    let answer = if drone_id == 3 { "DroneCrashed" } else { "NotCrashed" };
+                    gui_sender.send(format!(r#"{{"{}": {}}}"#, answer, drone_id)).unwrap();
+                }
+            }
+        });
+
+        let report = runner.run(&scenario);
+        drop(runner);
+        controller.join().unwrap();
+
+        assert_eq!(report.results.len(), 2);
+        assert!(report.results[0].passed);
+        assert!(!report.results[1].passed);
+        assert_eq!(report.results[1].detail, "timed out");
+        assert!(!report.all_passed());
+    }
+}
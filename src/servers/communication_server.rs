@@ -1,9 +1,10 @@
-use crossbeam_channel::{select_biased, Receiver, Sender};
+use crossbeam_channel::{select_biased, tick, Receiver, Sender};
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     fmt::Debug,
+    time::Duration,
 };
-use log::{error, info, warn};
+use log::{info, warn};
 use crate::general_use::{
     DataScope, DisplayDataCommunicationServer, Message, Query, Response, ServerCommand, ServerEvent,
     ServerType, SpecificNodeType
@@ -18,6 +19,10 @@ use crate::clients::client_chen::NodeType;
 use crate::general_use::DataScope::{UpdateAll, UpdateSelf};
 use super::server::CommunicationServer as CharTrait;
 use super::server::Server as MainTrait;
+use crate::fragmentation::{OutgoingMessage, Reassembler, Throughput, DEFAULT_SEND_WINDOW, MAX_QUERY_FRAGMENTS, RETRANSMISSION_CHECK_INTERVAL};
+use crate::simulation_controller::STATISTICS_INTERVAL;
+use crate::link_quality::LinkQuality;
+use super::drone_repair::{DroneRepair, DEFAULT_DRONE_REPAIR_TIMEOUT, REPAIR_CHECK_INTERVAL};
 
 type FloodId = u64;
 type SessionId = u64;
@@ -29,19 +34,26 @@ pub struct CommunicationServer{
     pub id: NodeId,
 
     //Fragment-related
-    pub reassembling_messages: HashMap<SessionId, Vec<u8>>,
-    pub sending_messages: HashMap<SessionId, (Vec<u8>, NodeId)>,
+    pub reassembler: Reassembler,                               // Queries by sender and session
+    pub sending_messages: HashMap<SessionId, OutgoingMessage>,  // Responses waiting for their acks
 
     //Flood-related
     pub clients: HashSet<NodeId>,                               // Available clients
     pub topology: HashMap<NodeId, HashSet<NodeId>>,             // Nodes and their neighbours
     pub nodes: HashMap<NodeId, NodeType>,                       // Nodes and their types
     pub routes: HashMap<NodeId, Vec<NodeId>>,                   // Routes to the servers
+    pub backup_routes: HashMap<NodeId, Vec<NodeId>>,            // Routes sharing no drone with the primary ones, for the failover
+    pub backup_routes_to_repair: HashSet<NodeId>,               // Clients whose backup route has to be computed again
     pub flood_ids: Vec<FloodId>,
     pub counter: (FloodId, SessionId),
 
     //Drop counter
     pub drops_counter: HashMap<SessionId, HashMap<NodeId, u8>>,
+    pub drones_under_repair: HashMap<NodeId, DroneRepair>,      // Drones waiting for the technicians, with the parked fragments
+    pub drone_repair_timeout: Duration,                         // Then the parked fragments are sent anyway
+    pub link_quality: LinkQuality,
+    pub send_window: usize,
+    pub throughput: Throughput,
 
     //Channels
     pub to_controller_event: Sender<ServerEvent>,
@@ -68,13 +80,15 @@ impl CommunicationServer{
         CommunicationServer {
             id,
 
-            reassembling_messages: Default::default(),
+            reassembler: Reassembler::new(MAX_QUERY_FRAGMENTS),
             sending_messages: Default::default(),
 
             clients: Default::default(),                                   // Available clients
             topology: Default::default(),
             nodes: Default::default(),
             routes: Default::default(),
+            backup_routes: Default::default(),
+            backup_routes_to_repair: Default::default(),
             flood_ids: Default::default(),
             counter: (0, 0),
 
@@ -86,6 +100,11 @@ impl CommunicationServer{
             list_users: Vec::new(),
 
             drops_counter: HashMap::new(),
+            drones_under_repair: HashMap::new(),
+            drone_repair_timeout: DEFAULT_DRONE_REPAIR_TIMEOUT,
+            link_quality: LinkQuality::new(),
+            send_window: DEFAULT_SEND_WINDOW,
+            throughput: Throughput::new(),
 
             queries_to_process: VecDeque::new(),
         }
@@ -102,6 +121,9 @@ impl Monitoring for CommunicationServer {
             connected_node_ids: neighbors,
             known_clients: self.clients.clone(),
             routing_table: self.routes.clone(),
+            route_costs: self.link_quality.route_costs(&self.routes),
+            send_window: self.send_window,
+            throughput: self.throughput.fragments_per_second(),
             registered_clients: self.list_users.clone(),
         };
 
@@ -111,6 +133,9 @@ impl Monitoring for CommunicationServer {
         &mut self,
     ) {
         self.send_display_data(UpdateAll);
+        let repair_ticker = tick(REPAIR_CHECK_INTERVAL);
+        let retransmission_ticker = tick(RETRANSMISSION_CHECK_INTERVAL);
+        let throughput_ticker = tick(STATISTICS_INTERVAL);
         loop {
             select_biased! {
                 recv(self.get_from_controller_command()) -> command_res => {
@@ -135,6 +160,9 @@ impl Monitoring for CommunicationServer {
                                 self.handle_packet(packet);
                                 self.send_display_data(UpdateSelf);
                             },
+                            ServerCommand::DroneFixed(drone_id) => {
+                                self.handle_drone_fixed(drone_id);
+                            }
                             _ => {}
                         }
                     }
@@ -145,6 +173,18 @@ impl Monitoring for CommunicationServer {
                         self.send_display_data(UpdateSelf);
                     }
                 },
+                recv(repair_ticker) -> _ => {
+                    self.resume_expired_repairs();
+                    self.repair_backup_routes();
+                },
+                recv(retransmission_ticker) -> _ => {
+                    self.retransmit_timed_out_fragments();
+                },
+                recv(throughput_ticker) -> _ => {
+                    if self.throughput.has_changed() {
+                        self.send_display_data(UpdateSelf);
+                    }
+                },
             }
         }
     }
@@ -169,13 +209,15 @@ impl MainTrait for CommunicationServer{
     fn get_topology(&mut self) -> &mut HashMap<NodeId, HashSet<NodeId>>{ &mut self.topology }
     fn get_nodes(&mut self) -> &mut HashMap<NodeId, NodeType> { &mut self.nodes }
     fn get_routes(&mut self) -> &mut HashMap<NodeId, Vec<NodeId>>{ &mut self.routes }
+    fn get_backup_routes(&mut self) -> &mut HashMap<NodeId, Vec<NodeId>>{ &mut self.backup_routes }
+    fn get_backup_routes_to_repair(&mut self) -> &mut HashSet<NodeId>{ &mut self.backup_routes_to_repair }
 
     fn get_event_sender(&self) -> &Sender<ServerEvent>{ &self.to_controller_event }
     fn get_from_controller_command(&mut self) -> &mut Receiver<ServerCommand>{ &mut self.from_controller_command }
     fn get_packet_recv(&mut self) -> &mut Receiver<Packet>{ &mut self.packet_recv }
     fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>>{ &mut self.packet_send }
     fn get_packet_send_not_mutable(&self) -> &HashMap<NodeId, Sender<Packet>>{ &self.packet_send }
-    fn get_reassembling_messages(&mut self) -> &mut HashMap<u64, Vec<u8>>{ &mut self.reassembling_messages }
+    fn get_reassembler(&mut self) -> &mut Reassembler{ &mut self.reassembler }
     fn process_query(&mut self, query: Query, src_id: NodeId) {
         // Check if there is a route to the client, save query and start the discovery process if it's not.
         if self.routes.get(&src_id).is_none() {
@@ -195,12 +237,22 @@ impl MainTrait for CommunicationServer{
             _ => {}
         }
     }
-    fn get_sending_messages(&mut self) ->  &mut HashMap<u64, (Vec<u8>, u8)>{ &mut self.sending_messages }
+    fn get_sending_messages(&mut self) ->  &mut HashMap<u64, OutgoingMessage>{ &mut self.sending_messages }
 
-    fn get_sending_messages_not_mutable(&self) -> &HashMap<u64, (Vec<u8>, u8)>{ &self.sending_messages }
+    fn get_sending_messages_not_mutable(&self) -> &HashMap<u64, OutgoingMessage>{ &self.sending_messages }
 
     fn get_drops_counter(&mut self) -> &mut HashMap<u64, HashMap<NodeId, u8>>{ &mut self.drops_counter }
 
+    fn get_drones_under_repair(&mut self) -> &mut HashMap<NodeId, DroneRepair>{ &mut self.drones_under_repair }
+
+    fn get_drone_repair_timeout(&self) -> Duration{ self.drone_repair_timeout }
+
+    fn get_link_quality(&mut self) -> &mut LinkQuality{ &mut self.link_quality }
+    fn get_link_quality_not_mutable(&self) -> &LinkQuality{ &self.link_quality }
+
+    fn get_send_window(&self) -> usize{ self.send_window }
+    fn get_throughput(&mut self) -> &mut Throughput{ &mut self.throughput }
+
     fn get_queries_to_process(&mut self) -> &mut VecDeque<(NodeId, Query)>{ &mut self.queries_to_process }
 }
 
@@ -210,31 +262,8 @@ impl CharTrait for CommunicationServer {
 
         let response = Response::ClientRegistered;
 
-        //Serializing message to send
-        let response_as_string = serde_json::to_string(&response).unwrap();
-        let response_in_vec_bytes = response_as_string.as_bytes();
-        let length_response = response_in_vec_bytes.len();
-
-        //Counting fragments
-        let mut n_fragments = length_response / 128+1;
-        if n_fragments == 0 {
-            n_fragments -= 1;
-        }
-
-        // Finding route
-        let Some(route) = self.find_path_to(client_id) else {
-            error!("Server {}: No route found to the client {}", self.get_id(), client_id);
-            return;
-        };
-
-        //Generating header
-        let header = Self::create_source_routing(route);
-
-        // Generating ids
-        let session_id = self.generate_unique_session_id();
-
         //Send fragments
-        self.send_fragments(session_id, n_fragments,response_in_vec_bytes, header);
+        self.send_response(client_id, &response);
     }
 
     fn give_list_back(&mut self, client_id: NodeId) {
@@ -245,31 +274,8 @@ impl CharTrait for CommunicationServer {
         //Creating data to send
         let response = Response::ListClients(list_clients);
 
-        //Serializing message to send
-        let response_as_string = serde_json::to_string(&response).unwrap();
-        let response_in_vec_bytes = response_as_string.as_bytes();
-        let length_response = response_in_vec_bytes.len();
-
-        //Counting fragments
-        let mut n_fragments = length_response / 128+1;
-        if n_fragments == 0 {
-            n_fragments -= 1;
-        }
-
-        // Finding route
-        let Some(route) = self.find_path_to(client_id) else {
-            error!("Server {}: No route found to the client {}", self.get_id(), client_id);
-            return;
-        };
-
-        //Generating header
-        let header = Self::create_source_routing(route);
-
-        // Generating ids
-        let session_id = self.generate_unique_session_id();
-
         //Send fragments
-        self.send_fragments(session_id, n_fragments,response_in_vec_bytes, header);
+        self.send_response(client_id, &response);
 
     }
 
@@ -278,30 +284,7 @@ impl CharTrait for CommunicationServer {
         //Creating data to send
         let response = Response::MessageReceived(message.clone());
 
-        //Serializing message to send
-        let response_as_string = serde_json::to_string(&response).unwrap();
-        let response_in_vec_bytes = response_as_string.as_bytes();
-        let length_response = response_in_vec_bytes.len();
-
-        //Counting fragments
-        let mut n_fragments = length_response / 128+1;
-        if n_fragments == 0 {
-            n_fragments -= 1;
-        }
-
-        // Finding route
-        let recipient_id = message.get_recipient();
-        let Some(route) = self.find_path_to(recipient_id) else {
-            error!("Server {}: No route found to the client {}", self.get_id(), recipient_id);
-            return;
-        };
-
-        //Generating header
-        let header = Self::create_source_routing(route);
-
-        // Generating fragment
-        let session_id = self.generate_unique_session_id();
-
-        self.send_fragments(session_id, n_fragments,response_in_vec_bytes, header);
+        //Send fragments
+        self.send_response(message.get_recipient(), &response);
     }
 }
@@ -1,10 +1,10 @@
 use std::collections::HashMap;
-use rand::{random, thread_rng};
+use rand::Rng;
 use rand::seq::SliceRandom;
 
 const N_FILES: usize = 25;
 
-pub fn choose_random_texts() -> Vec<(String, String)> {
+pub fn choose_random_texts<R: Rng>(rng: &mut R) -> Vec<(String, String)> {
     let trying_closures = |x: u8| {
         if x < 4 {
             return x + 3;
@@ -13,13 +13,13 @@ pub fn choose_random_texts() -> Vec<(String, String)> {
         }
     };
 
-    let n_files = trying_closures(random::<u8>() % (N_FILES as u8));
+    let n_files = trying_closures(rng.gen::<u8>() % (N_FILES as u8));
 
     let mut vec_files: Vec<(String, String)> = Vec::new();
     let mut randomized_indexes: Vec<usize> = (0..TEXT.len()).collect();
-    randomized_indexes.shuffle(&mut thread_rng());
+    randomized_indexes.shuffle(rng);
 
-    if random::<u8>() % 2 == 0 {
+    if rng.gen::<u8>() % 2 == 0 {
         for &i in randomized_indexes.iter().take(n_files as usize) {
             vec_files.push((TEXT[i].0.to_string(), TEXT[i].1.to_string()));
         }
@@ -32,6 +32,14 @@ pub fn choose_random_texts() -> Vec<(String, String)> {
     vec_files
 }
 
+/// Returns the built-in texts with the given titles, unknown titles are skipped.
+pub fn get_texts(titles: &[String]) -> Vec<(String, String)> {
+    TEXT.iter()
+        .filter(|(title, _)| titles.iter().any(|chosen_title| chosen_title == title))
+        .map(|(title, text)| (title.to_string(), text.to_string()))
+        .collect()
+}
+
 pub fn get_media(vec_files: Vec<(String, String)>) -> HashMap<String, String> {
     IMAGE_PATHS.iter().filter_map(|(title, ref_s, media)| {
         // Check if the `title` exists in `vec_files`
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often the servers backed by a directory look for changed files.
+pub const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Files added or changed, with their new bytes, and files removed since the last scan.
+#[derive(Debug, Default)]
+pub struct DirectoryChanges {
+    pub updated: Vec<(String, Vec<u8>)>,
+    pub removed: Vec<String>,
+}
+
+impl DirectoryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Directory whose files are served by a server, keyed by file name.
+/// Only the files directly inside it are served, hidden files and subdirectories are skipped.
+/// A file is read again when its size or modification time changes.
+#[derive(Debug)]
+pub struct ContentDirectory {
+    path: PathBuf,
+    files: HashMap<String, (SystemTime, u64)>,
+}
+
+impl ContentDirectory {
+    /// Opens the directory, every file in it is returned as updated.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<(Self, DirectoryChanges), String> {
+        let mut directory = Self { path: path.as_ref().to_path_buf(), files: HashMap::new() };
+        let changes = directory.rescan()?;
+        Ok((directory, changes))
+    }
+
+    pub fn rescan(&mut self) -> Result<DirectoryChanges, String> {
+        let entries = fs::read_dir(&self.path)
+            .map_err(|e| format!("Unable to read {}: {}", self.path.display(), e))?;
+
+        let mut changes = DirectoryChanges::default();
+        let mut found = HashMap::new();
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if !metadata.is_file() || name.starts_with('.') {
+                continue;
+            }
+
+            let version = (metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), metadata.len());
+            if self.files.get(&name) != Some(&version) {
+                match fs::read(entry.path()) {
+                    Ok(bytes) => changes.updated.push((name.clone(), bytes)),
+                    // Maybe still being written, it is tried again at the next scan
+                    Err(_) => continue,
+                }
+            }
+            found.insert(name, version);
+        }
+
+        changes.removed = self.files.keys()
+            .filter(|name| !found.contains_key(*name))
+            .cloned()
+            .collect();
+        changes.updated.sort_by(|(a, _), (b, _)| a.cmp(b));
+        changes.removed.sort();
+        self.files = found;
+        Ok(changes)
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+use crate::general_use::{FragmentIndex, SessionId};
+
+/// How long the fragments wait for a drone to be fixed, when `[drone_repair]` does not say otherwise.
+pub const DEFAULT_DRONE_REPAIR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the servers look for repairs that are taking too long.
+pub const REPAIR_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Drone for which the technicians were called. The fragments whose route passes through it
+/// are parked until it is fixed, the other sessions keep flowing.
+#[derive(Debug, Clone)]
+pub struct DroneRepair {
+    since: Instant,
+    parked: Vec<(SessionId, FragmentIndex)>,
+}
+
+impl DroneRepair {
+    pub fn new() -> Self {
+        Self { since: Instant::now(), parked: Vec::new() }
+    }
+
+    pub fn park(&mut self, session_id: SessionId, fragment_index: FragmentIndex) {
+        if !self.parked.contains(&(session_id, fragment_index)) {
+            self.parked.push((session_id, fragment_index));
+        }
+    }
+
+    pub fn n_parked(&self) -> usize {
+        self.parked.len()
+    }
+
+    /// The technicians did not answer within the timeout.
+    pub fn is_expired(&self, now: Instant, timeout: Duration) -> bool {
+        now.duration_since(self.since) > timeout
+    }
+
+    /// The parked fragments, in the order they were parked.
+    pub fn into_parked(self) -> Vec<(SessionId, FragmentIndex)> {
+        self.parked
+    }
+}
+
+impl Default for DroneRepair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
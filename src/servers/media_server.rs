@@ -1,14 +1,21 @@
 use super::server::MediaServer as CharTrait;
 use super::server::Server as MainTrait;
+use crate::fragmentation::{n_fragments, OutgoingMessage, Reassembler, Throughput, DEFAULT_SEND_WINDOW, MAX_QUERY_FRAGMENTS, MAX_RESPONSE_FRAGMENTS, RETRANSMISSION_CHECK_INTERVAL};
+use crate::simulation_controller::STATISTICS_INTERVAL;
+use crate::link_quality::LinkQuality;
+use super::drone_repair::{DroneRepair, DEFAULT_DRONE_REPAIR_TIMEOUT, REPAIR_CHECK_INTERVAL};
+use super::content_directory::{ContentDirectory, DirectoryChanges, RESCAN_INTERVAL};
 use crate::general_use::{
-    DataScope, DisplayDataMediaServer, Query, Response, ServerCommand, ServerEvent, ServerType,
+    DataScope, DisplayDataMediaServer, MediaFile, MediaRef, Query, Response, ServerCommand, ServerError, ServerEvent, ServerType,
     SpecificNodeType, DataScope::{UpdateAll, UpdateSelf}
 };
 use crate::ui_traits::Monitoring;
-use crossbeam_channel::{select_biased, Receiver, Sender};
+use crossbeam_channel::{never, select_biased, tick, Receiver, Sender};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
-use log::{error, warn};
+use std::path::Path;
+use std::time::Duration;
+use log::{info, warn};
 use wg_2024::{
     network::NodeId,
     packet::Packet,
@@ -24,14 +31,16 @@ pub struct MediaServer{
     pub id: NodeId,
 
     //Fragment-related
-    pub reassembling_messages: HashMap<SessionId, Vec<u8>>,
-    pub sending_messages: HashMap<SessionId, (Vec<u8>, NodeId)>,
+    pub reassembler: Reassembler,                               // Queries by sender and session
+    pub sending_messages: HashMap<SessionId, OutgoingMessage>,  // Responses waiting for their acks
 
     //Flood-related
     pub clients: HashSet<NodeId>,                               // Available clients
     pub topology: HashMap<NodeId, HashSet<NodeId>>,             // Nodes and their neighbours
     pub nodes: HashMap<NodeId, NodeType>,                       // Nodes and their types
     pub routes: HashMap<NodeId, Vec<NodeId>>,                   // Routes to the servers
+    pub backup_routes: HashMap<NodeId, Vec<NodeId>>,            // Routes sharing no drone with the primary ones, for the failover
+    pub backup_routes_to_repair: HashSet<NodeId>,               // Clients whose backup route has to be computed again
     pub flood_ids: Vec<FloodId>,
     pub counter: (FloodId, SessionId),
 
@@ -43,9 +52,17 @@ pub struct MediaServer{
 
     //Characteristic-Server fields
     pub media: HashMap<String, String>,
+    pub media_files: HashMap<MediaRef, MediaFile>,              // Files of the directory, by name without extension
+    pub media_file_names: HashMap<MediaRef, String>,            // File of the directory serving each media
+    pub content_directory: Option<ContentDirectory>,
 
     //Drop counter
     pub drops_counter: HashMap<SessionId, HashMap<NodeId, u8>>,
+    pub drones_under_repair: HashMap<NodeId, DroneRepair>,      // Drones waiting for the technicians, with the parked fragments
+    pub drone_repair_timeout: Duration,                         // Then the parked fragments are sent anyway
+    pub link_quality: LinkQuality,
+    pub send_window: usize,
+    pub throughput: Throughput,
 
     //Queries to process
     pub queries_to_process: VecDeque<(NodeId, Query)>,
@@ -63,13 +80,15 @@ impl MediaServer {
         MediaServer {
             id,
 
-            reassembling_messages: Default::default(),
+            reassembler: Reassembler::new(MAX_QUERY_FRAGMENTS),
             sending_messages: Default::default(),
 
             clients: Default::default(),  // Available clients
             topology: Default::default(),
             nodes: Default::default(),
             routes: Default::default(),
+            backup_routes: Default::default(),
+            backup_routes_to_repair: Default::default(),
             flood_ids: Default::default(),
             counter: (0, 0),
 
@@ -79,12 +98,114 @@ impl MediaServer {
             packet_send,
 
             media,
+            media_files: HashMap::new(),
+            media_file_names: HashMap::new(),
+            content_directory: None,
 
             drops_counter: HashMap::new(),
+            drones_under_repair: HashMap::new(),
+            drone_repair_timeout: DEFAULT_DRONE_REPAIR_TIMEOUT,
+            link_quality: LinkQuality::new(),
+            send_window: DEFAULT_SEND_WINDOW,
+            throughput: Throughput::new(),
 
             queries_to_process: VecDeque::new(),
         }
     }
+
+    /// Serves the PNG, JPEG, GIF and MP3 files of a directory, `#Media[name]` refers to `name.png` and the others.
+    /// While running with monitoring the directory is scanned again every `RESCAN_INTERVAL`.
+    pub fn from_directory<P: AsRef<Path>>(
+        id: NodeId,
+        directory: P,
+        to_controller_event: Sender<ServerEvent>,
+        from_controller_command: Receiver<ServerCommand>,
+        packet_recv: Receiver<Packet>,
+        packet_send: HashMap<NodeId, Sender<Packet>>,
+    ) -> Result<Self, String> {
+        let (content_directory, changes) = ContentDirectory::open(directory)?;
+        let mut server = Self::new(id, HashMap::new(), to_controller_event, from_controller_command, packet_recv, packet_send);
+        server.apply_directory_changes(changes);
+        server.content_directory = Some(content_directory);
+        Ok(server)
+    }
+
+    /// Scans the directory of the media, returns true if the media changed.
+    pub fn rescan_content_directory(&mut self) -> bool {
+        let Some(content_directory) = self.content_directory.as_mut() else {
+            return false;
+        };
+        match content_directory.rescan() {
+            Ok(changes) => self.apply_directory_changes(changes),
+            Err(e) => {
+                warn!("Server {}: {}", self.id, e);
+                false
+            }
+        }
+    }
+
+    fn apply_directory_changes(&mut self, changes: DirectoryChanges) -> bool {
+        if changes.is_empty() {
+            return false;
+        }
+        for name in changes.removed {
+            let (reference, _) = split_extension(&name);
+            // Only the file serving the media removes it, not a skipped file with the same name
+            if self.media_file_names.get(reference) != Some(&name) {
+                continue;
+            }
+            info!("Server {}: media {} removed", self.id, name);
+            self.media_file_names.remove(reference);
+            self.media_files.remove(reference);
+            self.media.remove(reference);
+        }
+        for (name, bytes) in changes.updated {
+            let (reference, extension) = split_extension(&name);
+            let Some(mime) = mime_type(extension) else {
+                warn!("Server {}: {} is not a PNG, JPEG, GIF or MP3 file, it is not served", self.id, name);
+                continue;
+            };
+            if let Some(serving_name) = self.media_file_names.get(reference).filter(|&serving_name| *serving_name != name) {
+                warn!("Server {}: {} is not served, media {} is already served by {}", self.id, name, reference, serving_name);
+                continue;
+            }
+            let media_file = MediaFile::new(mime, &bytes);
+            let response_fragments = response_n_fragments(&media_file);
+            if response_fragments > MAX_RESPONSE_FRAGMENTS {
+                warn!("Server {}: {} is not served, its response would take {} fragments and the clients accept at most {}",
+                    self.id, name, response_fragments, MAX_RESPONSE_FRAGMENTS);
+                continue;
+            }
+            info!("Server {}: media {} loaded ({}, {} bytes)", self.id, name, mime, media_file.size);
+            // The monitoring shows only the description, not the whole file
+            self.media.insert(reference.to_string(), format!("{} ({}, {} bytes)", name, mime, media_file.size));
+            self.media_files.insert(reference.to_string(), media_file);
+            self.media_file_names.insert(reference.to_string(), name);
+        }
+        true
+    }
+}
+
+/// Fragments of the `MediaWithMeta` response carrying the file.
+fn response_n_fragments(media_file: &MediaFile) -> u64 {
+    // The base64 characters are not escaped by json, only the rest of the response is serialized
+    let envelope = Response::MediaWithMeta(MediaFile { mime: media_file.mime.clone(), size: media_file.size, base64: String::new() });
+    let envelope_length = serde_json::to_vec(&envelope).map_or(0, |bytes| bytes.len());
+    n_fragments(envelope_length + media_file.base64.len())
+}
+
+fn split_extension(name: &str) -> (&str, &str) {
+    name.rsplit_once('.').unwrap_or((name, ""))
+}
+
+fn mime_type(extension: &str) -> Option<&'static str> {
+    match extension.to_ascii_lowercase().as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "mp3" => Some("audio/mpeg"),
+        _ => None,
+    }
 }
 
 
@@ -98,6 +219,9 @@ impl Monitoring for MediaServer {
             connected_node_ids: neighbors,
             known_clients: self.clients.clone(),
             routing_table: self.routes.clone(),
+            route_costs: self.link_quality.route_costs(&self.routes),
+            send_window: self.send_window,
+            throughput: self.throughput.fragments_per_second(),
             media: self.media.clone(),
         };
         self.to_controller_event.send(ServerEvent::MediaServerData(self.id, display_data, data_scope)).expect("Failed to send media server data");
@@ -106,6 +230,13 @@ impl Monitoring for MediaServer {
         &mut self
     ) {
         self.send_display_data(UpdateAll);
+        let rescan_ticker = match self.content_directory {
+            Some(_) => tick(RESCAN_INTERVAL),
+            None => never(),
+        };
+        let repair_ticker = tick(REPAIR_CHECK_INTERVAL);
+        let retransmission_ticker = tick(RETRANSMISSION_CHECK_INTERVAL);
+        let throughput_ticker = tick(STATISTICS_INTERVAL);
         loop {
             select_biased! {
                 recv(self.get_from_controller_command()) -> command_res => {
@@ -130,6 +261,9 @@ impl Monitoring for MediaServer {
                                 self.handle_packet(packet);
                                 self.send_display_data(UpdateSelf);
                             },
+                            ServerCommand::DroneFixed(drone_id) => {
+                                self.handle_drone_fixed(drone_id);
+                            }
                             _ => {}
                         }
                     }
@@ -140,6 +274,23 @@ impl Monitoring for MediaServer {
                         self.send_display_data(UpdateSelf);
                     }
                 },
+                recv(repair_ticker) -> _ => {
+                    self.resume_expired_repairs();
+                    self.repair_backup_routes();
+                },
+                recv(retransmission_ticker) -> _ => {
+                    self.retransmit_timed_out_fragments();
+                },
+                recv(throughput_ticker) -> _ => {
+                    if self.throughput.has_changed() {
+                        self.send_display_data(UpdateSelf);
+                    }
+                },
+                recv(rescan_ticker) -> _ => {
+                    if self.rescan_content_directory() {
+                        self.send_display_data(UpdateSelf);
+                    }
+                },
             }
         }
     }
@@ -164,13 +315,15 @@ impl MainTrait for MediaServer{
     fn get_topology(&mut self) -> &mut HashMap<NodeId, HashSet<NodeId>>{ &mut self.topology }
     fn get_nodes(&mut self) -> &mut HashMap<NodeId, NodeType> { &mut self.nodes }
     fn get_routes(&mut self) -> &mut HashMap<NodeId, Vec<NodeId>>{ &mut self.routes }
+    fn get_backup_routes(&mut self) -> &mut HashMap<NodeId, Vec<NodeId>>{ &mut self.backup_routes }
+    fn get_backup_routes_to_repair(&mut self) -> &mut HashSet<NodeId>{ &mut self.backup_routes_to_repair }
 
     fn get_event_sender(&self) -> &Sender<ServerEvent>{ &self.to_controller_event }
     fn get_from_controller_command(&mut self) -> &mut Receiver<ServerCommand>{ &mut self.from_controller_command }
     fn get_packet_recv(&mut self) -> &mut Receiver<Packet>{ &mut self.packet_recv }
     fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>>{ &mut self.packet_send }
     fn get_packet_send_not_mutable(&self) -> &HashMap<NodeId, Sender<Packet>>{ &self.packet_send }
-    fn get_reassembling_messages(&mut self) -> &mut HashMap<u64, Vec<u8>>{ &mut self.reassembling_messages }
+    fn get_reassembler(&mut self) -> &mut Reassembler{ &mut self.reassembler }
     fn process_query(&mut self, query: Query, src_id: NodeId) {
         // Check if there is a route to the client, save query and start the discovery process if it's not.
         if self.routes.get(&src_id).is_none() {
@@ -188,12 +341,22 @@ impl MainTrait for MediaServer{
             _ => {}
         }
     }
-    fn get_sending_messages(&mut self) ->  &mut HashMap<u64, (Vec<u8>, u8)>{ &mut self.sending_messages }
+    fn get_sending_messages(&mut self) ->  &mut HashMap<u64, OutgoingMessage>{ &mut self.sending_messages }
 
-    fn get_sending_messages_not_mutable(&self) -> &HashMap<u64, (Vec<u8>, u8)>{ &self.sending_messages }
+    fn get_sending_messages_not_mutable(&self) -> &HashMap<u64, OutgoingMessage>{ &self.sending_messages }
 
     fn get_drops_counter(&mut self) -> &mut HashMap<u64, HashMap<NodeId, u8>>{ &mut self.drops_counter }
 
+    fn get_drones_under_repair(&mut self) -> &mut HashMap<NodeId, DroneRepair>{ &mut self.drones_under_repair }
+
+    fn get_drone_repair_timeout(&self) -> Duration{ self.drone_repair_timeout }
+
+    fn get_link_quality(&mut self) -> &mut LinkQuality{ &mut self.link_quality }
+    fn get_link_quality_not_mutable(&self) -> &LinkQuality{ &self.link_quality }
+
+    fn get_send_window(&self) -> usize{ self.send_window }
+    fn get_throughput(&mut self) -> &mut Throughput{ &mut self.throughput }
+
     fn get_queries_to_process(&mut self) -> &mut VecDeque<(NodeId, Query)>{ &mut self.queries_to_process }
 }
 
@@ -201,37 +364,99 @@ impl CharTrait for MediaServer{
     fn give_media_back(&mut self, client_id: NodeId, reference: String) {
         //Get media
         //println!("media in the server {:?}", self.media);
-        if let Some(media) = self.media.get(&reference).cloned() {
-            //Checking if present
-            let response = Response::Media(media);
-
-            //Serializing message to send
-            let response_as_string = serde_json::to_string(&response).unwrap();
-            let response_in_vec_bytes = response_as_string.as_bytes();
-            let length_response = response_in_vec_bytes.len();
-
-            //Counting fragments
-            let mut n_fragments = length_response / 128+1;
-            if n_fragments == 0 {
-                n_fragments -= 1;
-            }
+        // The files of a directory carry their type, the built-in media are paths of the web server
+        let response = match self.media_files.get(&reference) {
+            Some(media_file) => Some(Response::MediaWithMeta(media_file.clone())),
+            None => self.media.get(&reference).cloned().map(Response::Media),
+        };
+        if let Some(response) = response {
 
-            // Finding route
-            let Some(route) = self.find_path_to(client_id) else {
-                error!("Server {}: No route found to the client {}", self.get_id(), client_id);
-                return;
-            };
+            //Send fragments
+            self.send_response(client_id, &response);
+        } else {
+            self.give_error_back(client_id, ServerError::MediaNotFound(reference));
+        }
+    }
 
-            //Generating header
-            let header = Self::create_source_routing(route);
 
-            // Generating ids
-            let session_id = self.generate_unique_session_id();
+}
 
-            //Send fragments
-            self.send_fragments(session_id, n_fragments,response_in_vec_bytes, header);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragmentation::serialize_to_fragments;
+    use crossbeam_channel::unbounded;
+
+    fn media_server() -> MediaServer {
+        let (event_sender, _) = unbounded();
+        let (_, command_receiver) = unbounded();
+        let (_, packet_receiver) = unbounded();
+        MediaServer::new(4, HashMap::new(), event_sender, command_receiver, packet_receiver, HashMap::new())
+    }
+
+    fn changes(updated: &[&str], removed: &[&str]) -> DirectoryChanges {
+        DirectoryChanges {
+            updated: updated.iter().map(|name| (name.to_string(), name.as_bytes().to_vec())).collect(),
+            removed: removed.iter().map(|name| name.to_string()).collect(),
         }
     }
 
+    #[test]
+    fn extension_is_split_at_the_last_dot() {
+        assert_eq!(split_extension("cat.png"), ("cat", "png"));
+        assert_eq!(split_extension("cat.small.jpeg"), ("cat.small", "jpeg"));
+        assert_eq!(split_extension("cat"), ("cat", ""));
+    }
+
+    #[test]
+    fn mime_type_ignores_the_case_of_the_extension() {
+        assert_eq!(mime_type("png"), Some("image/png"));
+        assert_eq!(mime_type("JPG"), Some("image/jpeg"));
+        assert_eq!(mime_type("jpeg"), Some("image/jpeg"));
+        assert_eq!(mime_type("gif"), Some("image/gif"));
+        assert_eq!(mime_type("Mp3"), Some("audio/mpeg"));
+        assert_eq!(mime_type("txt"), None);
+        assert_eq!(mime_type(""), None);
+    }
 
+    #[test]
+    fn each_media_is_served_by_a_single_file() {
+        let mut server = media_server();
+        assert!(server.apply_directory_changes(changes(&["cat.png", "notes.txt"], &[])));
+        assert!(server.apply_directory_changes(changes(&["cat.gif"], &[])));
+        assert_eq!(server.media_file_names.get("cat").map(String::as_str), Some("cat.png"));
+        assert_eq!(server.media_files["cat"].mime, "image/png");
+        assert!(!server.media_files.contains_key("notes"));
+
+        // Removing the skipped file keeps the media, removing the serving one removes it
+        server.apply_directory_changes(changes(&[], &["cat.gif"]));
+        assert!(server.media_files.contains_key("cat"));
+        server.apply_directory_changes(changes(&[], &["cat.png"]));
+        assert!(!server.media_files.contains_key("cat") && !server.media.contains_key("cat"));
+
+        // Then the other file can serve it
+        server.apply_directory_changes(changes(&["cat.gif"], &[]));
+        assert_eq!(server.media_files["cat"].mime, "image/gif");
+    }
+
+    #[test]
+    fn response_fragments_are_counted_without_serializing_the_file() {
+        for length in [0, 1, 95, 96, 97, 1000] {
+            let media_file = MediaFile::new("image/png", &vec![7; length]);
+            let fragments = serialize_to_fragments(&Response::MediaWithMeta(media_file.clone())).expect("serializable");
+            assert_eq!(response_n_fragments(&media_file), fragments.len() as u64);
+        }
+    }
+
+    #[test]
+    fn files_too_big_for_the_clients_are_not_served() {
+        let mut server = media_server();
+        let largest_base64 = MAX_RESPONSE_FRAGMENTS as usize * wg_2024::packet::FRAGMENT_DSIZE;
+        let changes = DirectoryChanges {
+            updated: vec![("huge.mp3".to_string(), vec![0; largest_base64 / 4 * 3])],
+            removed: Vec::new(),
+        };
+        server.apply_directory_changes(changes);
+        assert!(!server.media_files.contains_key("huge"));
+    }
 }
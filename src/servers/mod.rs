@@ -5,3 +5,5 @@ pub mod communication_server;
 pub mod media_server;
 pub mod text_server;
 pub mod content;
+pub mod content_directory;
+pub mod drone_repair;
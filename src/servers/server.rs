@@ -1,15 +1,19 @@
 //I am a god
 
-use crossbeam_channel::{select_biased, Receiver, Sender};
+use crossbeam_channel::{select_biased, tick, Receiver, Sender};
 use std::collections::{HashMap, HashSet, VecDeque};
-use log::{debug, error, info};
+use std::time::{Duration, Instant};
+use log::{debug, error, info, warn};
 use wg_2024::{
     network::{NodeId, SourceRoutingHeader},
     packet::{
         Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet, PacketType,
     },
 };
-use crate::general_use::{FloodId, Message, Query, Response, ServerCommand, ServerEvent, ServerType};
+use crate::general_use::{FailedSession, FloodId, Message, Query, Response, ServerCommand, ServerError, ServerEvent, ServerType};
+use crate::fragmentation::{self, FragmentError, OutgoingMessage, Reassembler, Throughput, MAX_SEND_ATTEMPTS, REASSEMBLY_TIMEOUT, RETRANSMISSION_CHECK_INTERVAL};
+use crate::link_quality::LinkQuality;
+use super::drone_repair::{DroneRepair, REPAIR_CHECK_INTERVAL};
 
 
 ///SERVER TRAIT
@@ -25,6 +29,8 @@ pub trait Server{
     fn get_topology(&mut self) -> &mut HashMap<NodeId, HashSet<NodeId>>;
     fn get_nodes(&mut self) -> &mut HashMap<NodeId, NodeType>;
     fn get_routes(&mut self) -> &mut HashMap<NodeId, Vec<NodeId>>;
+    fn get_backup_routes(&mut self) -> &mut HashMap<NodeId, Vec<NodeId>>;
+    fn get_backup_routes_to_repair(&mut self) -> &mut HashSet<NodeId>;
 
     fn get_event_sender(&self) -> &Sender<ServerEvent>;
     fn get_from_controller_command(&mut self) -> &mut Receiver<ServerCommand>;
@@ -32,17 +38,25 @@ pub trait Server{
     fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>>;
     fn get_packet_send_not_mutable(&self) -> &HashMap<NodeId, Sender<Packet>>;
 
-    fn get_reassembling_messages(&mut self) -> &mut HashMap<u64, Vec<u8>>;
+    fn get_reassembler(&mut self) -> &mut Reassembler;
     fn process_query(&mut self, query: Query, src_id: NodeId);
-    fn get_sending_messages(&mut self) -> &mut HashMap<u64, (Vec<u8>, u8)>;
-    fn get_sending_messages_not_mutable(&self) -> &HashMap<u64, (Vec<u8>, u8)>;
+    fn get_sending_messages(&mut self) -> &mut HashMap<u64, OutgoingMessage>;
+    fn get_sending_messages_not_mutable(&self) -> &HashMap<u64, OutgoingMessage>;
 
     fn get_drops_counter(&mut self) -> &mut HashMap<u64, HashMap<NodeId, u8>>;
+    fn get_drones_under_repair(&mut self) -> &mut HashMap<NodeId, DroneRepair>;
+    fn get_drone_repair_timeout(&self) -> Duration;
+    fn get_link_quality(&mut self) -> &mut LinkQuality;
+    fn get_link_quality_not_mutable(&self) -> &LinkQuality;
+    fn get_send_window(&self) -> usize;
+    fn get_throughput(&mut self) -> &mut Throughput;
 
     fn get_queries_to_process(&mut self) -> &mut VecDeque<(NodeId, Query)>;
 
     fn run(&mut self) {
         info!("Running {} server with ID: {}", self.get_server_type(), self.get_id());
+        let repair_ticker = tick(REPAIR_CHECK_INTERVAL);
+        let retransmission_ticker = tick(RETRANSMISSION_CHECK_INTERVAL);
         loop {
             select_biased! {
                 recv(self.get_from_controller_command()) -> command_res => {
@@ -57,6 +71,13 @@ pub trait Server{
                         self.handle_packet(packet)
                     }
                 },
+                recv(repair_ticker) -> _ => {
+                    self.resume_expired_repairs();
+                    self.repair_backup_routes();
+                },
+                recv(retransmission_ticker) -> _ => {
+                    self.retransmit_timed_out_fragments();
+                },
             }
         }
     }
@@ -79,6 +100,9 @@ pub trait Server{
                 info!("Server {}: Shortcut packet received from SC: {:?}", self.get_id(), packet);
                 self.handle_packet(packet);
             }
+            ServerCommand::DroneFixed(drone_id) => {
+                self.handle_drone_fixed(drone_id);
+            }
             _ => {},
         }
     }
@@ -86,7 +110,7 @@ pub trait Server{
     fn handle_packet(&mut self, packet: Packet) {
         match packet.pack_type {
             PacketType::Nack(nack) => self.handle_nack(nack, packet.session_id, packet.routing_header.hops[0]),
-            PacketType::Ack(ack) => self.handle_ack(ack),
+            PacketType::Ack(ack) => self.handle_ack(ack, packet.session_id, &packet.routing_header.hops),
             PacketType::MsgFragment(fragment) => self.handle_fragment(fragment, packet.routing_header, packet.session_id),
             PacketType::FloodRequest(flood_request) => self.handle_flood_request(flood_request, packet.session_id),
             PacketType::FloodResponse(flood_response) => self.handle_flood_response(flood_response),
@@ -98,6 +122,7 @@ pub trait Server{
         info!("Server {}: Starting discovery process", self.get_id());
 
         self.get_routes().clear();
+        self.get_backup_routes().clear();
         self.get_topology().clear();
 
         let flood_id = self.generate_unique_flood_id();
@@ -148,18 +173,21 @@ pub trait Server{
 
     fn update_routes_to_clients(&mut self, path: &[(NodeId, NodeType)]) {
         if let Some((id, NodeType::Client)) = path.last() {
-            if self
-                .get_routes()
+            // The new path may give a backup route, or the primary route may change
+            self.get_backup_routes_to_repair().insert(*id);
+
+            let route: Vec<NodeId> = path.iter().map(|entry| entry.0).collect();
+            let cost = self.get_link_quality_not_mutable().route_cost(&route);
+            let prev_cost = self.get_routes()
                 .get(id)
-                .map_or(true, |prev_path| prev_path.len() > path.len())
+                .cloned()
+                .map(|prev_path| self.get_link_quality_not_mutable().route_cost(&prev_path));
+            if prev_cost.map_or(true, |prev_cost| prev_cost > cost)
             {
                 self.get_clients().insert(*id);
 
-                // Update the routing table with the new, shorter path.
-                self.get_routes().insert(
-                    *id,
-                    path.iter().map(|entry| entry.0.clone()).collect(),
-                );
+                // Update the routing table with the new, cheaper path.
+                self.get_routes().insert(*id, route);
                 info!("Server {}: Updated route to client {}: {:?}", self.get_id(), id, path);
 
                 // Resend responses that were waiting for the route to the client.
@@ -229,7 +257,17 @@ pub trait Server{
     }
 
     fn handle_nack_dropped(&mut self, session_id: u64, fragment_index: u64, last_node_id: NodeId) {
-        let drones_and_counters = self.get_drops_counter().get_mut(&session_id).unwrap();
+        if let Err(e) = self.get_event_sender().send(ServerEvent::FragmentRetransmitted(self.get_id(), session_id)) {
+            error!("Server {}: failed to send FragmentRetransmitted event: {}", self.get_id(), e);
+        }
+
+        // The drop makes the drone more expensive, the fragment may go around it
+        self.get_link_quality().record_drop(last_node_id);
+        if let Some(client_id) = self.get_sending_messages_not_mutable().get(&session_id).map(OutgoingMessage::destination) {
+            self.refresh_route(client_id);
+        }
+
+        let drones_and_counters = self.get_drops_counter().entry(session_id).or_default();
         let Some(counter) = drones_and_counters.get_mut(&last_node_id) else {
             drones_and_counters.insert(last_node_id, 1);
 
@@ -240,33 +278,66 @@ pub trait Server{
         *counter += 1;
 
         // If the counter reaches 10, send an event to call technicians to fix the drone.
+        // The fragments through the drone are parked until it is fixed, the other sessions keep flowing.
         if *counter == 10 {
             *counter = 0;
-            let me = (self.get_id(), NodeType::Server);
-            self.get_event_sender().send(ServerEvent::CallTechniciansToFixDrone(last_node_id, me)).unwrap();
-            self.wait_for_drone_fix(last_node_id);
+            if !self.get_drones_under_repair().contains_key(&last_node_id) {
+                let me = (self.get_id(), NodeType::Server);
+                self.get_event_sender().send(ServerEvent::CallTechniciansToFixDrone(last_node_id, me)).unwrap();
+                self.get_drones_under_repair().insert(last_node_id, DroneRepair::new());
+                info!("Server {}: Technicians called for drone {}", self.get_id(), last_node_id);
+            }
         }
 
         self.send_again_fragment(session_id, fragment_index);
     }
 
-    fn wait_for_drone_fix(&mut self, last_node_id: NodeId) {
-        loop {
-            match self.get_from_controller_command().recv() {
-                Ok(command) => {
-                    match command {
-                        ServerCommand::DroneFixed(node_id) => {
-                            if node_id == last_node_id {
-                                break;
-                            }
-                        }
-                        _ => { self.handle_command(command) }
-                    }
-                }
-                Err(err) => {
-                    error!("Server {}: Error receiving command from the controller: {}", self.get_id(), err);
-                }
-            }
+    /// The first drone of the route that is waiting for the technicians.
+    fn drone_under_repair_on(&mut self, route: &[NodeId]) -> Option<NodeId> {
+        let drones_under_repair = self.get_drones_under_repair();
+        route.iter().copied().find(|node_id| drones_under_repair.contains_key(node_id))
+    }
+
+    fn park_fragment(&mut self, drone_id: NodeId, session_id: u64, fragment_index: u64) {
+        debug!("Server {}: Fragment {} of session {} parked until drone {} is fixed", self.get_id(), fragment_index, session_id, drone_id);
+        if let Some(drone_repair) = self.get_drones_under_repair().get_mut(&drone_id) {
+            drone_repair.park(session_id, fragment_index);
+        }
+        // The ack is not awaited while the fragment is parked
+        if let Some(message) = self.get_sending_messages().get_mut(&session_id) {
+            message.stop_timer(fragment_index);
+        }
+    }
+
+    /// Sends again the fragments parked for the drone, the ones still crossing a drone under repair are parked again.
+    fn resume_parked_fragments(&mut self, drone_id: NodeId) {
+        let Some(drone_repair) = self.get_drones_under_repair().remove(&drone_id) else {
+            return;
+        };
+        info!("Server {}: Drone {} no longer waited for, sending {} parked fragments", self.get_id(), drone_id, drone_repair.n_parked());
+        for (session_id, fragment_index) in drone_repair.into_parked() {
+            self.send_again_fragment(session_id, fragment_index);
+        }
+    }
+
+    /// The technicians fixed the drone: its drops are forgotten and its parked fragments sent.
+    fn handle_drone_fixed(&mut self, drone_id: NodeId) {
+        self.get_link_quality().forget(drone_id);
+        self.resume_parked_fragments(drone_id);
+    }
+
+    /// Stops waiting for the drones that were not fixed within the repair timeout.
+    fn resume_expired_repairs(&mut self) {
+        let now = Instant::now();
+        let timeout = self.get_drone_repair_timeout();
+        let expired: Vec<NodeId> = self.get_drones_under_repair()
+            .iter()
+            .filter(|(_, drone_repair)| drone_repair.is_expired(now, timeout))
+            .map(|(drone_id, _)| *drone_id)
+            .collect();
+        for drone_id in expired {
+            warn!("Server {}: Drone {} not fixed after {:?}", self.get_id(), drone_id, timeout);
+            self.resume_parked_fragments(drone_id);
         }
     }
 
@@ -276,8 +347,28 @@ pub trait Server{
     }
 
     //ACK
-    fn handle_ack(&mut self, _ack: Ack){
-        //UI stuff I guess?
+    fn handle_ack(&mut self, ack: Ack, session_id: u64, route: &[NodeId]){
+        let Some(message) = self.get_sending_messages().get_mut(&session_id) else {
+            return;
+        };
+        let first_ack = !message.is_acked(ack.fragment_index);
+
+        // The fragments are kept until the client has all of them
+        let is_complete = message.ack(ack.fragment_index);
+
+        // The drones of the ack route are the ones that forwarded the fragment
+        if first_ack {
+            self.get_link_quality().record_delivery(route);
+            self.get_throughput().record_ack();
+        }
+        if is_complete {
+            self.get_sending_messages().remove(&session_id);
+            self.get_drops_counter().remove(&session_id);
+            debug!("Server {}: Every fragment of session {} acked", self.get_id(), session_id);
+        } else {
+            // The window slides past the acked fragments
+            self.send_window_fragments(session_id);
+        }
     }
 
     fn send_ack(&self, ack: Ack, routing_header: SourceRoutingHeader, session_id: u64) {
@@ -335,8 +426,13 @@ pub trait Server{
     //FRAGMENT TO DECIDE IF IMPLEMENTING DEFAULT FOR EACH ONE
 
     fn handle_fragment(&mut self, fragment: Fragment, routing_header: SourceRoutingHeader, session_id: u64, ){
+        let Some(&src_id) = routing_header.hops.first() else {
+            warn!("Server {}: Fragment without a route discarded", self.get_id());
+            return;
+        };
+
         // Packet Verification
-        if self.get_id() != routing_header.hops[routing_header.hop_index] {
+        if routing_header.hops.get(routing_header.hop_index) != Some(&self.get_id()) {
             // Send Nack (UnexpectedRecipient)
             let nack = Nack {
                 fragment_index: fragment.fragment_index,
@@ -345,7 +441,7 @@ pub trait Server{
             self.send_nack(nack, routing_header.get_reversed(), session_id);
             return;
         }else{
-            // Send Ack
+            // Send Ack, also for the duplicates, their first ack may have been lost
             let ack = Ack {
                 fragment_index: fragment.fragment_index,
             };
@@ -353,76 +449,36 @@ pub trait Server{
         }
 
         info!("Handling Fragment {:?}", fragment);
-
-        //Getting vec of data from fragment
-        let mut data_to_add :Vec<u8> = fragment.data.to_vec();
-        data_to_add.truncate(fragment.length as usize);
-
-        //Fragment reassembly
-        // Check if it exists already
-        if let Some(reassembling_message) = self.get_reassembling_messages().get_mut(&session_id) {
-
-            // Check for valid fragment index and length
-            let offset = reassembling_message.len();
-            if offset + fragment.length as usize > reassembling_message.capacity()
-            {
-                info!("Nack");
-                //Maybe cancelling also message in reassembling_message
-                // ... error handling logic ...
-                return;
-
-            }else{
-                // Copy data to the correct offset in the vector.
-                reassembling_message.splice(((fragment.fragment_index*128) as usize)..((fragment.fragment_index*128) as usize), data_to_add);
-
-                // Check if all fragments have been received
-                let reassembling_message_clone = reassembling_message.clone();
-                info!("N fragments + current fragment length{}", fragment.total_n_fragments*128 + fragment.length as u64);
-                self.if_all_fragments_received_process(&reassembling_message_clone, &fragment, session_id, routing_header);
-            }
-
-        }else {
-            //Check if it is only 1 fragment
-            if !self.if_all_fragments_received_process(&data_to_add, &fragment, session_id, routing_header)
-            {
-                // New message, create a new entry in the HashMap.
-                let mut reassembling_message = Vec::with_capacity(fragment.total_n_fragments as usize * 128);
-
-                //Copying data
-                reassembling_message.splice(((fragment.fragment_index*128) as usize)..((fragment.fragment_index*128) as usize), data_to_add);
-
-                //Inserting message for future fragments
-                self.get_reassembling_messages()
-                    .insert(session_id, reassembling_message.clone());
-            }
+        self.discard_expired_messages();
+
+        //Fragment reassembly, the fragments can arrive in any order
+        match self.get_reassembler().add_fragment(src_id, session_id, &fragment) {
+            Ok(Some(data)) => self.process_reassembled_message(data, src_id),
+            Ok(None) => {}
+            // The whole message is refused, the client has to send it again
+            Err(e) => self.refuse_message(src_id, session_id, e),
         }
     }
 
-    fn if_all_fragments_received_process(&mut self, message: &Vec<u8>, current_fragment: &Fragment, session_id: u64, routing_header: SourceRoutingHeader) -> bool {
-        // Message Processing
-
-        info!("Message length {} n_fragments {} current.fragment length {}, fragment_index: {}", message.len(), current_fragment.total_n_fragments, current_fragment.length, current_fragment.fragment_index );
-        if(message.len() as u64) == ((current_fragment.total_n_fragments-1)*128 + current_fragment.length as u64)
-        {
+    fn refuse_message(&mut self, src_id: NodeId, session_id: u64, error: FragmentError) {
+        warn!("Server {}: Message {} from {} refused: {}", self.get_id(), session_id, src_id, error);
+        self.give_error_back(src_id, ServerError::MalformedMessage(error.to_string()));
+    }
 
-            let reassembled_data = message.clone(); // Take ownership of the data
-            self.get_drops_counter().remove(&session_id);
-            self.get_reassembling_messages().remove(&session_id); // Remove from map
-            self.process_reassembled_message(reassembled_data, routing_header.hops[0]);
-            return true;
+    /// Forgets the messages whose fragments stopped arriving.
+    fn discard_expired_messages(&mut self) {
+        for (src_id, session_id) in self.get_reassembler().discard_expired() {
+            warn!("Server {}: Message {} from {} still incomplete after {:?}, discarded", self.get_id(), session_id, src_id, REASSEMBLY_TIMEOUT);
         }
-        false
     }
 
     fn process_reassembled_message(&mut self, data: Vec<u8>, src_id: NodeId) {
-        match String::from_utf8(data.clone()) {
-            Ok(data_string) => match serde_json::from_str(&data_string) {
-                Ok(query) => self.process_query(query, src_id),
-                Err(_) => {
-                    panic!("Damn, not the right struct")
-                }
-            },
-            Err(e) => println!("Argh, {:?}", e),
+        match fragmentation::deserialize_message::<Query>(&data) {
+            Ok(query) => self.process_query(query, src_id),
+            Err(e) => {
+                warn!("Server {}: Message from {} is not a query: {}", self.get_id(), src_id, e);
+                self.give_error_back(src_id, ServerError::InvalidQuery(e));
+            }
         }
     }
 
@@ -438,41 +494,44 @@ pub trait Server{
         return;
     }
 
-    fn send_fragments(&mut self, session_id: u64, n_fragments: usize, response_in_vec_bytes: &[u8], header: SourceRoutingHeader) {
-
-        //Storing the all the fragments to send
-        self.get_sending_messages().insert(session_id, (response_in_vec_bytes.to_vec(), header.destination().unwrap()));
+    fn send_response(&mut self, client_id: NodeId, response: &Response) {
 
-        info!("Sending fragments n_fragments: {}", n_fragments);
-        //Sending
-        for i in 0..n_fragments{
+        //Serializing and fragmenting the response
+        let fragments = match fragmentation::serialize_to_fragments(response) {
+            Ok(fragments) => fragments,
+            Err(e) => {
+                error!("Server {}: {}", self.get_id(), e);
+                return;
+            }
+        };
 
-            info!("Sending fragment fragment: {}", i);
-            //Preparing data of fragment
-            let mut data:[u8;128] = [0;128];
-            if(i+1)*128>response_in_vec_bytes.len(){
-                data[0..(response_in_vec_bytes.len()-(i*128))].copy_from_slice(&response_in_vec_bytes[i*128..response_in_vec_bytes.len()]);
+        // Finding route
+        if self.find_path_to(client_id).is_none() {
+            error!("Server {}: No route found to the client {}", self.get_id(), client_id);
+            return;
+        }
 
-            }else{
-                data.copy_from_slice(&response_in_vec_bytes[i*128..(1+i)*128]);
-            }
+        // Generating ids
+        let session_id = self.generate_unique_session_id();
 
-            //Generating fragment
-            let fragment = Fragment::new(
-                i as u64,
-                n_fragments as u64,
-                data,
-            );
+        //Storing the all the fragments to send, until they are acked
+        info!("Sending fragments n_fragments: {}", fragments.len());
+        let window = self.get_send_window();
+        self.get_sending_messages().insert(session_id, OutgoingMessage::new(client_id, fragments, window));
+        self.get_drops_counter().insert(session_id, HashMap::new());
 
-            //Generating packet
-            let packet = Self::create_packet(
-                PacketType::MsgFragment(fragment),
-                header.clone(),
-                session_id,
-            );
+        //Sending the first window, the acks slide it
+        self.send_window_fragments(session_id);
+    }
 
-            self.send_packet(packet);
-            self.get_drops_counter().insert(session_id, HashMap::new());
+    /// Sends the fragments of the session that entered the send window.
+    /// They take the current route, or are parked if it crosses a drone waiting for the technicians.
+    fn send_window_fragments(&mut self, session_id: u64) {
+        let Some(message) = self.get_sending_messages().get_mut(&session_id) else {
+            return;
+        };
+        for fragment_index in message.take_sendable() {
+            self.send_again_fragment(session_id, fragment_index);
         }
     }
 
@@ -484,13 +543,37 @@ pub trait Server{
         self.get_topology().remove(&error_node);
         info!("Server {}: Removed node {} from the topology", self.get_id(), error_node);
 
-        // Replace the paths that contain the node that caused the error with an empty vector.
-        for route in self.get_routes().values_mut() {
-            if route.contains(&error_node) {
-                *route = Vec::new();
+        // Switch the paths that contain the node that caused the error to their backup,
+        // or replace them with an empty vector if the backup contains the node too.
+        let broken_routes: Vec<NodeId> = self
+            .get_routes()
+            .iter()
+            .filter(|(_, path)| path.contains(&error_node))
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        for client_id in broken_routes {
+            let backup = self.get_backup_routes()
+                .remove(&client_id)
+                .filter(|backup| !backup.contains(&error_node));
+            if let Some(backup) = &backup {
+                info!("Server {}: Route to client {} switched to the backup {:?}", self.get_id(), client_id, backup);
             }
+            self.get_routes().insert(client_id, backup.unwrap_or_default());
+            self.get_backup_routes_to_repair().insert(client_id);
+        }
+
+        // The backups that contain the node are of no use anymore.
+        let broken_backups: Vec<NodeId> = self
+            .get_backup_routes()
+            .iter()
+            .filter(|(_, backup)| backup.contains(&error_node))
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        for client_id in broken_backups {
+            self.get_backup_routes().remove(&client_id);
+            self.get_backup_routes_to_repair().insert(client_id);
         }
-        info!("Server {}: Routes with node {} cleared", self.get_id(), error_node);
+        info!("Server {}: Routes with node {} switched or cleared", self.get_id(), error_node);
 
         // Collect server IDs that need new routes.
         let clients_to_update: Vec<NodeId> = self
@@ -502,7 +585,7 @@ pub trait Server{
 
         // Find new routes for the collected client IDs.
         for client_id in clients_to_update {
-            if let Some(new_path) = self.bfs(client_id) {
+            if let Some(new_path) = self.find_best_route(client_id) {
                 if let Some(path) = self.get_routes().get_mut(&client_id) {
                     *path = new_path;
                 }
@@ -510,86 +593,95 @@ pub trait Server{
                 error!("Server {}: No route found to the client {}", self.get_id(), client_id);
             }
         }
+
+        // A crashed drone will not be fixed, its parked fragments take the new routes
+        self.resume_parked_fragments(error_node);
     }
 
-    fn bfs(&mut self, client_id: NodeId) -> Option<Vec<NodeId>> {
-        // Initialize a queue for breadth-first search and a set to track visited nodes.
-        let mut queue: VecDeque<(NodeId, Vec<NodeId>)> = VecDeque::new();
-        let mut visited: HashSet<NodeId> = HashSet::new();
+    /// Route to the client with the least expected transmissions, given the drops observed so far.
+    fn find_best_route(&mut self, client_id: NodeId) -> Option<Vec<NodeId>> {
         let nodes = self.get_nodes().clone();
+        let topology = self.get_topology().clone();
 
-        // Start from the current node with an initial path containing just the current node.
-        queue.push_back((self.get_id(), vec![self.get_id()]));
-
-        // Perform breadth-first search.
-        while let Some((current, path)) = queue.pop_front() {
-            // If the destination node is reached, return the path.
-            if current == client_id {
-                return Some(path);
-            }
+        // Skip servers and clients in the search.
+        let can_cross = |node_id: NodeId| !matches!(nodes.get(&node_id), Some(NodeType::Server) | Some(NodeType::Client));
+        self.get_link_quality_not_mutable().best_route(self.get_id(), client_id, &topology, can_cross)
+    }
 
-            // Mark the current node as visited.
-            visited.insert(current);
+    /// Takes a cheaper route to the client if the drops made the current one too expensive.
+    fn refresh_route(&mut self, client_id: NodeId) {
+        let Some(route) = self.find_path_to(client_id) else {
+            return;
+        };
+        let Some(best_route) = self.find_best_route(client_id) else {
+            return;
+        };
+        let cost = self.get_link_quality_not_mutable().route_cost(&route);
+        let best_cost = self.get_link_quality_not_mutable().route_cost(&best_route);
+        if best_cost < cost {
+            info!("Server {}: Route to client {} changed from {:?} (cost {:.2}) to {:?} (cost {:.2})", self.get_id(), client_id, route, cost, best_route, best_cost);
+            self.get_routes().insert(client_id, best_route);
+            self.get_backup_routes_to_repair().insert(client_id);
+        }
+    }
 
-            // Explore the neighbors of the current node.
-            if let Some(neighbors) = self.get_topology().get(&current) {
-                for neighbor in neighbors {
-                    // Only visit unvisited neighbors.
-                    if visited.contains(neighbor) {
-                        continue;
-                    }
+    /// Route to the client that shares no drone with the primary one, used if the primary one breaks.
+    fn find_backup_route(&mut self, client_id: NodeId, primary: &[NodeId]) -> Option<Vec<NodeId>> {
+        let nodes = self.get_nodes().clone();
+        let topology = self.get_topology().clone();
 
-                    // Skip servers and clients in the search.
-                    if *neighbor != client_id {
-                        match nodes.get(neighbor) {
-                            Some(NodeType::Server) | Some(NodeType::Client) => continue,
-                            _ => {},
-                        }
-                    }
+        // Skip servers and clients in the search.
+        let can_cross = |node_id: NodeId| !matches!(nodes.get(&node_id), Some(NodeType::Server) | Some(NodeType::Client));
+        self.get_link_quality_not_mutable().disjoint_route(self.get_id(), client_id, &topology, can_cross, primary)
+    }
 
-                    let mut new_path = path.clone();
-                    new_path.push(*neighbor); // Extend the path to include the neighbor.
-                    queue.push_back((*neighbor, new_path)); // Add the neighbor to the queue.
+    /// Computes again the backup routes that a new primary route or a crash made invalid.
+    /// Called periodically, so that the failover itself does not wait for it.
+    fn repair_backup_routes(&mut self) {
+        let clients_to_repair: Vec<NodeId> = self.get_backup_routes_to_repair().drain().collect();
+        for client_id in clients_to_repair {
+            let backup = match self.find_path_to(client_id) {
+                Some(primary) if !primary.is_empty() => self.find_backup_route(client_id, &primary),
+                _ => None,
+            };
+            match backup {
+                Some(backup) => {
+                    debug!("Server {}: Backup route to client {}: {:?}", self.get_id(), client_id, backup);
+                    self.get_backup_routes().insert(client_id, backup);
+                }
+                None => {
+                    debug!("Server {}: No backup route to client {}", self.get_id(), client_id);
+                    self.get_backup_routes().remove(&client_id);
                 }
             }
         }
-        None    // Return None if no path to the server is found.
     }
 
     fn send_again_fragment(&mut self, session_id: u64, fragment_index: u64){
 
         //Getting right message and destination id
-        let message_and_destination = self.get_sending_messages_not_mutable().get(&session_id).unwrap();
-
-        //Preparing fields for Fragment
-        let length_response = message_and_destination.0.len();
-        let mut n_fragments = length_response / 128+1;
-        if n_fragments == 0 {
-            n_fragments -= 1;
-        }
-
-        let offset = fragment_index*128;
-        let mut data:[u8;128] = [0;128];
-        if (offset+128) > length_response as u64 {
-            data[0..(message_and_destination.0.len() as u64 - offset) as usize].copy_from_slice(&message_and_destination.0[offset as usize..message_and_destination.0.len()]);
-        }else{
-            data.copy_from_slice(&message_and_destination.0[offset as usize..(offset+128) as usize]);
-        }
-
-        //Generating fragment
-        let fragment = Fragment::new(
-            fragment_index,
-            n_fragments as u64,
-            data,
-        );
+        let Some(message) = self.get_sending_messages_not_mutable().get(&session_id) else {
+            warn!("Server {}: Session {} is not being sent, fragment {} not sent again", self.get_id(), session_id, fragment_index);
+            return;
+        };
+        let Some(fragment) = message.fragment(fragment_index).cloned() else {
+            warn!("Server {}: Session {} has no fragment {}", self.get_id(), session_id, fragment_index);
+            return;
+        };
 
         //Finding route
-        let recipient_id = message_and_destination.1;
+        let recipient_id = message.destination();
         let Some(route) = self.find_path_to(recipient_id) else {
             error!("Server {}: No route found to the client {}", self.get_id(), recipient_id);
             return;
         };
 
+        //Waiting for the technicians
+        if let Some(drone_id) = self.drone_under_repair_on(&route) {
+            self.park_fragment(drone_id, session_id, fragment_index);
+            return;
+        }
+
         //Generating packet
         let packet = Self::create_packet(
             PacketType::MsgFragment(fragment),
@@ -597,7 +689,44 @@ pub trait Server{
             session_id,
         );
         self.send_packet(packet);
+        if let Some(message) = self.get_sending_messages().get_mut(&session_id) {
+            message.start_timer(fragment_index);
+        }
+    }
+
+    /// Sends again the fragments whose ack is overdue, the drone or the ack may have been lost without a nack.
+    /// A session whose fragment was sent `MAX_SEND_ATTEMPTS` times is given up and reported to the controller.
+    fn retransmit_timed_out_fragments(&mut self) {
+        let now = Instant::now();
+        let session_ids: Vec<u64> = self.get_sending_messages().keys().copied().collect();
+        for session_id in session_ids {
+            let Some(message) = self.get_sending_messages().get_mut(&session_id) else {
+                continue;
+            };
+            let timed_out = message.take_timed_out(now);
+            if message.is_failed() {
+                let destination = message.destination();
+                self.give_up_session(session_id, destination);
+                continue;
+            }
+            for fragment_index in timed_out {
+                debug!("Server {}: No ack for fragment {} of session {}, sending it again", self.get_id(), fragment_index, session_id);
+                if let Err(e) = self.get_event_sender().send(ServerEvent::FragmentRetransmitted(self.get_id(), session_id)) {
+                    error!("Server {}: failed to send FragmentRetransmitted event: {}", self.get_id(), e);
+                }
+                self.send_again_fragment(session_id, fragment_index);
+            }
+        }
+    }
 
+    fn give_up_session(&mut self, session_id: u64, client_id: NodeId) {
+        warn!("Server {}: Session {} to client {} given up, a fragment was sent {} times without ack", self.get_id(), session_id, client_id, MAX_SEND_ATTEMPTS);
+        self.get_sending_messages().remove(&session_id);
+        self.get_drops_counter().remove(&session_id);
+        let failed_session = FailedSession { node_id: self.get_id(), session_id, destination: client_id };
+        if let Err(e) = self.get_event_sender().send(ServerEvent::SessionFailed(failed_session)) {
+            error!("Server {}: failed to send SessionFailed event: {}", self.get_id(), e);
+        }
     }
 
     //Common functions
@@ -608,32 +737,19 @@ pub trait Server{
         //Get data
         let response = Response::ServerType(self.get_server_type());
 
-        //Serializing type
-        let response_as_string = serde_json::to_string(&response).unwrap();
-        let response_in_vec_bytes = response_as_string.as_bytes();
-        let length_response = response_in_vec_bytes.len();
-
-        //Counting fragments
-        let mut n_fragments = length_response / 128+1;
-        if n_fragments == 0 {
-            n_fragments -= 1;
-        }
+        //Send fragments
+        self.send_response(src_id, &response);
+    }
 
-        // Finding route
-        let Some(route) = self.find_path_to(src_id) else {
-            error!("Server {}: No route found to the client {}", self.get_id(), src_id);
-            return;
-        };
+    fn give_error_back(&mut self, client_id: NodeId, error: ServerError){
 
-        //Generating header
-        let header = Self::create_source_routing(route);
+        warn!("Server {}: Sending error to the client {}: {}", self.get_id(), client_id, error);
 
-        // Generating ids
-        let session_id = self.generate_unique_session_id();
+        //Creating data to send
+        let response = Response::Err(error);
 
         //Send fragments
-        info!("Sending fragments");
-        self.send_fragments(session_id, n_fragments, response_in_vec_bytes, header);
+        self.send_response(client_id, &response);
     }
 
     fn generate_unique_flood_id(&mut self) -> u64 {
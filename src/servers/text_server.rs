@@ -1,12 +1,19 @@
 use super::server::Server as MainTrait;
 use super::server::TextServer as CharTrait;
 use crate::general_use::DataScope::{UpdateAll, UpdateSelf};
-use crate::general_use::{DataScope, DisplayDataTextServer, Query, Response, ServerCommand, ServerEvent, ServerType, SpecificNodeType};
+use crate::general_use::{DataScope, DisplayDataTextServer, Query, Response, ServerCommand, ServerError, ServerEvent, ServerType, SpecificNodeType};
+use crate::fragmentation::{OutgoingMessage, Reassembler, Throughput, DEFAULT_SEND_WINDOW, MAX_QUERY_FRAGMENTS, RETRANSMISSION_CHECK_INTERVAL};
+use crate::simulation_controller::STATISTICS_INTERVAL;
+use crate::link_quality::LinkQuality;
+use super::drone_repair::{DroneRepair, DEFAULT_DRONE_REPAIR_TIMEOUT, REPAIR_CHECK_INTERVAL};
+use super::content_directory::{ContentDirectory, DirectoryChanges, RESCAN_INTERVAL};
 use crate::ui_traits::Monitoring;
-use crossbeam_channel::{select_biased, Receiver, Sender};
+use crossbeam_channel::{never, select_biased, tick, Receiver, Sender};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
-use log::{error, warn};
+use std::path::Path;
+use std::time::Duration;
+use log::{info, warn};
 use wg_2024::{
     network::NodeId,
     packet::Packet,
@@ -22,14 +29,16 @@ pub struct TextServer{
     pub id: NodeId,
 
     //Fragment-related
-    pub reassembling_messages: HashMap<SessionId, Vec<u8>>,
-    pub sending_messages: HashMap<SessionId, (Vec<u8>, NodeId)>,
+    pub reassembler: Reassembler,                               // Queries by sender and session
+    pub sending_messages: HashMap<SessionId, OutgoingMessage>,  // Responses waiting for their acks
 
     //Flood-related
     pub clients: HashSet<NodeId>,                               // Available clients
     pub topology: HashMap<NodeId, HashSet<NodeId>>,             // Nodes and their neighbours
     pub nodes: HashMap<NodeId, NodeType>,                       // Nodes and their types
     pub routes: HashMap<NodeId, Vec<NodeId>>,                   // Routes to the servers
+    pub backup_routes: HashMap<NodeId, Vec<NodeId>>,            // Routes sharing no drone with the primary ones, for the failover
+    pub backup_routes_to_repair: HashSet<NodeId>,               // Clients whose backup route has to be computed again
     pub flood_ids: Vec<FloodId>,
     pub counter: (FloodId, SessionId),
 
@@ -41,9 +50,15 @@ pub struct TextServer{
 
     //Characteristic-Server fields
     pub content: HashMap<String, String>,
+    pub content_directory: Option<ContentDirectory>,            // Source of the content, if it is not built in
 
     //Drop counter
     pub drops_counter: HashMap<SessionId, HashMap<NodeId, u8>>,
+    pub drones_under_repair: HashMap<NodeId, DroneRepair>,      // Drones waiting for the technicians, with the parked fragments
+    pub drone_repair_timeout: Duration,                         // Then the parked fragments are sent anyway
+    pub link_quality: LinkQuality,
+    pub send_window: usize,
+    pub throughput: Throughput,
 
     //Queries to process
     pub queries_to_process: VecDeque<(NodeId, Query)>,
@@ -61,13 +76,15 @@ impl TextServer{
         TextServer {
             id,
 
-            reassembling_messages: Default::default(),
+            reassembler: Reassembler::new(MAX_QUERY_FRAGMENTS),
             sending_messages: Default::default(),
 
             clients: Default::default(),                                   // Available clients
             topology: Default::default(),
             nodes: Default::default(),
             routes: Default::default(),
+            backup_routes: Default::default(),
+            backup_routes_to_repair: Default::default(),
             flood_ids: Default::default(),
             counter: (0, 0),
 
@@ -77,12 +94,73 @@ impl TextServer{
             packet_send,
 
             content,
+            content_directory: None,
 
             drops_counter: HashMap::new(),
+            drones_under_repair: HashMap::new(),
+            drone_repair_timeout: DEFAULT_DRONE_REPAIR_TIMEOUT,
+            link_quality: LinkQuality::new(),
+            send_window: DEFAULT_SEND_WINDOW,
+            throughput: Throughput::new(),
 
             queries_to_process: VecDeque::new(),
         }
     }
+
+    /// Serves the text files of a directory, the files that are not valid UTF-8 are skipped.
+    /// While running with monitoring the directory is scanned again every `RESCAN_INTERVAL`,
+    /// so added, changed and removed files show in the list of files.
+    pub fn from_directory<P: AsRef<Path>>(
+        id: NodeId,
+        directory: P,
+        to_controller_event: Sender<ServerEvent>,
+        from_controller_command: Receiver<ServerCommand>,
+        packet_recv: Receiver<Packet>,
+        packet_send: HashMap<NodeId, Sender<Packet>>,
+    ) -> Result<Self, String> {
+        let (content_directory, changes) = ContentDirectory::open(directory)?;
+        let mut server = Self::new(id, HashMap::new(), to_controller_event, from_controller_command, packet_recv, packet_send);
+        server.apply_directory_changes(changes);
+        server.content_directory = Some(content_directory);
+        Ok(server)
+    }
+
+    /// Scans the directory of the content, returns true if the content changed.
+    pub fn rescan_content_directory(&mut self) -> bool {
+        let Some(content_directory) = self.content_directory.as_mut() else {
+            return false;
+        };
+        match content_directory.rescan() {
+            Ok(changes) => self.apply_directory_changes(changes),
+            Err(e) => {
+                warn!("Server {}: {}", self.id, e);
+                false
+            }
+        }
+    }
+
+    fn apply_directory_changes(&mut self, changes: DirectoryChanges) -> bool {
+        if changes.is_empty() {
+            return false;
+        }
+        for name in changes.removed {
+            info!("Server {}: file {} removed", self.id, name);
+            self.content.remove(&name);
+        }
+        for (name, bytes) in changes.updated {
+            match String::from_utf8(bytes) {
+                Ok(text) => {
+                    info!("Server {}: file {} loaded", self.id, name);
+                    self.content.insert(name, text);
+                }
+                Err(_) => {
+                    warn!("Server {}: file {} is not a text file, it is not served", self.id, name);
+                    self.content.remove(&name);
+                }
+            }
+        }
+        true
+    }
 }
 
 
@@ -97,6 +175,9 @@ impl Monitoring for TextServer {
             connected_node_ids: neighbors,
             known_clients: self.clients.clone(),
             routing_table: self.routes.clone(),
+            route_costs: self.link_quality.route_costs(&self.routes),
+            send_window: self.send_window,
+            throughput: self.throughput.fragments_per_second(),
             text_files: text_files_list,
         };
 
@@ -106,6 +187,13 @@ impl Monitoring for TextServer {
         &mut self
     ) {
         self.send_display_data(UpdateAll);
+        let rescan_ticker = match self.content_directory {
+            Some(_) => tick(RESCAN_INTERVAL),
+            None => never(),
+        };
+        let repair_ticker = tick(REPAIR_CHECK_INTERVAL);
+        let retransmission_ticker = tick(RETRANSMISSION_CHECK_INTERVAL);
+        let throughput_ticker = tick(STATISTICS_INTERVAL);
         loop {
             select_biased! {
                 recv(self.get_from_controller_command()) -> command_res => {
@@ -130,6 +218,9 @@ impl Monitoring for TextServer {
                                 self.handle_packet(packet);
                                 self.send_display_data(UpdateSelf);
                             },
+                            ServerCommand::DroneFixed(drone_id) => {
+                                self.handle_drone_fixed(drone_id);
+                            }
                             _ => {}
                         }
                     }
@@ -140,6 +231,23 @@ impl Monitoring for TextServer {
                         self.send_display_data(UpdateSelf);
                     }
                 },
+                recv(repair_ticker) -> _ => {
+                    self.resume_expired_repairs();
+                    self.repair_backup_routes();
+                },
+                recv(retransmission_ticker) -> _ => {
+                    self.retransmit_timed_out_fragments();
+                },
+                recv(throughput_ticker) -> _ => {
+                    if self.throughput.has_changed() {
+                        self.send_display_data(UpdateSelf);
+                    }
+                },
+                recv(rescan_ticker) -> _ => {
+                    if self.rescan_content_directory() {
+                        self.send_display_data(UpdateSelf);
+                    }
+                },
             }
         }
     }
@@ -164,13 +272,15 @@ impl MainTrait for TextServer{
     fn get_topology(&mut self) -> &mut HashMap<NodeId, HashSet<NodeId>>{ &mut self.topology }
     fn get_nodes(&mut self) -> &mut HashMap<NodeId, NodeType> { &mut self.nodes }
     fn get_routes(&mut self) -> &mut HashMap<NodeId, Vec<NodeId>>{ &mut self.routes }
+    fn get_backup_routes(&mut self) -> &mut HashMap<NodeId, Vec<NodeId>>{ &mut self.backup_routes }
+    fn get_backup_routes_to_repair(&mut self) -> &mut HashSet<NodeId>{ &mut self.backup_routes_to_repair }
 
     fn get_event_sender(&self) -> &Sender<ServerEvent>{ &self.to_controller_event }
     fn get_from_controller_command(&mut self) -> &mut Receiver<ServerCommand>{ &mut self.from_controller_command }
     fn get_packet_recv(&mut self) -> &mut Receiver<Packet>{ &mut self.packet_recv }
     fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>>{ &mut self.packet_send }
     fn get_packet_send_not_mutable(&self) -> &HashMap<NodeId, Sender<Packet>>{ &self.packet_send }
-    fn get_reassembling_messages(&mut self) -> &mut HashMap<u64, Vec<u8>>{ &mut self.reassembling_messages }
+    fn get_reassembler(&mut self) -> &mut Reassembler{ &mut self.reassembler }
     fn process_query(&mut self, query: Query, src_id: NodeId) {
         // Check if there is a route to the client, save query and start the discovery process if it's not.
         if self.routes.get(&src_id).is_none() {
@@ -189,11 +299,21 @@ impl MainTrait for TextServer{
             _ => {}
         }
     }
-    fn get_sending_messages(&mut self) ->  &mut HashMap<u64, (Vec<u8>, u8)>{ &mut self.sending_messages }
-    fn get_sending_messages_not_mutable(&self) -> &HashMap<u64, (Vec<u8>, u8)>{ &self.sending_messages }
+    fn get_sending_messages(&mut self) ->  &mut HashMap<u64, OutgoingMessage>{ &mut self.sending_messages }
+    fn get_sending_messages_not_mutable(&self) -> &HashMap<u64, OutgoingMessage>{ &self.sending_messages }
 
     fn get_drops_counter(&mut self) -> &mut HashMap<u64, HashMap<NodeId, u8>>{ &mut self.drops_counter }
 
+    fn get_drones_under_repair(&mut self) -> &mut HashMap<NodeId, DroneRepair>{ &mut self.drones_under_repair }
+
+    fn get_drone_repair_timeout(&self) -> Duration{ self.drone_repair_timeout }
+
+    fn get_link_quality(&mut self) -> &mut LinkQuality{ &mut self.link_quality }
+    fn get_link_quality_not_mutable(&self) -> &LinkQuality{ &self.link_quality }
+
+    fn get_send_window(&self) -> usize{ self.send_window }
+    fn get_throughput(&mut self) -> &mut Throughput{ &mut self.throughput }
+
     fn get_queries_to_process(&mut self) -> &mut VecDeque<(NodeId, Query)>{ &mut self.queries_to_process }
 }
 
@@ -206,67 +326,24 @@ impl CharTrait for TextServer{
         //Creating data to send
         let response = Response::ListFiles(list_files.keys().cloned().collect::<Vec<String>>());
 
-        //Serializing message to send
-        let response_as_string = serde_json::to_string(&response).unwrap();
-        let response_in_vec_bytes = response_as_string.as_bytes();
-        let length_response = response_in_vec_bytes.len();
-
-        //Counting fragments
-        let mut n_fragments = length_response / 128+1;
-        if n_fragments == 0 {
-            n_fragments -= 1;
-        }
-
-        // Finding route
-        let Some(route) = self.find_path_to(client_id) else {
-            error!("Server {}: No route found to the client {}", self.get_id(), client_id);
-            return;
-        };
-
-        //Generating header
-        let header = Self::create_source_routing(route);
-
-        // Generating ids
-        let session_id = self.generate_unique_session_id();
-
         //Send fragments
-        self.send_fragments(session_id, n_fragments,response_in_vec_bytes, header);
+        self.send_response(client_id, &response);
 
     }
 
     fn give_file_back(&mut self, client_id: NodeId, file_key: String) {
 
         //Get file
-        let file:&String = self.content.get(&file_key).unwrap();
-
-        //Creating data to send
-        let response = Response::File(file.clone());
-
-        //Serializing message to send
-        let response_as_string = serde_json::to_string(&response).unwrap();
-        let response_in_vec_bytes = response_as_string.as_bytes();
-        let length_response = response_in_vec_bytes.len();
-
-        //Counting fragments
-        let mut n_fragments = length_response / 128+1;
-        if n_fragments == 0 {
-            n_fragments -= 1;
-        }
-
-        // Finding route
-        let Some(route) = self.find_path_to(client_id) else {
-            error!("Server {}: No route found to the client {}", self.get_id(), client_id);
+        let Some(file) = self.content.get(&file_key) else {
+            self.give_error_back(client_id, ServerError::FileNotFound(file_key));
             return;
         };
 
-        //Generating header
-        let header = Self::create_source_routing(route);
-
-        // Generating ids
-        let session_id = self.generate_unique_session_id();
+        //Creating data to send
+        let response = Response::File(file.clone());
 
         //Send fragments
-        self.send_fragments(session_id, n_fragments,response_in_vec_bytes, header);
+        self.send_response(client_id, &response);
 
     }
 }
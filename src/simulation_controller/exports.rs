@@ -0,0 +1,31 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory of the files written for the WebSocket clients, which only choose their name.
+pub const EXPORT_DIRECTORY: &str = "exports";
+
+/// Path in `EXPORT_DIRECTORY` of a file named by a WebSocket client. Only plain names are accepted
+/// (letters, digits, '.', '_' and '-', not starting with '.'), so no other file of the machine can be written.
+pub fn export_path(file_name: &str) -> Result<PathBuf, String> {
+    let is_plain = !file_name.is_empty()
+        && !file_name.starts_with('.')
+        && file_name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !is_plain {
+        return Err(format!("'{}' is not a plain file name", file_name));
+    }
+    fs::create_dir_all(EXPORT_DIRECTORY)
+        .map_err(|e| format!("Unable to create the directory {}: {}", EXPORT_DIRECTORY, e))?;
+    Ok(Path::new(EXPORT_DIRECTORY).join(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_leaving_the_export_directory_are_refused() {
+        for file_name in ["", ".", "..", "../run.jsonl", "/etc/passwd", "logs/run.jsonl", "logs\\run.jsonl", ".hidden", "run .jsonl"] {
+            assert!(export_path(file_name).is_err(), "{:?} accepted", file_name);
+        }
+    }
+}
@@ -1,4 +1,13 @@
 mod simulation_controller;
 mod monitoring;
+mod packet_log;
+mod statistics;
+mod spawning;
+mod topology;
+mod exports;
 
-pub use simulation_controller::*;
\ No newline at end of file
+pub use simulation_controller::*;
+pub use packet_log::*;
+pub use statistics::*;
+pub use topology::*;
+pub use exports::*;
\ No newline at end of file
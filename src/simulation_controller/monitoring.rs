@@ -1,11 +1,12 @@
 use std::collections::{HashSet};
-use crossbeam_channel::{select_biased, Sender};
-use log::{debug, info};
+use crossbeam_channel::{select_biased, tick, Sender};
+use log::{debug, info, warn};
 use crate::clients::client_chen::{NodeId};
-use crate::simulation_controller::SimulationController;
+use crate::simulation_controller::{export_path, PacketLogData, SimulationController, TopologyExportData, DEFAULT_QUERY_LIMIT, STATISTICS_INTERVAL};
 use crate::ui_traits::{SimulationControllerMonitoring};
 use crate::websocket::{WsCommand};
-use crate::general_use::{ClientCommand, ClientEvent, DataScope, DisplayDataSimulationController, ServerCommand, ServerEvent};
+use crate::randomness;
+use crate::general_use::{ClientCommand, ClientEvent, DataScope, DisplayDataSimulationController, FailedSession, ServerCommand, ServerEvent, TopologyOperation};
 
 impl SimulationControllerMonitoring for SimulationController {
     fn send_display_data(&mut self, sender_to_gui: Sender<String>) {
@@ -20,6 +21,7 @@ impl SimulationControllerMonitoring for SimulationController {
             media_servers_data: self.media_servers_data.clone(),
             drones_data: self.drones_data.clone(),
             topology: topology_with_types,
+            seed: randomness::seed(),
         };
         let json_string = serde_json::to_string(&display_data).unwrap();
         info!("Controller has sent the data of all the nodes {:?}", display_data);
@@ -37,10 +39,8 @@ impl SimulationControllerMonitoring for SimulationController {
         edge_nodes.extend(self.command_senders_servers.keys().cloned().collect::<HashSet<NodeId>>());
 
         self.updating_nodes = edge_nodes.clone();
+        let statistics_ticker = tick(STATISTICS_INTERVAL);
         loop {
-            self.process_packet_sent_events();
-            self.process_packet_dropped_events();
-            self.process_controller_shortcut_events();
             select_biased! {
                 recv(self.ws_command_receiver) -> command_res => {
                     debug!("Controller received command {:?}", command_res);
@@ -48,6 +48,15 @@ impl SimulationControllerMonitoring for SimulationController {
                         self.handle_ws_command(command, &sender_to_gui);
                     }
                 },
+                recv(statistics_ticker) -> _ => {
+                    self.send_statistics(&sender_to_gui);
+                },
+                // Before the clients and the servers, their traffic must not delay the drops and the shortcuts
+                recv(self.drone_event_receiver) -> drone_event => {
+                    if let Ok(event) = drone_event {
+                        self.handle_drone_event(event);
+                    }
+                },
                 recv(self.client_event_receiver) -> client_event => {
                     if let Ok(event) = client_event {
                         //debug!("Controller received client event {:?}", event);
@@ -90,7 +99,19 @@ impl SimulationControllerMonitoring for SimulationController {
                                 self.fix_drone(id, sender, sender_to_gui.clone());
                             },
                             ClientEvent::ControllerShortcut(packet) => {
-                                self.send_shortcut(packet);
+                                self.handle_controller_shortcut(packet);
+                            },
+                            ClientEvent::FragmentRetransmitted(id, session_id) => {
+                                conditional_data_scope = DataScope::UpdateSelf;
+                                self.state.statistics.record_retransmission(id, session_id);
+                            },
+                            ClientEvent::QueryAnswered(_, query_kind, latency) => {
+                                conditional_data_scope = DataScope::UpdateSelf;
+                                self.state.statistics.record_latency(query_kind, latency);
+                            },
+                            ClientEvent::SessionFailed(failed_session) => {
+                                conditional_data_scope = DataScope::UpdateSelf;
+                                self.send_session_failed(failed_session, &sender_to_gui);
                             },
                             _ => {}
                         }
@@ -161,7 +182,15 @@ impl SimulationControllerMonitoring for SimulationController {
                                 self.fix_drone(id, sender, sender_to_gui.clone());
                             }
                             ServerEvent::ControllerShortcut(packet) => {
-                                self.send_shortcut(packet);
+                                self.handle_controller_shortcut(packet);
+                            },
+                            ServerEvent::FragmentRetransmitted(id, session_id) => {
+                                conditional_data_scope = DataScope::UpdateSelf;
+                                self.state.statistics.record_retransmission(id, session_id);
+                            },
+                            ServerEvent::SessionFailed(failed_session) => {
+                                conditional_data_scope = DataScope::UpdateSelf;
+                                self.send_session_failed(failed_session, &sender_to_gui);
                             },
                         }
 
@@ -172,13 +201,36 @@ impl SimulationControllerMonitoring for SimulationController {
                         }
                     }
                 },
-
             }
         }
     }
 }
 
 impl SimulationController {
+    fn send_statistics(&self, sender_to_gui: &Sender<String>) {
+        let statistics = self.state.statistics.snapshot(&self.drones_data);
+        let json_string = serde_json::json!({ "Statistics": statistics }).to_string();
+        sender_to_gui.send(json_string).expect("error in sending the statistics to the websocket");
+    }
+
+    /// A node gave up a message: it is counted by the statistics and shown by the GUI.
+    fn send_session_failed(&mut self, failed_session: FailedSession, sender_to_gui: &Sender<String>) {
+        warn!("Node {} gave up session {} to {}", failed_session.node_id, failed_session.session_id, failed_session.destination);
+        self.state.statistics.record_failed_session();
+        let json_string = serde_json::json!({ "SessionFailed": failed_session }).to_string();
+        sender_to_gui.send(json_string).expect("error in sending the failed session to the websocket");
+    }
+
+    /// Sends the result of a topology change, the network data is sent again if the topology changed.
+    fn send_topology_operation(&mut self, operation: TopologyOperation, sender_to_gui: &Sender<String>) {
+        let changed = matches!(operation, TopologyOperation::DroneAdded(..) | TopologyOperation::LinkAdded(..) | TopologyOperation::LinkRemoved(..));
+        let json_enum = serde_json::to_string(&operation).unwrap();
+        sender_to_gui.send(json_enum).expect("error in sending the topology operation to the websocket");
+        if changed {
+            self.send_display_data(sender_to_gui.clone());
+        }
+    }
+
     fn handle_ws_command(&mut self, command: WsCommand, sender_to_gui: &Sender<String>) {
         match command {
             WsCommand::WsUpdateData => {
@@ -271,6 +323,98 @@ impl SimulationController {
                     }
                 }
             }
+
+            WsCommand::WsSetPdr { drone_id, pdr } => {
+                match self.request_pdr_change(drone_id, pdr, sender_to_gui) {
+                    Ok(()) => info!("PDR of drone {} set to {}", drone_id, pdr),
+                    Err(e) => warn!("PDR of drone {} not changed: {}", drone_id, e),
+                }
+            }
+
+            WsCommand::WsAddDrone { id, neighbours, pdr, brand } => {
+                let operation = match self.add_drone(id, neighbours, pdr, brand) {
+                    Ok(drone_brand) => {
+                        info!("Drone {} of brand {:?} added to the network", id, drone_brand);
+                        TopologyOperation::DroneAdded(id, drone_brand)
+                    }
+                    Err(e) => {
+                        warn!("Drone {} not added: {}", id, e);
+                        TopologyOperation::DroneNotAdded(id, e)
+                    }
+                };
+                self.send_topology_operation(operation, sender_to_gui);
+            }
+
+            WsCommand::WsAddLink { from, to } => {
+                let operation = match self.add_link(from, to) {
+                    Ok(()) => {
+                        info!("Link {} - {} added", from, to);
+                        TopologyOperation::LinkAdded(from, to)
+                    }
+                    Err(e) => {
+                        warn!("Link {} - {} not added: {}", from, to, e);
+                        TopologyOperation::LinkNotChanged(from, to, e)
+                    }
+                };
+                self.send_topology_operation(operation, sender_to_gui);
+            }
+
+            WsCommand::WsRemoveLink { from, to } => {
+                let operation = match self.remove_link(from, to) {
+                    Ok(()) => {
+                        info!("Link {} - {} removed", from, to);
+                        TopologyOperation::LinkRemoved(from, to)
+                    }
+                    Err(e) => {
+                        warn!("Link {} - {} not removed: {}", from, to, e);
+                        TopologyOperation::LinkNotChanged(from, to, e)
+                    }
+                };
+                self.send_topology_operation(operation, sender_to_gui);
+            }
+
+            WsCommand::WsExportTopology { file_name } => {
+                let answer = match &file_name {
+                    Some(file_name) => self.export_topology_file(file_name)
+                        .map(|path| TopologyExportData::TopologyExported { path }),
+                    None => self.export_toml().map(TopologyExportData::TopologyToml),
+                };
+                let answer = answer.unwrap_or_else(|error| {
+                    warn!("Topology not exported: {}", error);
+                    TopologyExportData::TopologyExportFailed { path: file_name, error }
+                });
+                let json_string = serde_json::to_string(&answer).unwrap();
+                sender_to_gui.send(json_string).expect("error in sending the exported topology to the websocket");
+            }
+
+            WsCommand::WsQueryPacketLog { filter, limit } => {
+                let entries = self.state.packet_history.query(&filter, limit.unwrap_or(DEFAULT_QUERY_LIMIT));
+                let packet_log = PacketLogData::PacketLog {
+                    total_recorded: self.state.packet_history.total_recorded(),
+                    entries,
+                };
+                let json_string = serde_json::to_string(&packet_log).unwrap();
+                sender_to_gui.send(json_string).expect("error in sending the packet log to the websocket");
+            }
+
+            WsCommand::WsExportPacketLog { file_name, filter } => {
+                let exported = export_path(&file_name).and_then(|path| {
+                    self.state.packet_history.export_jsonl(&path, &filter)
+                        .map(|n_entries| (path.display().to_string(), n_entries))
+                });
+                let answer = match exported {
+                    Ok((path, n_entries)) => {
+                        info!("Exported {} packet log entries to {}", n_entries, path);
+                        PacketLogData::PacketLogExported { path, n_entries }
+                    }
+                    Err(error) => {
+                        warn!("Failed to export the packet log: {}", error);
+                        PacketLogData::PacketLogExportFailed { path: file_name, error }
+                    }
+                };
+                let json_string = serde_json::to_string(&answer).unwrap();
+                sender_to_gui.send(json_string).expect("error in sending the packet log to the websocket");
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,263 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use wg_2024::{
+    network::NodeId,
+    packet::{Packet, PacketType},
+};
+use crate::general_use::{FragmentIndex, SessionId};
+
+/// Number of entries kept by the controller, the oldest ones are overwritten.
+pub const PACKET_LOG_CAPACITY: usize = 10_000;
+/// Number of entries sent to the GUI when the query has no limit.
+pub const DEFAULT_QUERY_LIMIT: usize = 200;
+
+/// What happened to a packet recorded in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PacketEvent {
+    Sent,
+    Dropped,
+    Shortcut,       // Delivered by the controller instead of the drones
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PacketKind {
+    MsgFragment,
+    Ack,
+    Nack,
+    FloodRequest,
+    FloodResponse,
+}
+
+impl From<&PacketType> for PacketKind {
+    fn from(packet_type: &PacketType) -> Self {
+        match packet_type {
+            PacketType::MsgFragment(_) => PacketKind::MsgFragment,
+            PacketType::Ack(_) => PacketKind::Ack,
+            PacketType::Nack(_) => PacketKind::Nack,
+            PacketType::FloodRequest(_) => PacketKind::FloodRequest,
+            PacketType::FloodResponse(_) => PacketKind::FloodResponse,
+        }
+    }
+}
+
+/// One entry of the packet log.
+#[derive(Debug, Clone, Serialize)]
+pub struct PacketInfo {
+    pub timestamp_ms: u64,                      // Since the start of the simulation
+    pub event: PacketEvent,
+    pub packet_kind: PacketKind,
+    pub session_id: SessionId,
+    pub fragment_index: Option<FragmentIndex>,  // Fragments, Acks and Nacks
+    pub source: NodeId,
+    pub destination: NodeId,
+    pub hop_index: usize,
+    pub hop: Option<NodeId>,                    // Node at hop_index in the route
+}
+
+impl PacketInfo {
+    /// Returns true if the node is the source, the destination or the current hop of the packet.
+    pub fn involves(&self, node_id: NodeId) -> bool {
+        self.source == node_id || self.destination == node_id || self.hop == Some(node_id)
+    }
+}
+
+/// Filters of a packet log query, every field left empty matches all the entries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PacketLogFilter {
+    #[serde(default, with = "crate::websocket::optional_stringified")]
+    pub node_id: Option<NodeId>,
+    #[serde(default, with = "crate::websocket::optional_stringified")]
+    pub session_id: Option<SessionId>,
+    #[serde(default)]
+    pub packet_kind: Option<PacketKind>,
+}
+
+impl PacketLogFilter {
+    pub fn matches(&self, info: &PacketInfo) -> bool {
+        self.node_id.map_or(true, |node_id| info.involves(node_id))
+            && self.session_id.map_or(true, |session_id| info.session_id == session_id)
+            && self.packet_kind.map_or(true, |packet_kind| info.packet_kind == packet_kind)
+    }
+}
+
+/// Answers of the packet log commands sent to the GUI.
+#[derive(Debug, Clone, Serialize)]
+pub enum PacketLogData {
+    PacketLog {
+        total_recorded: u64,        // Including the entries already overwritten
+        entries: Vec<PacketInfo>,
+    },
+    PacketLogExported {
+        path: String,
+        n_entries: usize,
+    },
+    PacketLogExportFailed {
+        path: String,
+        error: String,
+    },
+}
+
+/// Bounded ring buffer of the packet events seen by the controller.
+pub struct PacketLog {
+    entries: VecDeque<PacketInfo>,
+    capacity: usize,
+    total_recorded: u64,
+    started_at: Instant,
+}
+
+impl PacketLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            total_recorded: 0,
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, packet: &Packet, source: NodeId, destination: NodeId, event: PacketEvent) {
+        let fragment_index = match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => Some(fragment.fragment_index),
+            PacketType::Ack(ack) => Some(ack.fragment_index),
+            PacketType::Nack(nack) => Some(nack.fragment_index),
+            PacketType::FloodRequest(_) | PacketType::FloodResponse(_) => None,
+        };
+        let hop_index = packet.routing_header.hop_index;
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(PacketInfo {
+            timestamp_ms: self.started_at.elapsed().as_millis() as u64,
+            event,
+            packet_kind: PacketKind::from(&packet.pack_type),
+            session_id: packet.session_id,
+            fragment_index,
+            source,
+            destination,
+            hop_index,
+            hop: packet.routing_header.hops.get(hop_index).copied(),
+        });
+        self.total_recorded += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn total_recorded(&self) -> u64 {
+        self.total_recorded
+    }
+
+    /// Iterates the entries from the oldest one.
+    pub fn iter(&self) -> impl Iterator<Item = &PacketInfo> {
+        self.entries.iter()
+    }
+
+    /// Returns the last `limit` entries matching the filter, from the oldest one.
+    pub fn query(&self, filter: &PacketLogFilter, limit: usize) -> Vec<PacketInfo> {
+        let mut entries: Vec<PacketInfo> = self.entries.iter()
+            .rev()
+            .filter(|info| filter.matches(info))
+            .take(limit)
+            .cloned()
+            .collect();
+        entries.reverse();
+        entries
+    }
+
+    /// Writes the entries matching the filter as JSON Lines, returns how many were written.
+    pub fn export_jsonl<P: AsRef<Path>>(&self, path: P, filter: &PacketLogFilter) -> Result<usize, String> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+
+        let mut n_entries = 0;
+        for info in self.entries.iter().filter(|info| filter.matches(info)) {
+            let line = serde_json::to_string(info).map_err(|e| e.to_string())?;
+            writeln!(writer, "{}", line)
+                .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+            n_entries += 1;
+        }
+        writer.flush().map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+        Ok(n_entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::{Ack, Fragment, Nack, NackType, FRAGMENT_DSIZE};
+
+    fn packet(session_id: SessionId, hops: Vec<NodeId>, hop_index: usize, pack_type: PacketType) -> Packet {
+        Packet { routing_header: SourceRoutingHeader { hop_index, hops }, session_id, pack_type }
+    }
+
+    fn fragment(fragment_index: FragmentIndex) -> PacketType {
+        PacketType::MsgFragment(Fragment { fragment_index, total_n_fragments: 4, length: 0, data: [0; FRAGMENT_DSIZE] })
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted_at_capacity() {
+        let mut log = PacketLog::new(3);
+        for session_id in 0..5 {
+            log.record(&packet(session_id, vec![1, 2, 3], 1, fragment(0)), 1, 3, PacketEvent::Sent);
+        }
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.total_recorded(), 5);
+        let session_ids: Vec<SessionId> = log.iter().map(|info| info.session_id).collect();
+        assert_eq!(session_ids, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn log_without_capacity_keeps_nothing() {
+        let mut log = PacketLog::new(0);
+        log.record(&packet(0, vec![1, 2, 3], 1, fragment(0)), 1, 3, PacketEvent::Sent);
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn entries_are_filtered_by_node_session_and_kind() {
+        let mut log = PacketLog::new(10);
+        log.record(&packet(1, vec![10, 2, 20], 1, fragment(0)), 10, 20, PacketEvent::Sent);
+        log.record(&packet(1, vec![20, 2, 10], 1, PacketType::Ack(Ack { fragment_index: 0 })), 20, 10, PacketEvent::Sent);
+        log.record(&packet(2, vec![11, 3, 21], 1, fragment(0)), 11, 21, PacketEvent::Dropped);
+        let nack = Nack { fragment_index: 0, nack_type: NackType::Dropped };
+        log.record(&packet(2, vec![3, 11], 1, PacketType::Nack(nack)), 3, 11, PacketEvent::Shortcut);
+
+        let sessions = |filter: PacketLogFilter| -> Vec<(SessionId, PacketKind)> {
+            log.query(&filter, DEFAULT_QUERY_LIMIT).iter().map(|info| (info.session_id, info.packet_kind)).collect()
+        };
+        assert_eq!(sessions(PacketLogFilter::default()).len(), 4);
+        // The source, the destination and the current hop are involved
+        assert_eq!(sessions(PacketLogFilter { node_id: Some(2), ..Default::default() }), vec![(1, PacketKind::MsgFragment), (1, PacketKind::Ack)]);
+        assert_eq!(sessions(PacketLogFilter { node_id: Some(11), ..Default::default() }), vec![(2, PacketKind::MsgFragment), (2, PacketKind::Nack)]);
+        assert_eq!(sessions(PacketLogFilter { session_id: Some(2), ..Default::default() }), vec![(2, PacketKind::MsgFragment), (2, PacketKind::Nack)]);
+        assert_eq!(sessions(PacketLogFilter { packet_kind: Some(PacketKind::MsgFragment), ..Default::default() }), vec![(1, PacketKind::MsgFragment), (2, PacketKind::MsgFragment)]);
+        assert_eq!(sessions(PacketLogFilter { node_id: Some(10), session_id: Some(1), packet_kind: Some(PacketKind::Ack) }), vec![(1, PacketKind::Ack)]);
+        assert!(sessions(PacketLogFilter { node_id: Some(99), ..Default::default() }).is_empty());
+    }
+
+    #[test]
+    fn query_returns_the_newest_entries_from_the_oldest() {
+        let mut log = PacketLog::new(10);
+        for session_id in 0..6 {
+            log.record(&packet(session_id, vec![1, 2, 3], 1, fragment(0)), 1, 3, PacketEvent::Sent);
+        }
+        let session_ids: Vec<SessionId> = log.query(&PacketLogFilter::default(), 2).iter().map(|info| info.session_id).collect();
+        assert_eq!(session_ids, vec![4, 5]);
+    }
+}
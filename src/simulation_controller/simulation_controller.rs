@@ -7,26 +7,24 @@ use wg_2024::{
     network::NodeId,
     packet::{NodeType, Packet, PacketType}
 };
-use crate::general_use::{ClientCommand, ClientEvent, ClientType, ServerCommand, ServerEvent, ServerType, ServerId, Query, DisplayDataWebBrowser, DisplayDataCommunicationServer, DisplayDataMediaServer, DisplayDataChatClient, DisplayDataTextServer, DisplayDataDrone, SpecificNodeType, DroneId, TechnicalOperationOnDrone};
+use crate::general_use::{ClientCommand, ClientEvent, ClientType, ServerCommand, ServerEvent, ServerType, ServerId, Query, DisplayDataWebBrowser, DisplayDataCommunicationServer, DisplayDataMediaServer, DisplayDataChatClient, DisplayDataTextServer, DisplayDataDrone, SpecificNodeType, DroneId, TechnicalOperationOnDrone, UsingTimes};
 use crate::websocket::WsCommand;
+use super::packet_log::{PacketEvent, PacketLog, PACKET_LOG_CAPACITY};
+use super::statistics::NetworkStatistics;
 use std::collections::hash_map::Entry;
 use rand::Rng;
+use rand::rngs::StdRng;
+use crate::randomness;
+use crate::network_initializer::DroneBrand;
 
 pub struct SimulationState {
     pub nodes: HashMap<NodeId, NodeType>,
     pub topology: HashMap<NodeId, Vec<NodeId>>,
-    pub packet_history: Vec<PacketInfo>,
+    pub packet_history: PacketLog,
+    pub statistics: NetworkStatistics,
 }
 
 
-#[derive(Debug, Clone)]
-pub struct PacketInfo {
-    pub source: NodeId,
-    pub destination: NodeId,
-    pub packet_type: PacketType,
-    pub dropped: bool,
-}
-
 pub struct SimulationController {
     pub state: SimulationState,
     pub drone_event_sender: Sender<DroneEvent>,
@@ -41,6 +39,10 @@ pub struct SimulationController {
     pub packet_senders: HashMap<NodeId, Sender<Packet>>,
 
     fixed_drones: HashSet<NodeId>,
+    rng: StdRng,
+    pub(super) drone_brands: Vec<DroneBrand>,                        // Brands chosen by choose_drone_brand_evenly
+    pub(super) drone_brand_usage: HashMap<DroneBrand, UsingTimes>,
+    pub(super) brand_rng: StdRng,
 
     //for the monitoring
     pub web_clients_data: HashMap<NodeId, DisplayDataWebBrowser>,
@@ -70,7 +72,8 @@ impl SimulationController {
             state: SimulationState {
                 nodes: HashMap::new(),
                 topology: HashMap::new(),
-                packet_history: Vec::new(),
+                packet_history: PacketLog::new(PACKET_LOG_CAPACITY),
+                statistics: NetworkStatistics::new(),
             },
             command_senders_drones: HashMap::new(),
             command_senders_clients: HashMap::new(),
@@ -84,6 +87,10 @@ impl SimulationController {
             packet_senders: HashMap::new(),
 
             fixed_drones: HashSet::new(),
+            rng: randomness::seeded_rng("simulation_controller"),
+            drone_brands: DroneBrand::iter().collect(),
+            drone_brand_usage: DroneBrand::iter().map(|brand| (brand, 0)).collect(),
+            brand_rng: randomness::seeded_rng("drone_brands"),
 
             //for the monitoring
             web_clients_data: HashMap::new(),
@@ -97,27 +104,12 @@ impl SimulationController {
         }
     }
 
-    pub(crate) fn process_packet_sent_events(&mut self) {
-        if let Ok(event) = self.drone_event_receiver.try_recv() {
-            if let DroneEvent::PacketSent(packet) = event {
-                self.handle_packet_sent(packet);
-            }
-        }
-    }
-
-    pub(crate) fn process_packet_dropped_events(&mut self) {
-        if let Ok(event) = self.drone_event_receiver.try_recv() {
-            if let DroneEvent::PacketDropped(packet) = event {
-                self.handle_packet_dropped(packet);
-            }
-        }
-    }
-
-    pub(crate) fn process_controller_shortcut_events(&mut self) {
-        if let Ok(event) = self.drone_event_receiver.try_recv() {  // Receive event or continue if none is available
-            if let DroneEvent::ControllerShortcut(packet) = event {   // Check event type
-                self.send_shortcut(packet);
-            }
+    /// Dispatches every event coming from the drones.
+    pub(crate) fn handle_drone_event(&mut self, event: DroneEvent) {
+        match event {
+            DroneEvent::PacketSent(packet) => self.handle_packet_sent(packet),
+            DroneEvent::PacketDropped(packet) => self.handle_packet_dropped(packet),
+            DroneEvent::ControllerShortcut(packet) => self.handle_controller_shortcut(packet),
         }
     }
 
@@ -272,8 +264,7 @@ impl SimulationController {
             }
             info!("Drone {} is already fixed", drone_id);
         } else {
-            let mut rng = rand::thread_rng();
-            new_pdr = rng.gen_range(0.0..=0.1); // Generate random PDR between 0 and 0.1
+            new_pdr = self.rng.gen_range(0.0..=0.1); // Generate random PDR between 0 and 0.1
             self.set_packet_drop_rate(drone_id, new_pdr); // Update the drone's PDR
             info!("Drone {} has been fixed! New PDR: {}", drone_id, new_pdr);
 
@@ -355,24 +346,26 @@ impl SimulationController {
 
     /// Handles `PacketSent` events, adding packet information to the history.
     fn handle_packet_sent(&mut self, packet: Packet) {
-        let destination = self.get_destination_from_packet(&packet).unwrap_or(255); // Provide default if None
-
-        self.state.packet_history.push(PacketInfo {
-            source: self.get_source_from_packet(&packet),
-            destination,
-            packet_type: packet.pack_type.clone(),
-            dropped: false,
-        });
+        self.record_packet_event(&packet, PacketEvent::Sent);
     }
 
     /// Handles `PacketDropped` events, adding packet information to the history.
     fn handle_packet_dropped(&mut self, packet: Packet) {
-        self.state.packet_history.push(PacketInfo {
-            source: self.get_source_from_packet(&packet),
-            destination: self.get_destination_from_packet(&packet).unwrap_or(255), // 255 is a valid default
-            packet_type: packet.pack_type.clone(),
-            dropped: true,
-        });
+        self.record_packet_event(&packet, PacketEvent::Dropped);
+    }
+
+    /// Handles `ControllerShortcut` events of drones, clients and servers, adding packet information
+    /// to the history before delivering it.
+    pub(crate) fn handle_controller_shortcut(&mut self, packet: Packet) {
+        self.record_packet_event(&packet, PacketEvent::Shortcut);
+        self.send_shortcut(packet);
+    }
+
+    pub(crate) fn record_packet_event(&mut self, packet: &Packet, event: PacketEvent) {
+        let source = self.get_source_from_packet(packet);
+        let destination = self.get_destination_from_packet(packet).unwrap_or(255); // 255 is a valid default
+        self.state.packet_history.record(packet, source, destination, event);
+        self.state.statistics.record_drone_event(packet, event, &self.state.nodes);
     }
 
     pub fn add_sender(&mut self, node_id: NodeId, node_type: NodeType, connected_node_id: NodeId, sender: Sender<Packet>) {
@@ -424,6 +417,9 @@ impl SimulationController {
                     if let Err(e) = command_sender.send(DroneCommand::RemoveSender(connected_node_id)) {  // Send command, return error if fails
                         return Err(format!("Failed to send RemoveSender command to drone {}: {:?}", node_id, e));
                     }
+                    if let Some(display_data_drone) = self.drones_data.get_mut(&node_id) {
+                        display_data_drone.connected_nodes_ids.retain(|&id| id != connected_node_id);
+                    }
                     Ok(())
 
                 } else {
@@ -466,6 +462,35 @@ impl SimulationController {
         }
     }
 
+    /// Changes the PDR of a drone on request of the user, the result is sent to the GUI.
+    /// The drone can be fixed again by the technicians afterwards.
+    pub fn request_pdr_change(&mut self, drone_id: DroneId, pdr: f32, sender_to_gui: &Sender<String>) -> Result<(), String> {
+        let result = if !(0.0..=1.0).contains(&pdr) {
+            Err(format!("PDR {} is not between 0 and 1", pdr))
+        } else if let Some(command_sender) = self.command_senders_drones.get(&drone_id) {
+            command_sender.send(DroneCommand::SetPacketDropRate(pdr))
+                .map_err(|e| format!("Failed to send SetPacketDropRate command to drone {}: {:?}", drone_id, e))
+        } else {
+            Err(format!("Drone {} not found in controller", drone_id))
+        };
+
+        let operation = match &result {
+            Ok(()) => {
+                if let Some(drone_data) = self.drones_data.get_mut(&drone_id) {
+                    drone_data.pdr = pdr;
+                }
+                self.fixed_drones.remove(&drone_id);
+                TechnicalOperationOnDrone::PdrChanged(drone_id, pdr)
+            }
+            Err(e) => TechnicalOperationOnDrone::PdrNotChanged(drone_id, e.clone()),
+        };
+        let json_enum = serde_json::to_string(&operation).unwrap();
+        if let Err(e) = sender_to_gui.send(json_enum) {
+            warn!("Error sending PDR change result to WebSocket: {}", e);
+        }
+        result
+    }
+
     pub fn request_drone_crash(
         &mut self,
         drone_id: NodeId,
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::thread;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rand::seq::SliceRandom;
+use wg_2024::{
+    config::Drone as ConfigDrone,
+    controller::{DroneCommand, DroneEvent},
+    drone::Drone as TraitDrone,
+    network::NodeId,
+    packet::{NodeType, Packet},
+};
+use log::warn;
+use crate::general_use::{ClientCommand, DisplayDataDrone, DroneId, ServerCommand, SpecificNodeType, TopologyChangeError};
+use crate::network_initializer::DroneBrand;
+use crate::simulation_controller::SimulationController;
+
+//Drones
+use rusty_drones::RustyDrone;
+use rolling_drone::RollingDrone;
+use rustable_drone::RustableDrone;
+use rustbusters_drone::RustBustersDrone;
+use rusteze_drone::RustezeDrone;
+use fungi_drone::FungiDrone;
+use bagel_bomber::BagelBomber;
+use skylink::SkyLinkDrone;
+use RF_drone::RustAndFurious;
+use bobry_w_locie::drone::BoberDrone;
+
+type DroneParams = (
+    DroneId,
+    Sender<DroneEvent>,
+    Receiver<DroneCommand>,
+    Receiver<Packet>,
+    HashMap<NodeId, Sender<Packet>>,
+    f32,
+);
+
+impl SimulationController {
+    /// Spawns a drone of the given brand without neighbours and registers it in the controller.
+    /// The neighbours are connected afterwards with `add_sender`.
+    pub fn spawn_drone(&mut self, drone_id: DroneId, pdr: f32, drone_brand: DroneBrand) -> Result<(), String> {
+        // Adding channel to controller
+        let (command_sender, command_receiver) = unbounded();
+
+        // Creating channels with the connected nodes
+        let (packet_sender, packet_receiver) = unbounded();
+
+        let drone_params = (
+            drone_id,
+            self.drone_event_sender.clone(),
+            command_receiver,
+            packet_receiver,
+            HashMap::new(),
+            pdr,
+        );
+
+        match drone_brand {
+            DroneBrand::RustyDrone => self.create_and_spawn_drone::<RustyDrone>(drone_params),
+            DroneBrand::RollingDrones => self.create_and_spawn_drone::<RollingDrone>(drone_params),
+            DroneBrand::Rustable => self.create_and_spawn_drone::<RustableDrone>(drone_params),
+            DroneBrand::RustBusters => self.create_and_spawn_drone::<RustBustersDrone>(drone_params),
+            DroneBrand::RustEze => self.create_and_spawn_drone::<RustezeDrone>(drone_params),
+            DroneBrand::Fungi => self.create_and_spawn_drone::<FungiDrone>(drone_params),
+            DroneBrand::BagelBomber => self.create_and_spawn_drone::<BagelBomber>(drone_params),
+            DroneBrand::SkyLink => self.create_and_spawn_drone::<SkyLinkDrone>(drone_params),
+            DroneBrand::RustAndFurious => self.create_and_spawn_drone::<RustAndFurious>(drone_params),
+            DroneBrand::BobryWLucie => self.create_and_spawn_drone::<BoberDrone>(drone_params),
+            DroneBrand::KrustyDrone | DroneBrand::Undefined => {
+                return Err(format!("Drones of brand {:?} cannot be spawned", drone_brand));
+            }
+        }
+
+        if let Some(usage) = self.drone_brand_usage.get_mut(&drone_brand) {
+            *usage += 1;
+        }
+        self.register_drone(drone_id, command_sender);
+        self.packet_senders.insert(drone_id, packet_sender);
+        self.drones_data.insert(drone_id, DisplayDataDrone {
+            node_id: drone_id,
+            node_type: SpecificNodeType::Drone,
+            drone_brand,
+            connected_nodes_ids: Vec::new(),
+            pdr,
+        });
+        Ok(())
+    }
+
+    /// Spawns a drone at runtime and connects it to its neighbours, the clients and the servers flood again
+    /// so their routes can use it. The resulting network must follow the rules of the initialization file.
+    /// Returns the brand of the drone.
+    pub fn add_drone(&mut self, drone_id: DroneId, neighbours: Vec<NodeId>, pdr: f32, drone_brand: Option<DroneBrand>) -> Result<DroneBrand, TopologyChangeError> {
+        if !(0.0..=1.0).contains(&pdr) {
+            return Err(TopologyChangeError::InvalidPdr(pdr));
+        }
+        // A chosen brand follows the same force and exclude rules of the other drones
+        if let Some(brand) = drone_brand {
+            if !self.drone_brands.contains(&brand) {
+                return Err(TopologyChangeError::BrandNotInPool(brand));
+            }
+        }
+        // Crashed drones stay in the nodes, their ids are not reused
+        if self.state.nodes.contains_key(&drone_id) {
+            return Err(TopologyChangeError::NodeAlreadyExists(drone_id));
+        }
+        if neighbours.is_empty() {
+            return Err(TopologyChangeError::InvalidTopology(format!("Drone {} needs at least one neighbour", drone_id)));
+        }
+        if neighbours.contains(&drone_id) {
+            return Err(TopologyChangeError::SameNode(drone_id));
+        }
+        if let Some(&unknown) = neighbours.iter().find(|neighbour| !self.state.topology.contains_key(neighbour)) {
+            return Err(TopologyChangeError::UnknownNode(unknown));
+        }
+
+        // The network with the new drone must pass the same checks of the initialization file
+        let mut topology = self.state.topology.clone();
+        for neighbour in neighbours.iter() {
+            topology.entry(*neighbour).or_default().push(drone_id);
+        }
+        let mut config = self.config_from_topology(&topology);
+        config.drone.push(ConfigDrone {
+            id: drone_id,
+            connected_node_ids: neighbours.clone(),
+            pdr,
+        });
+        self.validate_change(&config)?;
+
+        let drone_brand = match drone_brand {
+            Some(drone_brand) => drone_brand,
+            None => self.choose_drone_brand_evenly(),
+        };
+        self.spawn_drone(drone_id, pdr, drone_brand).map_err(TopologyChangeError::CommandFailed)?;
+        self.state.nodes.insert(drone_id, NodeType::Drone);
+        self.state.topology.insert(drone_id, neighbours.clone());
+
+        // Connecting both directions
+        let drone_sender = self.packet_senders.get(&drone_id).cloned()
+            .ok_or_else(|| TopologyChangeError::CommandFailed(format!("Packet sender of drone {} not found", drone_id)))?;
+        for neighbour in neighbours {
+            let (Some(&neighbour_type), Some(neighbour_sender)) = (self.state.nodes.get(&neighbour), self.packet_senders.get(&neighbour).cloned()) else {
+                warn!("Packet sender of node {} not found", neighbour);
+                continue;
+            };
+            self.add_sender(drone_id, NodeType::Drone, neighbour, neighbour_sender);
+            self.add_sender(neighbour, neighbour_type, drone_id, drone_sender.clone());
+            if let Some(neighbour_links) = self.state.topology.get_mut(&neighbour) {
+                neighbour_links.push(drone_id);
+            }
+        }
+
+        self.restart_flooding();
+        Ok(drone_brand)
+    }
+
+    /// Asks every client and server to discover the network again.
+    pub(crate) fn restart_flooding(&self) {
+        for (client_id, (sender, _)) in self.command_senders_clients.iter() {
+            if let Err(e) = sender.send(ClientCommand::StartFlooding) {
+                warn!("Failed to send StartFlooding to client {}: {:?}", client_id, e);
+            }
+        }
+        for (server_id, (sender, _)) in self.command_senders_servers.iter() {
+            if let Err(e) = sender.send(ServerCommand::StartFlooding) {
+                warn!("Failed to send StartFlooding to server {}: {:?}", server_id, e);
+            }
+        }
+    }
+
+    /// Sets the brands given to the drones without a chosen brand, the usage counts start again from zero.
+    /// The order of the brands is kept, so the same seed gives the same choices.
+    pub fn set_drone_brands(&mut self, drone_brands: Vec<DroneBrand>) {
+        self.drone_brand_usage = drone_brands.iter().map(|&brand| (brand, 0)).collect();
+        self.drone_brands = drone_brands;
+    }
+
+    /// Chooses randomly one of the least used brands.
+    pub(crate) fn choose_drone_brand_evenly(&mut self) -> DroneBrand {
+        // We retain the Brands that are least used.
+        if let Some(&min_usage) = self.drone_brand_usage.values().min() {
+            let min_usage_drone_brands: Vec<_> = self.drone_brands
+                .iter()
+                .filter(|&&drone_brand| self.drone_brand_usage.get(&drone_brand) == Some(&min_usage))
+                .cloned()
+                .collect();
+            // From those we choose randomly one Brand and we use it
+            if let Some(&chosen_brand) = min_usage_drone_brands.choose(&mut self.brand_rng) {
+                return chosen_brand;
+            }
+        }
+        //Shouldn't happen
+        DroneBrand::Fungi
+    }
+
+    fn create_and_spawn_drone<T>(&mut self, drone_params: DroneParams)
+    where
+        T: TraitDrone + Send + 'static, // Ensure T implements the Drone trait and is Sendable
+    {
+        let (drone_id, event_sender, cmd_receiver, pkt_receiver, pkt_senders, pdr) = drone_params;
+
+        let drone_instance = self.create_drone::<T>(
+            drone_id,
+            event_sender,
+            cmd_receiver,
+            pkt_receiver,
+            pkt_senders,
+            pdr,
+        );
+
+        thread::spawn(move || {
+            match drone_instance {
+                Ok(mut drone) => drone.run(),
+                Err(e) => panic!("Failed to run drone {}: {}", drone_id, e),
+            }
+        });
+    }
+}
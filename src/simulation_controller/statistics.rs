@@ -0,0 +1,290 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use wg_2024::{
+    network::NodeId,
+    packet::{NodeType, Packet, PacketType},
+};
+use crate::general_use::{DisplayDataDrone, DisplayDataStatistics, DroneStatistics, LatencyStatistics, QueryKind, SessionId, SessionRetransmissions};
+use super::packet_log::PacketEvent;
+
+/// Period of the statistics sent to the GUI.
+pub const STATISTICS_INTERVAL: Duration = Duration::from_secs(1);
+/// Sessions whose retransmissions are reported, the oldest ones are forgotten.
+const MAX_TRACKED_SESSIONS: usize = 200;
+/// Samples used for the average latency of each query kind.
+const LATENCY_WINDOW: usize = 100;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct DroneCounters {
+    forwarded: u64,
+    fragments_forwarded: u64,
+    fragments_dropped: u64,
+}
+
+#[derive(Debug, Default)]
+struct LatencySamples {
+    count: u64,
+    min: Option<Duration>,
+    max: Option<Duration>,
+    last: VecDeque<Duration>,
+}
+
+/// Rolling metrics computed by the controller from the events of the nodes.
+pub struct NetworkStatistics {
+    started_at: Instant,
+    drones: HashMap<NodeId, DroneCounters>,
+    retransmissions: HashMap<(NodeId, SessionId), u64>,
+    sessions_order: VecDeque<(NodeId, SessionId)>,
+    latencies: HashMap<QueryKind, LatencySamples>,
+    failed_sessions: u64,
+}
+
+impl Default for NetworkStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkStatistics {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            drones: HashMap::new(),
+            retransmissions: HashMap::new(),
+            sessions_order: VecDeque::new(),
+            latencies: HashMap::new(),
+            failed_sessions: 0,
+        }
+    }
+
+    /// Counts a `PacketSent` or a `PacketDropped` of a drone, shortcuts are not counted.
+    pub fn record_drone_event(&mut self, packet: &Packet, event: PacketEvent, nodes: &HashMap<NodeId, NodeType>) {
+        let Some(drone_id) = Self::reporting_drone(packet, nodes) else {
+            return;
+        };
+        let counters = self.drones.entry(drone_id).or_default();
+        let is_fragment = matches!(packet.pack_type, PacketType::MsgFragment(_));
+        match event {
+            PacketEvent::Sent => {
+                counters.forwarded += 1;
+                if is_fragment {
+                    counters.fragments_forwarded += 1;
+                }
+            }
+            PacketEvent::Dropped => {
+                if is_fragment {
+                    counters.fragments_dropped += 1;
+                }
+            }
+            PacketEvent::Shortcut => {}
+        }
+    }
+
+    pub fn record_retransmission(&mut self, node_id: NodeId, session_id: SessionId) {
+        let key = (node_id, session_id);
+        if !self.retransmissions.contains_key(&key) {
+            if self.sessions_order.len() == MAX_TRACKED_SESSIONS {
+                if let Some(oldest) = self.sessions_order.pop_front() {
+                    self.retransmissions.remove(&oldest);
+                }
+            }
+            self.sessions_order.push_back(key);
+        }
+        *self.retransmissions.entry(key).or_insert(0) += 1;
+    }
+
+    pub fn record_failed_session(&mut self) {
+        self.failed_sessions += 1;
+    }
+
+    pub fn record_latency(&mut self, query_kind: QueryKind, latency: Duration) {
+        let samples = self.latencies.entry(query_kind).or_default();
+        samples.count += 1;
+        samples.min = Some(samples.min.map_or(latency, |min| min.min(latency)));
+        samples.max = Some(samples.max.map_or(latency, |max| max.max(latency)));
+        if samples.last.len() == LATENCY_WINDOW {
+            samples.last.pop_front();
+        }
+        samples.last.push_back(latency);
+    }
+
+    /// Builds the data sent to the GUI, the configured PDRs come from the drones data of the controller.
+    pub fn snapshot(&self, drones_data: &HashMap<NodeId, DisplayDataDrone>) -> DisplayDataStatistics {
+        let drones = drones_data.iter()
+            .map(|(&drone_id, drone_data)| {
+                let counters = self.drones.get(&drone_id).copied().unwrap_or_default();
+                let fragments_seen = counters.fragments_forwarded + counters.fragments_dropped;
+                let observed_pdr = (fragments_seen > 0)
+                    .then(|| counters.fragments_dropped as f32 / fragments_seen as f32);
+                (drone_id, DroneStatistics {
+                    forwarded: counters.forwarded,
+                    dropped: counters.fragments_dropped,
+                    observed_pdr,
+                    configured_pdr: drone_data.pdr,
+                })
+            })
+            .collect();
+
+        let retransmissions = self.sessions_order.iter()
+            .map(|&(node_id, session_id)| SessionRetransmissions {
+                node_id,
+                session_id,
+                count: self.retransmissions.get(&(node_id, session_id)).copied().unwrap_or(0),
+            })
+            .collect();
+
+        let latencies = self.latencies.iter()
+            .map(|(&query_kind, samples)| {
+                let total: Duration = samples.last.iter().sum();
+                let average_ms = if samples.last.is_empty() {
+                    0.0
+                } else {
+                    total.as_secs_f64() * 1000.0 / samples.last.len() as f64
+                };
+                (query_kind, LatencyStatistics {
+                    count: samples.count,
+                    average_ms,
+                    min_ms: samples.min.map_or(0, |min| min.as_millis() as u64),
+                    max_ms: samples.max.map_or(0, |max| max.as_millis() as u64),
+                })
+            })
+            .collect();
+
+        DisplayDataStatistics {
+            elapsed_ms: self.started_at.elapsed().as_millis() as u64,
+            drones,
+            retransmissions,
+            latencies,
+            failed_sessions: self.failed_sessions,
+        }
+    }
+
+    // The protocol makes the drone increase hop_index before forwarding or dropping,
+    // some drones report the packet before that, so the current hop is the fallback.
+    fn reporting_drone(packet: &Packet, nodes: &HashMap<NodeId, NodeType>) -> Option<NodeId> {
+        let is_drone = |node_id: &&NodeId| matches!(nodes.get(*node_id), Some(NodeType::Drone));
+
+        if let PacketType::FloodRequest(flood_request) = &packet.pack_type {
+            return flood_request.path_trace.last()
+                .map(|(node_id, _)| node_id)
+                .filter(is_drone)
+                .copied();
+        }
+
+        let hops = &packet.routing_header.hops;
+        let hop_index = packet.routing_header.hop_index;
+        hop_index.checked_sub(1)
+            .and_then(|previous| hops.get(previous))
+            .filter(is_drone)
+            .or_else(|| hops.get(hop_index).filter(is_drone))
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::{Ack, FloodRequest, Fragment, FRAGMENT_DSIZE};
+    use crate::general_use::SpecificNodeType;
+    use crate::network_initializer::DroneBrand;
+
+    // Client 1 reaches server 4 through drones 2 and 3
+    const ROUTE: [NodeId; 4] = [1, 2, 3, 4];
+
+    fn nodes() -> HashMap<NodeId, NodeType> {
+        HashMap::from([(1, NodeType::Client), (2, NodeType::Drone), (3, NodeType::Drone), (4, NodeType::Server)])
+    }
+
+    fn drones_data() -> HashMap<NodeId, DisplayDataDrone> {
+        [(2, 0.3), (3, 0.0)].into_iter()
+            .map(|(node_id, pdr)| (node_id, DisplayDataDrone {
+                node_id,
+                node_type: SpecificNodeType::Drone,
+                drone_brand: DroneBrand::RustyDrone,
+                connected_nodes_ids: Vec::new(),
+                pdr,
+            }))
+            .collect()
+    }
+
+    fn packet(hop_index: usize, pack_type: PacketType) -> Packet {
+        Packet { routing_header: SourceRoutingHeader { hop_index, hops: ROUTE.to_vec() }, session_id: 9, pack_type }
+    }
+
+    fn fragment() -> PacketType {
+        PacketType::MsgFragment(Fragment { fragment_index: 0, total_n_fragments: 1, length: 0, data: [0; FRAGMENT_DSIZE] })
+    }
+
+    fn flood_request(path_trace: Vec<(NodeId, NodeType)>) -> Packet {
+        let pack_type = PacketType::FloodRequest(FloodRequest { flood_id: 1, initiator_id: 1, path_trace });
+        Packet { routing_header: SourceRoutingHeader { hop_index: 0, hops: Vec::new() }, session_id: 0, pack_type }
+    }
+
+    #[test]
+    fn reporting_drone_is_the_previous_hop_or_else_the_current_one() {
+        let nodes = nodes();
+        // Drone 2 reports after increasing hop_index, then before it: the previous hop is the client
+        assert_eq!(NetworkStatistics::reporting_drone(&packet(2, fragment()), &nodes), Some(2));
+        assert_eq!(NetworkStatistics::reporting_drone(&packet(1, fragment()), &nodes), Some(2));
+        assert_eq!(NetworkStatistics::reporting_drone(&packet(3, fragment()), &nodes), Some(3));
+        // Past the last drone or at the client neither hop is a drone
+        assert_eq!(NetworkStatistics::reporting_drone(&packet(4, fragment()), &nodes), None);
+        assert_eq!(NetworkStatistics::reporting_drone(&packet(0, fragment()), &nodes), None);
+
+        assert_eq!(NetworkStatistics::reporting_drone(&flood_request(vec![(1, NodeType::Client), (2, NodeType::Drone)]), &nodes), Some(2));
+        assert_eq!(NetworkStatistics::reporting_drone(&flood_request(vec![(1, NodeType::Client)]), &nodes), None);
+    }
+
+    #[test]
+    fn observed_pdr_counts_the_dropped_fragments() {
+        let nodes = nodes();
+        let mut statistics = NetworkStatistics::new();
+        for _ in 0..3 {
+            statistics.record_drone_event(&packet(2, fragment()), PacketEvent::Sent, &nodes);
+        }
+        statistics.record_drone_event(&packet(1, fragment()), PacketEvent::Dropped, &nodes);
+        statistics.record_drone_event(&packet(2, PacketType::Ack(Ack { fragment_index: 0 })), PacketEvent::Sent, &nodes);
+        statistics.record_drone_event(&packet(3, fragment()), PacketEvent::Shortcut, &nodes);
+
+        let snapshot = statistics.snapshot(&drones_data());
+        let drone = &snapshot.drones[&2];
+        assert_eq!((drone.forwarded, drone.dropped), (4, 1));
+        assert_eq!(drone.observed_pdr, Some(0.25));
+        assert_eq!(drone.configured_pdr, 0.3);
+        // The shortcut is not counted, so drone 3 has seen no fragment
+        assert_eq!(snapshot.drones[&3].forwarded, 0);
+        assert_eq!(snapshot.drones[&3].observed_pdr, None);
+    }
+
+    #[test]
+    fn oldest_sessions_are_forgotten() {
+        let mut statistics = NetworkStatistics::new();
+        for session_id in 0..=MAX_TRACKED_SESSIONS as SessionId {
+            statistics.record_retransmission(1, session_id);
+        }
+        statistics.record_retransmission(1, 5);
+
+        let retransmissions = statistics.snapshot(&HashMap::new()).retransmissions;
+        assert_eq!(retransmissions.len(), MAX_TRACKED_SESSIONS);
+        assert_eq!(retransmissions[0].session_id, 1);
+        assert_eq!(retransmissions.last().map(|session| session.session_id), Some(MAX_TRACKED_SESSIONS as SessionId));
+        assert_eq!(retransmissions.iter().find(|session| session.session_id == 5).map(|session| session.count), Some(2));
+    }
+
+    #[test]
+    fn average_latency_uses_the_last_samples() {
+        let mut statistics = NetworkStatistics::new();
+        statistics.record_latency(QueryKind::AskFile, Duration::from_millis(1000));
+        for _ in 0..LATENCY_WINDOW {
+            statistics.record_latency(QueryKind::AskFile, Duration::from_millis(10));
+        }
+
+        let latencies = statistics.snapshot(&HashMap::new()).latencies;
+        let latency = &latencies[&QueryKind::AskFile];
+        assert_eq!(latency.count, LATENCY_WINDOW as u64 + 1);
+        assert!((latency.average_ms - 10.0).abs() < 1e-9);
+        assert_eq!((latency.min_ms, latency.max_ms), (10, 1000));
+        assert!(!latencies.contains_key(&QueryKind::AskMedia));
+    }
+}
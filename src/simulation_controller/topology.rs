@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::fs;
+use log::warn;
+use serde::Serialize;
+use wg_2024::{
+    config::{Client, Config, Drone, Server},
+    network::NodeId,
+    packet::NodeType,
+};
+use crate::general_use::TopologyChangeError;
+use crate::initialization_file_checker::{self, InitializationFileChecker};
+use crate::randomness;
+use crate::simulation_controller::{export_path, SimulationController};
+
+/// Answers of the topology export sent to the GUI.
+#[derive(Debug, Clone, Serialize)]
+pub enum TopologyExportData {
+    TopologyToml(String),           // When the browser did not give a path
+    TopologyExported {
+        path: String,
+    },
+    TopologyExportFailed {
+        path: Option<String>,
+        error: String,
+    },
+}
+
+impl SimulationController {
+    /// Builds the configuration of the network described by `topology`, with the PDRs of the drones data.
+    /// Nodes of unknown type are left out.
+    pub(crate) fn config_from_topology(&self, topology: &HashMap<NodeId, Vec<NodeId>>) -> Config {
+        let mut config = Config {
+            drone: Vec::new(),
+            client: Vec::new(),
+            server: Vec::new(),
+        };
+
+        // Sorted, so the same network always gives the same file
+        let mut node_ids: Vec<NodeId> = topology.keys().copied().collect();
+        node_ids.sort_unstable();
+
+        for id in node_ids {
+            let connected_ids = topology[&id].clone();
+            match self.state.nodes.get(&id) {
+                Some(NodeType::Drone) => config.drone.push(Drone {
+                    id,
+                    connected_node_ids: connected_ids,
+                    pdr: self.drones_data.get(&id).map_or(0.0, |drone_data| drone_data.pdr),
+                }),
+                Some(NodeType::Client) => config.client.push(Client {
+                    id,
+                    connected_drone_ids: connected_ids,
+                }),
+                Some(NodeType::Server) => config.server.push(Server {
+                    id,
+                    connected_drone_ids: connected_ids,
+                }),
+                None => warn!("Node {} has no type, it is left out of the configuration", id),
+            }
+        }
+        config
+    }
+
+    /// Returns the configuration of the live network, with the crashed drones left out and the current PDRs.
+    pub fn export_config(&self) -> Result<Config, String> {
+        let config = self.config_from_topology(&self.state.topology);
+        InitializationFileChecker::new(&config)
+            .check()
+            .map_err(|e| format!("The live network is not a valid initialization file: {}", e))?;
+        Ok(config)
+    }
+
+    /// Returns the live network as an initialization file, with the seed of the simulation.
+    pub fn export_toml(&self) -> Result<String, String> {
+        let config = self.export_config()?;
+        let mut toml = initialization_file_checker::config_to_toml(&config, randomness::seed())?;
+
+        // The types of the servers, so the same network is built again
+        let mut server_ids: Vec<NodeId> = config.server.iter().map(|server| server.id).collect();
+        server_ids.sort_unstable();
+        for server_id in server_ids {
+            if let Some((_, server_type)) = self.command_senders_servers.get(&server_id) {
+                toml.push_str(&format!("\n[[server_role]]\nid = {}\ntype = \"{:?}\"\n", server_id, server_type));
+            }
+        }
+        Ok(toml)
+    }
+
+    /// Writes the live network as an initialization file in the export directory, returns its path.
+    pub fn export_topology_file(&self, file_name: &str) -> Result<String, String> {
+        let path = export_path(file_name)?;
+        let toml = self.export_toml()?;
+        fs::write(&path, toml).map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+        Ok(path.display().to_string())
+    }
+
+    /// Checks a change of the network with the same rules used for the initialization file.
+    /// Only the violations the change adds are refused, the ones the live network already has
+    /// (e.g. a server left with one drone by a crash) do not block unrelated edits.
+    pub(crate) fn validate_change(&self, config: &Config) -> Result<(), TopologyChangeError> {
+        let Err(report) = InitializationFileChecker::new(config).check() else {
+            return Ok(());
+        };
+        let current_errors = InitializationFileChecker::new(&self.config_from_topology(&self.state.topology))
+            .check()
+            .err()
+            .map(|current_report| current_report.errors)
+            .unwrap_or_default();
+
+        let errors: Vec<String> = report.errors.iter()
+            .filter(|error| !current_errors.contains(error))
+            .map(|error| error.to_string())
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(TopologyChangeError::InvalidTopology(errors.join("; ")))
+        }
+    }
+
+    /// Connects two nodes of the network, if the resulting network is still valid.
+    pub fn add_link(&mut self, from: NodeId, to: NodeId) -> Result<(), TopologyChangeError> {
+        let (from_type, to_type) = self.link_ends(from, to)?;
+        if self.state.topology[&from].contains(&to) {
+            return Err(TopologyChangeError::AlreadyConnected(from, to));
+        }
+
+        let mut topology = self.state.topology.clone();
+        topology.entry(from).or_default().push(to);
+        topology.entry(to).or_default().push(from);
+        self.validate_change(&self.config_from_topology(&topology))?;
+
+        let (Some(from_sender), Some(to_sender)) = (self.packet_senders.get(&from).cloned(), self.packet_senders.get(&to).cloned()) else {
+            return Err(TopologyChangeError::CommandFailed(format!("Packet senders of nodes {} and {} not found", from, to)));
+        };
+        self.add_sender(from, from_type, to, to_sender);
+        self.add_sender(to, to_type, from, from_sender);
+        self.state.topology = topology;
+
+        self.restart_flooding();
+        Ok(())
+    }
+
+    /// Disconnects two nodes of the network, if the resulting network is still valid.
+    pub fn remove_link(&mut self, from: NodeId, to: NodeId) -> Result<(), TopologyChangeError> {
+        let (from_type, to_type) = self.link_ends(from, to)?;
+        if !self.state.topology[&from].contains(&to) {
+            return Err(TopologyChangeError::NotConnected(from, to));
+        }
+
+        let mut topology = self.state.topology.clone();
+        if let Some(connected_ids) = topology.get_mut(&from) {
+            connected_ids.retain(|&id| id != to);
+        }
+        if let Some(connected_ids) = topology.get_mut(&to) {
+            connected_ids.retain(|&id| id != from);
+        }
+        self.validate_change(&self.config_from_topology(&topology))?;
+
+        // Both ends are checked before touching any of them, so the link is never half removed
+        for (node_id, node_type) in [(from, from_type), (to, to_type)] {
+            if !self.has_command_sender(node_id, node_type) {
+                return Err(TopologyChangeError::CommandFailed(format!("Command sender of node {} not found", node_id)));
+            }
+        }
+        self.remove_sender(from, from_type, to).map_err(TopologyChangeError::CommandFailed)?;
+        if let Err(error) = self.remove_sender(to, to_type, from) {
+            // Putting back the first half of the link
+            match self.packet_senders.get(&to).cloned() {
+                Some(to_sender) => self.add_sender(from, from_type, to, to_sender),
+                None => warn!("Unable to restore the link from node {} to node {}, its packet sender is missing", from, to),
+            }
+            return Err(TopologyChangeError::CommandFailed(error));
+        }
+        self.state.topology = topology;
+
+        self.restart_flooding();
+        Ok(())
+    }
+
+    /// Returns whether the controller can send commands to the node.
+    fn has_command_sender(&self, node_id: NodeId, node_type: NodeType) -> bool {
+        match node_type {
+            NodeType::Drone => self.command_senders_drones.contains_key(&node_id),
+            NodeType::Client => self.command_senders_clients.contains_key(&node_id),
+            NodeType::Server => self.command_senders_servers.contains_key(&node_id),
+        }
+    }
+
+    /// Returns the types of the two ends of a link.
+    fn link_ends(&self, from: NodeId, to: NodeId) -> Result<(NodeType, NodeType), TopologyChangeError> {
+        if from == to {
+            return Err(TopologyChangeError::SameNode(from));
+        }
+        let node_type = |id: NodeId| {
+            self.state.topology.contains_key(&id)
+                .then(|| self.state.nodes.get(&id).copied())
+                .flatten()
+                .ok_or(TopologyChangeError::UnknownNode(id))
+        };
+        Ok((node_type(from)?, node_type(to)?))
+    }
+}
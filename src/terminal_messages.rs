@@ -1,4 +1,6 @@
 use std::{thread, time, io::{stdout, Write}};
+use crate::general_use::DroneId;
+use crate::network_initializer::DroneBrand;
 
 // ANSI Escape Codes for Colors
 const RED: &str = "\x1b[31m";
@@ -76,7 +78,20 @@ pub fn network_valid(){
 }
 
 pub fn network_not_valid(err: String){
-    fancy_box(format!("❌ Network Status: NOT VALID: {}", err).as_str(), RED);
+    // The first line goes in the box, the details of the report below it
+    let mut lines = err.lines();
+    fancy_box(format!("❌ Network Status: NOT VALID: {}", lines.next().unwrap_or_default()).as_str(), RED);
+    for line in lines {
+        println!("{}{}{}", RED, line, RESET);
+    }
+}
+
+/// One line for each drone, so a misbehaving brand can be found in the logs.
+pub fn drone_brands(brands: &[(DroneId, DroneBrand)]){
+    fancy_box("🛸 Drone Brands", CYAN);
+    for (drone_id, drone_brand) in brands {
+        println!("{}  drone {:>3} → {:?}{}", CYAN, drone_id, drone_brand, RESET);
+    }
 }
 
 pub fn network_stopped(){
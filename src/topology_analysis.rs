@@ -0,0 +1,271 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use wg_2024::config::Config;
+use wg_2024::network::NodeId;
+use crate::general_use::{ClientId, DroneId, ServerId};
+
+/// Graph statistics of an initialization file, computed on the drones since clients and servers do not forward.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopologyStatistics {
+    pub n_drones: usize,
+    pub n_clients: usize,
+    pub n_servers: usize,
+    pub diameter: Option<usize>,                        // None if the drones are not connected
+    pub articulation_points: Vec<DroneId>,              // Drones whose crash splits the drone network
+    pub min_cuts: Vec<(ClientId, ServerId, usize)>,     // Fewest drones to crash to separate the two
+}
+
+impl TopologyStatistics {
+    pub fn new(config: &Config) -> Self {
+        let graph = drone_graph(config);
+        let mut min_cuts = Vec::new();
+        for client in &config.client {
+            for server in &config.server {
+                let cut = min_vertex_cut(&graph, &client.connected_drone_ids, &server.connected_drone_ids);
+                min_cuts.push((client.id, server.id, cut));
+            }
+        }
+
+        Self {
+            n_drones: config.drone.len(),
+            n_clients: config.client.len(),
+            n_servers: config.server.len(),
+            diameter: diameter(&graph),
+            articulation_points: articulation_points(&graph),
+            min_cuts,
+        }
+    }
+}
+
+impl Display for TopologyStatistics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "drones: {}, clients: {}, servers: {}", self.n_drones, self.n_clients, self.n_servers)?;
+        match self.diameter {
+            Some(diameter) => writeln!(f, "drone network diameter: {}", diameter)?,
+            None => writeln!(f, "drone network diameter: infinite (not connected)")?,
+        }
+        writeln!(f, "articulation point drones: {:?}", self.articulation_points)?;
+        write!(f, "min-cut between clients and servers:")?;
+        if self.min_cuts.is_empty() {
+            write!(f, " none")?;
+        }
+        for (client_id, server_id, cut) in &self.min_cuts {
+            write!(f, "\n  client {} - server {}: {}", client_id, server_id, cut)?;
+        }
+        Ok(())
+    }
+}
+
+/// Undirected graph of the drones, a connection listed by only one of the two drones is kept.
+pub fn drone_graph(config: &Config) -> HashMap<DroneId, HashSet<DroneId>> {
+    let drone_ids: HashSet<DroneId> = config.drone.iter().map(|d| d.id).collect();
+    let mut graph: HashMap<DroneId, HashSet<DroneId>> = HashMap::new();
+    for drone in &config.drone {
+        graph.entry(drone.id).or_default();
+        for &neighbour in &drone.connected_node_ids {
+            if neighbour != drone.id && drone_ids.contains(&neighbour) {
+                graph.entry(drone.id).or_default().insert(neighbour);
+                graph.entry(neighbour).or_default().insert(drone.id);
+            }
+        }
+    }
+    graph
+}
+
+/// Hops between every pair of drones, with a BFS from each drone.
+fn diameter(graph: &HashMap<DroneId, HashSet<DroneId>>) -> Option<usize> {
+    let mut diameter = 0;
+    for &start in graph.keys() {
+        let mut distances = HashMap::from([(start, 0)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            let distance = distances[&node];
+            for &neighbour in &graph[&node] {
+                if let Entry::Vacant(entry) = distances.entry(neighbour) {
+                    entry.insert(distance + 1);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        if distances.len() != graph.len() {
+            return None;
+        }
+        diameter = diameter.max(distances.values().copied().max().unwrap_or(0));
+    }
+    Some(diameter)
+}
+
+/// Tarjan's algorithm, the result is sorted.
+fn articulation_points(graph: &HashMap<DroneId, HashSet<DroneId>>) -> Vec<DroneId> {
+    struct Search<'a> {
+        graph: &'a HashMap<DroneId, HashSet<DroneId>>,
+        discovery: HashMap<DroneId, usize>,
+        low: HashMap<DroneId, usize>,
+        points: HashSet<DroneId>,
+    }
+
+    impl Search<'_> {
+        fn visit(&mut self, node: DroneId, parent: Option<DroneId>) {
+            let time = self.discovery.len();
+            self.discovery.insert(node, time);
+            self.low.insert(node, time);
+            let mut children = 0;
+
+            let mut neighbours: Vec<DroneId> = self.graph[&node].iter().copied().collect();
+            neighbours.sort_unstable();
+            for neighbour in neighbours {
+                if Some(neighbour) == parent {
+                    continue;
+                }
+                if let Some(&neighbour_discovery) = self.discovery.get(&neighbour) {
+                    let low = self.low[&node].min(neighbour_discovery);
+                    self.low.insert(node, low);
+                } else {
+                    children += 1;
+                    self.visit(neighbour, Some(node));
+                    let low = self.low[&node].min(self.low[&neighbour]);
+                    self.low.insert(node, low);
+                    if parent.is_some() && self.low[&neighbour] >= self.discovery[&node] {
+                        self.points.insert(node);
+                    }
+                }
+            }
+            if parent.is_none() && children > 1 {
+                self.points.insert(node);
+            }
+        }
+    }
+
+    let mut search = Search { graph, discovery: HashMap::new(), low: HashMap::new(), points: HashSet::new() };
+    let mut drone_ids: Vec<DroneId> = graph.keys().copied().collect();
+    drone_ids.sort_unstable();
+    for drone_id in drone_ids {
+        if !search.discovery.contains_key(&drone_id) {
+            search.visit(drone_id, None);
+        }
+    }
+
+    let mut points: Vec<DroneId> = search.points.into_iter().collect();
+    points.sort_unstable();
+    points
+}
+
+/// Fewest drones to remove so that no drone of `sources` reaches a drone of `targets`,
+/// the number of drone-disjoint paths found with unit capacity augmenting paths.
+pub fn min_vertex_cut(graph: &HashMap<DroneId, HashSet<DroneId>>, sources: &[NodeId], targets: &[NodeId]) -> usize {
+    // Every drone is split in an "in" and an "out" vertex joined by an arc of capacity one
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Vertex {
+        Source,
+        Sink,
+        In(DroneId),
+        Out(DroneId),
+    }
+
+    let mut capacity: HashMap<(Vertex, Vertex), usize> = HashMap::new();
+    let mut adjacency: HashMap<Vertex, Vec<Vertex>> = HashMap::new();
+    let mut add_arc = |from: Vertex, to: Vertex, arc_capacity: usize| {
+        *capacity.entry((from, to)).or_insert(0) += arc_capacity;
+        capacity.entry((to, from)).or_insert(0);
+        adjacency.entry(from).or_default().push(to);
+        adjacency.entry(to).or_default().push(from);
+    };
+
+    let unbounded = graph.len() + 1;
+    for (&drone, neighbours) in graph {
+        add_arc(Vertex::In(drone), Vertex::Out(drone), 1);
+        for &neighbour in neighbours {
+            add_arc(Vertex::Out(drone), Vertex::In(neighbour), unbounded);
+        }
+    }
+    for source in sources.iter().filter(|id| graph.contains_key(id)) {
+        add_arc(Vertex::Source, Vertex::In(*source), unbounded);
+    }
+    for target in targets.iter().filter(|id| graph.contains_key(id)) {
+        add_arc(Vertex::Out(*target), Vertex::Sink, unbounded);
+    }
+
+    let mut flow = 0;
+    loop {
+        // BFS for a path with residual capacity
+        let mut previous: HashMap<Vertex, Vertex> = HashMap::new();
+        let mut queue = VecDeque::from([Vertex::Source]);
+        while let Some(vertex) = queue.pop_front() {
+            if vertex == Vertex::Sink {
+                break;
+            }
+            for &next in adjacency.get(&vertex).into_iter().flatten() {
+                if next != Vertex::Source && !previous.contains_key(&next) && capacity[&(vertex, next)] > 0 {
+                    previous.insert(next, vertex);
+                    queue.push_back(next);
+                }
+            }
+        }
+        if !previous.contains_key(&Vertex::Sink) {
+            return flow;
+        }
+
+        // One unit at a time, the arcs of the drones have capacity one
+        let mut vertex = Vertex::Sink;
+        while let Some(&before) = previous.get(&vertex) {
+            *capacity.get_mut(&(before, vertex)).unwrap() -= 1;
+            *capacity.get_mut(&(vertex, before)).unwrap() += 1;
+            vertex = before;
+        }
+        flow += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(n_drones: DroneId, edges: &[(DroneId, DroneId)]) -> HashMap<DroneId, HashSet<DroneId>> {
+        let mut graph: HashMap<DroneId, HashSet<DroneId>> = (1..=n_drones).map(|id| (id, HashSet::new())).collect();
+        for &(a, b) in edges {
+            graph.get_mut(&a).unwrap().insert(b);
+            graph.get_mut(&b).unwrap().insert(a);
+        }
+        graph
+    }
+
+    #[test]
+    fn path_has_its_inner_drones_as_articulation_points() {
+        let path = graph(5, &[(1, 2), (2, 3), (3, 4), (4, 5)]);
+        assert_eq!(diameter(&path), Some(4));
+        assert_eq!(articulation_points(&path), vec![2, 3, 4]);
+        assert_eq!(min_vertex_cut(&path, &[1], &[5]), 1);
+    }
+
+    #[test]
+    fn ring_has_no_articulation_points() {
+        let ring = graph(6, &[(1, 2), (2, 3), (3, 4), (4, 5), (5, 6), (6, 1)]);
+        assert_eq!(diameter(&ring), Some(3));
+        assert!(articulation_points(&ring).is_empty());
+    }
+
+    #[test]
+    fn bridge_between_triangles_has_its_ends_as_articulation_points() {
+        let triangles = graph(6, &[(1, 2), (2, 3), (3, 1), (3, 4), (4, 5), (5, 6), (6, 4)]);
+        assert_eq!(diameter(&triangles), Some(3));
+        assert_eq!(articulation_points(&triangles), vec![3, 4]);
+        assert_eq!(min_vertex_cut(&triangles, &[1, 2], &[5, 6]), 1);
+    }
+
+    #[test]
+    fn disjoint_paths_give_a_min_cut_of_two() {
+        let paths = graph(6, &[(1, 2), (2, 3), (4, 5), (5, 6), (2, 5)]);
+        assert_eq!(min_vertex_cut(&paths, &[1, 4], &[3, 6]), 2);
+        // Without the drones of one path only the other is left
+        assert_eq!(min_vertex_cut(&paths, &[1], &[3, 6]), 1);
+    }
+
+    #[test]
+    fn disconnected_drones_have_no_diameter() {
+        let disconnected = graph(4, &[(1, 2), (3, 4)]);
+        assert_eq!(diameter(&disconnected), None);
+        assert!(articulation_points(&disconnected).is_empty());
+        assert_eq!(min_vertex_cut(&disconnected, &[1], &[4]), 0);
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use wg_2024::config::{Client, Config, Drone, Server};
+use wg_2024::network::NodeId;
+use crate::initialization_file_checker::InitializationFileChecker;
+
+/// How the drones are connected to each other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphShape {
+    Ring,
+    Grid,
+    ErdosRenyi { p: f64 },              // Each pair connected with probability p
+    BarabasiAlbert { m: usize },        // Each new drone connected to m drones, preferring the busiest
+}
+
+impl FromStr for GraphShape {
+    type Err = String;
+
+    /// Parses `ring`, `grid`, `er:<p>` or `ba:<m>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (s, None),
+        };
+        match (name, parameter) {
+            ("ring", None) => Ok(GraphShape::Ring),
+            ("grid", None) => Ok(GraphShape::Grid),
+            ("er", Some(p)) => {
+                let p = p.parse::<f64>().map_err(|_| format!("Invalid probability '{}'", p))?;
+                Ok(GraphShape::ErdosRenyi { p })
+            }
+            ("ba", Some(m)) => {
+                let m = m.parse::<usize>().map_err(|_| format!("Invalid number of links '{}'", m))?;
+                Ok(GraphShape::BarabasiAlbert { m })
+            }
+            _ => Err(format!("Unknown shape '{}', expected one of: ring, grid, er:<p>, ba:<m>", s)),
+        }
+    }
+}
+
+impl Display for GraphShape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphShape::Ring => write!(f, "ring"),
+            GraphShape::Grid => write!(f, "grid"),
+            GraphShape::ErdosRenyi { p } => write!(f, "er:{}", p),
+            GraphShape::BarabasiAlbert { m } => write!(f, "ba:{}", m),
+        }
+    }
+}
+
+/// PDR given to each drone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PdrDistribution {
+    Constant(f32),
+    Uniform { min: f32, max: f32 },
+}
+
+impl FromStr for PdrDistribution {
+    type Err = String;
+
+    /// Parses `<pdr>` or `<min>..<max>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| value.trim().parse::<f32>().map_err(|_| format!("Invalid PDR '{}'", value));
+        match s.split_once("..") {
+            Some((min, max)) => Ok(PdrDistribution::Uniform { min: parse(min)?, max: parse(max)? }),
+            None => Ok(PdrDistribution::Constant(parse(s)?)),
+        }
+    }
+}
+
+/// Parameters of a generated topology, the same parameters always give the same file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorParams {
+    pub n_drones: usize,
+    pub n_clients: usize,
+    pub n_servers: usize,
+    pub shape: GraphShape,
+    pub pdr: PdrDistribution,
+    pub seed: u64,
+}
+
+impl GeneratorParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.n_drones == 0 {
+            return Err("At least one drone is needed".to_string());
+        }
+        // The ids start from 1
+        if self.n_drones + self.n_clients + self.n_servers > NodeId::MAX as usize {
+            return Err(format!("At most {} nodes can be generated", NodeId::MAX));
+        }
+        if self.n_servers > 0 && self.n_drones < 2 {
+            return Err("Servers need at least two drones".to_string());
+        }
+        match self.shape {
+            GraphShape::ErdosRenyi { p } if !(0.0..=1.0).contains(&p) => {
+                return Err(format!("Probability {} is not between 0 and 1", p));
+            }
+            GraphShape::BarabasiAlbert { m: 0 } => {
+                return Err("Barabási-Albert needs at least one link per drone".to_string());
+            }
+            _ => {}
+        }
+        let (min, max) = match self.pdr {
+            PdrDistribution::Constant(pdr) => (pdr, pdr),
+            PdrDistribution::Uniform { min, max } => (min, max),
+        };
+        if !(0.0..=1.0).contains(&min) || !(0.0..=1.0).contains(&max) || min > max {
+            return Err(format!("PDR range {}..{} is not inside 0..1", min, max));
+        }
+        Ok(())
+    }
+}
+
+/// Generates a network that passes the checks of the initialization file.
+/// Drones get the ids from 1, then the clients, then the servers.
+pub fn generate(params: &GeneratorParams) -> Result<Config, String> {
+    params.validate()?;
+    let mut rng = StdRng::seed_from_u64(params.seed);
+
+    let mut drone_links = match params.shape {
+        GraphShape::Ring => ring(params.n_drones),
+        GraphShape::Grid => grid(params.n_drones),
+        GraphShape::ErdosRenyi { p } => erdos_renyi(params.n_drones, p, &mut rng),
+        GraphShape::BarabasiAlbert { m } => barabasi_albert(params.n_drones, m, &mut rng),
+    };
+    connect_components(&mut drone_links, &mut rng);
+
+    let drone_id = |index: usize| (index + 1) as NodeId;
+    let drone_indexes: Vec<usize> = (0..params.n_drones).collect();
+    let mut edge_links: Vec<Vec<NodeId>> = vec![Vec::new(); params.n_drones];
+
+    // Clients have one or two drones, servers at least two
+    let mut clients = Vec::new();
+    for i in 0..params.n_clients {
+        let id = (params.n_drones + i + 1) as NodeId;
+        let degree = if params.n_drones >= 2 { rng.gen_range(1..=2) } else { 1 };
+        let drones: Vec<usize> = drone_indexes.choose_multiple(&mut rng, degree).copied().collect();
+        for &drone in &drones {
+            edge_links[drone].push(id);
+        }
+        clients.push(Client { id, connected_drone_ids: drones.into_iter().map(drone_id).collect() });
+    }
+    let mut servers = Vec::new();
+    for i in 0..params.n_servers {
+        let id = (params.n_drones + params.n_clients + i + 1) as NodeId;
+        let degree = rng.gen_range(2..=params.n_drones.min(3));
+        let drones: Vec<usize> = drone_indexes.choose_multiple(&mut rng, degree).copied().collect();
+        for &drone in &drones {
+            edge_links[drone].push(id);
+        }
+        servers.push(Server { id, connected_drone_ids: drones.into_iter().map(drone_id).collect() });
+    }
+
+    let drones = drone_links.iter()
+        .zip(edge_links)
+        .enumerate()
+        .map(|(index, (links, edge_links))| {
+            let pdr = match params.pdr {
+                PdrDistribution::Constant(pdr) => pdr,
+                PdrDistribution::Uniform { min, max } => rng.gen_range(min..=max),
+            };
+            Drone {
+                id: drone_id(index),
+                connected_node_ids: links.iter().map(|&link| drone_id(link)).chain(edge_links).collect(),
+                pdr: ((pdr * 100.0).round() / 100.0).clamp(0.0, 1.0),       // Readable files
+            }
+        })
+        .collect();
+
+    let config = Config { drone: drones, client: clients, server: servers };
+    InitializationFileChecker::new(&config)
+        .check()
+        .map_err(|report| format!("The generated network is not valid: {}", report))?;
+    Ok(config)
+}
+
+fn add_link(links: &mut [BTreeSet<usize>], a: usize, b: usize) {
+    if a != b {
+        links[a].insert(b);
+        links[b].insert(a);
+    }
+}
+
+fn ring(n: usize) -> Vec<BTreeSet<usize>> {
+    let mut links = vec![BTreeSet::new(); n];
+    for i in 0..n {
+        add_link(&mut links, i, (i + 1) % n);
+    }
+    links
+}
+
+// Row by row, the last row can be shorter
+fn grid(n: usize) -> Vec<BTreeSet<usize>> {
+    let columns = (n as f64).sqrt().ceil() as usize;
+    let mut links = vec![BTreeSet::new(); n];
+    for i in 0..n {
+        if (i + 1) % columns != 0 && i + 1 < n {
+            add_link(&mut links, i, i + 1);
+        }
+        if i + columns < n {
+            add_link(&mut links, i, i + columns);
+        }
+    }
+    links
+}
+
+fn erdos_renyi(n: usize, p: f64, rng: &mut StdRng) -> Vec<BTreeSet<usize>> {
+    let mut links = vec![BTreeSet::new(); n];
+    for a in 0..n {
+        for b in a + 1..n {
+            if rng.gen_bool(p) {
+                add_link(&mut links, a, b);
+            }
+        }
+    }
+    links
+}
+
+// Starts from a full graph of m + 1 drones
+fn barabasi_albert(n: usize, m: usize, rng: &mut StdRng) -> Vec<BTreeSet<usize>> {
+    let mut links = vec![BTreeSet::new(); n];
+    let initial = (m + 1).min(n);
+    // Every drone appears once for each of its links, so choosing from here prefers the busiest ones
+    let mut endpoints = Vec::new();
+    for a in 0..initial {
+        for b in a + 1..initial {
+            add_link(&mut links, a, b);
+            endpoints.extend([a, b]);
+        }
+    }
+    for new in initial..n {
+        let mut targets = BTreeSet::new();
+        while targets.len() < m.min(new) {
+            if let Some(&target) = endpoints.choose(rng) {
+                targets.insert(target);
+            } else {
+                targets.insert(rng.gen_range(0..new));
+            }
+        }
+        for target in targets {
+            add_link(&mut links, new, target);
+            endpoints.extend([new, target]);
+        }
+    }
+    links
+}
+
+/// Links the connected components with one random link each, so the drone network is connected.
+fn connect_components(links: &mut [BTreeSet<usize>], rng: &mut StdRng) {
+    let mut component = vec![usize::MAX; links.len()];
+    let mut components: Vec<Vec<usize>> = Vec::new();
+    for start in 0..links.len() {
+        if component[start] != usize::MAX {
+            continue;
+        }
+        let mut members = vec![start];
+        component[start] = components.len();
+        let mut i = 0;
+        while i < members.len() {
+            let node = members[i];
+            for &neighbour in &links[node] {
+                if component[neighbour] == usize::MAX {
+                    component[neighbour] = components.len();
+                    members.push(neighbour);
+                }
+            }
+            i += 1;
+        }
+        components.push(members);
+    }
+
+    for pair in components.windows(2) {
+        let (Some(&a), Some(&b)) = (pair[0].choose(rng), pair[1].choose(rng)) else {
+            continue;
+        };
+        add_link(links, a, b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::initialization_file_checker::config_to_toml;
+
+    const SHAPES: [GraphShape; 8] = [
+        GraphShape::Ring,
+        GraphShape::Grid,
+        GraphShape::ErdosRenyi { p: 0.0 },      // Only the links of connect_components
+        GraphShape::ErdosRenyi { p: 0.1 },
+        GraphShape::ErdosRenyi { p: 1.0 },
+        GraphShape::BarabasiAlbert { m: 1 },
+        GraphShape::BarabasiAlbert { m: 2 },
+        GraphShape::BarabasiAlbert { m: 5 },
+    ];
+
+    fn params(shape: GraphShape, n_drones: usize, seed: u64) -> GeneratorParams {
+        GeneratorParams {
+            n_drones,
+            n_clients: 4,
+            n_servers: if n_drones >= 2 { 3 } else { 0 },
+            shape,
+            pdr: PdrDistribution::Uniform { min: 0.0, max: 0.3 },
+            seed,
+        }
+    }
+
+    #[test]
+    fn every_shape_gives_a_valid_network() {
+        for shape in SHAPES {
+            for n_drones in [1, 2, 3, 4, 5, 9, 10, 17, 40] {
+                for seed in 0..20 {
+                    let params = params(shape, n_drones, seed);
+                    let config = generate(&params).unwrap_or_else(|e| panic!("{:?}: {}", params, e));
+                    assert!(InitializationFileChecker::new(&config).check().is_ok(), "{:?}", params);
+                    assert_eq!(config.drone.len(), params.n_drones);
+                    assert_eq!(config.client.len(), params.n_clients);
+                    assert_eq!(config.server.len(), params.n_servers);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_network() {
+        for shape in SHAPES {
+            for n_drones in [1, 5, 17] {
+                for seed in 0..5 {
+                    let params = params(shape, n_drones, seed);
+                    let first = config_to_toml(&generate(&params).unwrap(), seed).unwrap();
+                    let second = config_to_toml(&generate(&params).unwrap(), seed).unwrap();
+                    assert_eq!(first, second, "{:?}", params);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn components_are_connected() {
+        for n in 1..30 {
+            for seed in 0..20 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut links = erdos_renyi(n, 0.05, &mut rng);
+                connect_components(&mut links, &mut rng);
+
+                // Every drone is reached from the first one
+                let mut reached = BTreeSet::from([0]);
+                let mut queue = vec![0];
+                while let Some(node) = queue.pop() {
+                    for &neighbour in &links[node] {
+                        if reached.insert(neighbour) {
+                            queue.push(neighbour);
+                        }
+                    }
+                }
+                assert_eq!(reached.len(), n, "{} drones, seed {}", n, seed);
+            }
+        }
+    }
+}
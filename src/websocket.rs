@@ -7,6 +7,9 @@ use log::{info, warn};
 use tungstenite::{accept, Message, Utf8Bytes};
 use tungstenite::error::Error as WsError;
 use crate::general_use::{ClientId, DroneId, FileRef, MediaRef, ServerId};
+use crate::simulation_controller::PacketLogFilter;
+use crate::network_initializer::DroneBrand;
+use wg_2024::network::NodeId;
 
 // Helper module for handling u64 as strings in JSON
 mod stringified_u8 {
@@ -21,6 +24,71 @@ mod stringified_u8 {
     }
 }
 
+// Same for lists of ids
+mod stringified_u8_vec {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::ser::SerializeSeq;
+    use serde::de::Error;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u8),
+    }
+
+    pub fn serialize<S: Serializer>(values: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(values.len()))?;
+        for value in values {
+            seq.serialize_element(&value.to_string())?;
+        }
+        seq.end()
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        Vec::<StringOrNumber>::deserialize(deserializer)?
+            .into_iter()
+            .map(|value| match value {
+                StringOrNumber::String(s) => s.trim().parse().map_err(Error::custom),
+                StringOrNumber::Number(n) => Ok(n),
+            })
+            .collect()
+    }
+}
+
+// Same for optional ids, a missing field or an empty string is None (plain numbers are accepted too)
+pub(crate) mod optional_stringified {
+    use std::fmt::Display;
+    use std::str::FromStr;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64),
+    }
+
+    pub fn serialize<T: Display, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.serialize_some(&value.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        match Option::<StringOrNumber>::deserialize(deserializer)? {
+            Some(StringOrNumber::String(s)) if !s.trim().is_empty() => s.trim().parse().map(Some).map_err(Error::custom),
+            Some(StringOrNumber::Number(n)) => n.to_string().parse().map(Some).map_err(Error::custom),
+            _ => Ok(None),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WsCommand {
     WsUpdateData,
@@ -64,15 +132,64 @@ pub enum WsCommand {
     WsCrashDrone{
         #[serde(with = "stringified_u8")]
         drone_id: DroneId,
-    }
+    },
+
+    WsSetPdr{
+        #[serde(with = "stringified_u8")]
+        drone_id: DroneId,
+        pdr: f32,
+    },
+
+    WsAddDrone {
+        #[serde(with = "stringified_u8")]
+        id: DroneId,
+        #[serde(with = "stringified_u8_vec")]
+        neighbours: Vec<NodeId>,
+        pdr: f32,
+        #[serde(default)]
+        brand: Option<DroneBrand>,      // None picks the least used brand
+    },
+
+    WsAddLink {
+        #[serde(with = "stringified_u8")]
+        from: NodeId,
+        #[serde(with = "stringified_u8")]
+        to: NodeId,
+    },
+
+    WsRemoveLink {
+        #[serde(with = "stringified_u8")]
+        from: NodeId,
+        #[serde(with = "stringified_u8")]
+        to: NodeId,
+    },
+
+    WsExportTopology {
+        #[serde(default)]
+        file_name: Option<String>,  // Written in the export directory, None sends the TOML to the browser
+    },
+
+    WsQueryPacketLog {
+        #[serde(flatten)]
+        filter: PacketLogFilter,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+
+    WsExportPacketLog {
+        file_name: String,          // JSON Lines file written by the controller in the export directory
+        #[serde(flatten)]
+        filter: PacketLogFilter,
+    },
 }
 
 // A type alias for the global list of client inboxes.
 // Each client (internet WebSocket connection) gets its own Sender<String> for receiving broadcast updates.
 type ClientList = Arc<Mutex<Vec<Sender<String>>>>;
 
-pub fn start_websocket_server(rx: Receiver<String>, cmd_tx: Sender<WsCommand>) {
-    let listener = TcpListener::bind("0.0.0.0:8080").unwrap();
+pub fn start_websocket_server(rx: Receiver<String>, cmd_tx: Sender<WsCommand>, port: u16) {
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port))
+        .unwrap_or_else(|e| panic!("Unable to bind the WebSocket server on port {}: {}", port, e));
     let clients: ClientList = Arc::new(Mutex::new(Vec::new()));
 
     // Spawn a dedicated broadcaster thread.
@@ -716,7 +716,7 @@ function  openPopup(fileName) {
                 console.log(media)
                 console.log(found)
                 // Use the already loaded media image.
-                return `<img src="${absolutePath + found.media}" id="reference-${p1}" alt="Media loaded" class="image-loaded" />`;
+                return mediaHtml(p1, mediaSource(found.media, absolutePath), `class="image-loaded"`);
             });
             // Insert the processed HTML into the popup.
             popupFileContent.innerHTML = processedContent;
@@ -929,7 +929,7 @@ function updateFile(file_content) {
                 const found = media.find(item => item.reference === reference);
                 if (found && found.media) {
                     // Media is already loaded.
-                    return `<img src="${found.media}" id="reference-${reference}" alt="Media loaded" />`;
+                    return mediaHtml(reference, found.media, "");
                 } else if (!requestedMedia.has(reference)) {
                     // Request the media only if it hasn't been requested yet.
                     requestedMedia.add(reference); // Mark as requested
@@ -950,6 +950,65 @@ function updateFile(file_content) {
 }
 
 
+// The clients keep their last server error in every update, it is shown once
+let lastShownServerError = null;
+
+function describeServerError(error) {
+    if (error.FileNotFound !== undefined) return `file ${error.FileNotFound} not found`;
+    if (error.MediaNotFound !== undefined) return `media ${error.MediaNotFound} not found`;
+    if (error.MalformedMessage !== undefined) return `malformed message: ${error.MalformedMessage}`;
+    if (error.InvalidQuery !== undefined) return `invalid query: ${error.InvalidQuery}`;
+    return JSON.stringify(error);
+}
+
+function updateServerError(lastServerError) {
+    if (!lastServerError) return;
+    const serialized = JSON.stringify(lastServerError);
+    if (serialized === lastShownServerError) return;
+    lastShownServerError = serialized;
+
+    const [serverId, error] = lastServerError;
+    let banner = document.getElementById("server-error");
+    if (!banner) {
+        banner = document.createElement("div");
+        banner.id = "server-error";
+        banner.style = "position:fixed; bottom:20px; right:20px; padding:10px 16px; border-radius:6px; background:#c0392b; color:white; z-index:1000;";
+        document.body.appendChild(banner);
+    }
+    banner.textContent = `Server ${serverId}: ${describeServerError(error)}`;
+    banner.style.display = "block";
+    clearTimeout(banner.hideTimeout);
+    banner.hideTimeout = setTimeout(() => banner.style.display = "none", 5000);
+}
+
+// A node gave up a message, a fragment was never acked
+function showSessionFailed(failedSession) {
+    let banner = document.getElementById("session-failed");
+    if (!banner) {
+        banner = document.createElement("div");
+        banner.id = "session-failed";
+        banner.style = "position:fixed; bottom:70px; right:20px; padding:10px 16px; border-radius:6px; background:#d35400; color:white; z-index:1000;";
+        document.body.appendChild(banner);
+    }
+    const { node_id, session_id, destination } = failedSession;
+    banner.textContent = `Node ${node_id}: message ${session_id} to ${destination} failed, no ack after several attempts`;
+    banner.style.display = "block";
+    clearTimeout(banner.hideTimeout);
+    banner.hideTimeout = setTimeout(() => banner.style.display = "none", 5000);
+}
+
+// Media from a directory arrive as data urls, the built-in ones as paths of the web server
+function mediaSource(media, absolutePath) {
+    return media.startsWith("data:") ? media : absolutePath + media;
+}
+
+function mediaHtml(reference, src, attributes) {
+    if (src.startsWith("data:audio/")) {
+        return `<audio controls src="${src}" id="reference-${reference}" ${attributes}></audio>`;
+    }
+    return `<img src="${src}" id="reference-${reference}" alt="Media loaded" ${attributes} />`;
+}
+
 function updateMedia(mediaRef) {
     console.log(mediaRef);
     const fullPath = window.location.pathname;
@@ -970,14 +1029,19 @@ function updateMedia(mediaRef) {
         }
         // Find the image element with the corresponding id.
         const imgElem = document.getElementById("reference-" + reference);
-        if (imgElem) {
+        if (imgElem && base64Image.startsWith("data:audio/")) {
+            if (imgElem.tagName !== "AUDIO") {
+                imgElem.outerHTML = mediaHtml(reference, base64Image, "");
+            }
+        } else if (imgElem) {
             //console.log(base64Image);
             imgElem.classList.remove("loading", "rotate");  // Remove any rotation classes
             imgElem.style.animation = "none";  // Stop rotation
             imgElem.style.transform = "none";  // Reset any transforms
             imgElem.style.width = "400px";
             imgElem.style.height = "auto";
-            imgElem.src = absolutePath + base64Image + "?t=" + new Date().getTime();
+            // The timestamp avoids the cache, a data url is already the whole file
+            imgElem.src = base64Image.startsWith("data:") ? base64Image : absolutePath + base64Image + "?t=" + new Date().getTime();
         } else {
             //console.warn("No element found with id:", "reference-" + reference);
         }
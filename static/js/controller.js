@@ -174,9 +174,8 @@ function updateCrashedDrone(isCrashed, parsedData){
 }
 
 function updatePdrDrone(parsedData) {
-    // Expect parsedData to include node_id and pdr
-    const nodeId = parsedData.node_id;
-    const newPdr = parsedData.pdr;
+    // The controller sends { "PdrChanged": [node_id, pdr] }
+    const [nodeId, newPdr] = parsedData.PdrChanged;
 
     // 1. Update the drawn_nodes array.
     const droneNode = drawn_nodes.find(node => node.id == nodeId);
@@ -200,10 +199,129 @@ function updatePdrDrone(parsedData) {
     }
 }
 
+function promptSetPdr(drone){
+    const pdr = prompt(`New packet drop rate of drone ${drone.id} (0 - 1)`, drone.pdr !== undefined ? drone.pdr : "0");
+    if (pdr === null) return;
+    sendSetPdrController(drone.id, parseFloat(pdr));
+}
+
+function sendSetPdrController(droneId, pdr){
+    if (ws.readyState === WebSocket.OPEN) {
+        const message = {
+            WsSetPdr: {
+                drone_id: droneId.toString(),
+                pdr: pdr,
+            }
+        };
+        ws.send(JSON.stringify(message));
+        console.log('Sent:', message);
+    } else {
+        console.error('WebSocket is not open. Unable to send set PDR command.');
+    }
+}
+
+function updatePdrNotChanged(parsedData){
+    const [droneId, reason] = parsedData.PdrNotChanged;
+    alert(`PDR of drone ${droneId} not changed: ${reason}`);
+}
+
 function crashDroneReview(){
     droneCrashed = "droneNotCrashed";
 }
 
+// Set when the topology changed, the next network data redraws the graph
+let pendingTopologyRefresh = false;
+
+function promptAddDrone(){
+    const id = prompt("Id of the new drone");
+    if (id === null) return;
+    const neighbours = prompt("Ids of the neighbours, separated by commas");
+    if (neighbours === null) return;
+    const pdr = prompt("Packet drop rate (0 - 1)", "0.05");
+    if (pdr === null) return;
+    const brand = prompt("Brand (empty to choose automatically)", "");
+    sendAddDroneController(id.trim(), neighbours.split(",").map(n => n.trim()).filter(n => n !== ""), parseFloat(pdr), brand);
+}
+
+function sendAddDroneController(droneId, neighbours, pdr, brand){
+    if (ws.readyState === WebSocket.OPEN) {
+        const message = {
+            WsAddDrone: {
+                id: droneId.toString(),
+                neighbours: neighbours.map(n => n.toString()),
+                pdr: pdr,
+                brand: brand ? brand.trim() : null,
+            }
+        };
+        ws.send(JSON.stringify(message));
+        console.log('Sent:', message);
+    } else {
+        console.error('WebSocket is not open. Unable to send add drone command.');
+    }
+}
+
+function promptLink(add){
+    const from = prompt("Id of the first node");
+    if (from === null) return;
+    const to = prompt("Id of the second node");
+    if (to === null) return;
+    sendLinkController(from.trim(), to.trim(), add);
+}
+
+function sendLinkController(from, to, add){
+    if (ws.readyState === WebSocket.OPEN) {
+        const link = { from: from.toString(), to: to.toString() };
+        const message = add ? { WsAddLink: link } : { WsRemoveLink: link };
+        ws.send(JSON.stringify(message));
+        console.log('Sent:', message);
+    } else {
+        console.error('WebSocket is not open. Unable to send link command.');
+    }
+}
+
+function sendExportTopologyController(){
+    if (ws.readyState === WebSocket.OPEN) {
+        const message = { WsExportTopology: { file_name: null } };
+        ws.send(JSON.stringify(message));
+        console.log('Sent:', message);
+    } else {
+        console.error('WebSocket is not open. Unable to send export topology command.');
+    }
+}
+
+function updateTopologyExport(parsedData){
+    if (parsedData.hasOwnProperty('TopologyToml')) {
+        // Download the file built by the controller
+        const blob = new Blob([parsedData.TopologyToml], { type: 'application/toml' });
+        const link = document.createElement('a');
+        link.href = URL.createObjectURL(blob);
+        link.download = 'topology.toml';
+        link.click();
+        URL.revokeObjectURL(link.href);
+    } else if (parsedData.hasOwnProperty('TopologyExportFailed')) {
+        alert(`Topology not exported: ${parsedData.TopologyExportFailed.error}`);
+    }
+}
+
+// The controller sends the reason as { "Kind": details }
+function describeTopologyError(error){
+    if (typeof error === 'string') return error;
+    const [kind, details] = Object.entries(error)[0];
+    return `${kind} ${JSON.stringify(details)}`;
+}
+
+function updateTopologyOperation(parsedData){
+    if (parsedData.hasOwnProperty('DroneAdded') || parsedData.hasOwnProperty('LinkAdded') || parsedData.hasOwnProperty('LinkRemoved')) {
+        pendingTopologyRefresh = true;
+    } else if (parsedData.hasOwnProperty('DroneNotAdded')) {
+        const [droneId, reason] = parsedData.DroneNotAdded;
+        alert(`Drone ${droneId} not added: ${describeTopologyError(reason)}`);
+    } else if (parsedData.hasOwnProperty('LinkNotChanged')) {
+        const [from, to, reason] = parsedData.LinkNotChanged;
+        alert(`Link ${from} - ${to} not changed: ${describeTopologyError(reason)}`);
+    }
+}
+
 
 
 
@@ -531,11 +649,13 @@ function showDroneDetails(droneData) {
     document.getElementById('drone-id').textContent = droneData.id;
     document.getElementById('drone-type').textContent = droneData.type;
     document.getElementById('drone-coordinates').textContent = `(${droneData.x}, ${droneData.y})`;
+    document.getElementById('drone-pdr').textContent = droneData.pdr !== undefined ? droneData.pdr : '';
     // If you have more fields, update them here.
 
     // Optionally, update the title or add additional content
     document.getElementById('drone-title').textContent = `Drone ${droneData.id} Details`;
     document.getElementById("crash-btn").onclick = () => crashDrone(droneData);
+    document.getElementById("pdr-btn").onclick = () => promptSetPdr(droneData);
 
     // Show the side tab
     document.getElementById('drone-details').style.display = 'block';